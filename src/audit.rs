@@ -0,0 +1,187 @@
+use crate::{Acquired, Client};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+type Sink = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// A [`Client`] which writes a record of every acquire and release to a sink.
+///
+/// This is created through [`Client::audit_log`]. Each operation is written as
+/// a single line of JSON with the following fields:
+///
+/// * `timestamp_ms`: milliseconds since the Unix epoch.
+/// * `thread`: the debug representation of the calling thread's ID.
+/// * `op`: one of `"acquire"`, `"try_acquire"` or `"release"`.
+/// * `wait_us`: microseconds spent in the operation.
+/// * `success`: whether a token was acquired or released.
+/// * `error`: the error message, only present if the operation failed.
+///
+/// Errors writing to the sink are ignored. Methods not provided by
+/// [`AuditClient`] are passed through to the underlying [`Client`] without
+/// being recorded.
+pub struct AuditClient {
+    client: Client,
+    sink: Sink,
+}
+
+/// An acquired token from an [`AuditClient`].
+///
+/// This behaves like [`Acquired`], additionally recording the release when
+/// dropped.
+pub struct AuditedAcquired {
+    token: Option<Acquired>,
+    sink: Sink,
+}
+
+impl AuditClient {
+    pub(crate) fn new(client: Client, sink: Box<dyn Write + Send>) -> AuditClient {
+        AuditClient {
+            client,
+            sink: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    /// Same as [`Client::acquire`], recording the result.
+    pub fn acquire(&self) -> io::Result<AuditedAcquired> {
+        let start = Instant::now();
+        let result = self.client.acquire();
+        record(
+            &self.sink,
+            "acquire",
+            start.elapsed(),
+            result.as_ref().map(|_| true),
+        );
+        result.map(|token| self.wrap(token))
+    }
+
+    /// Same as [`Client::try_acquire`], recording the result.
+    pub fn try_acquire(&self) -> io::Result<Option<AuditedAcquired>> {
+        let start = Instant::now();
+        let result = self.client.try_acquire();
+        let success = result.as_ref().map(|token| token.is_some());
+        record(&self.sink, "try_acquire", start.elapsed(), success);
+        result.map(|token| token.map(|token| self.wrap(token)))
+    }
+
+    /// Same as [`Client::release_raw`], recording the result.
+    pub fn release_raw(&self) -> io::Result<()> {
+        let start = Instant::now();
+        let result = self.client.release_raw();
+        record(
+            &self.sink,
+            "release",
+            start.elapsed(),
+            result.as_ref().map(|()| true),
+        );
+        result
+    }
+
+    /// Stops auditing, returning the underlying [`Client`].
+    ///
+    /// The sink is flushed and dropped once all tokens acquired through this
+    /// client have been released.
+    pub fn into_inner(self) -> Client {
+        let _ = self.sink.lock().unwrap_or_else(|e| e.into_inner()).flush();
+        self.client
+    }
+
+    fn wrap(&self, token: Acquired) -> AuditedAcquired {
+        AuditedAcquired {
+            token: Some(token),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl Deref for AuditClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl fmt::Debug for AuditClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditClient")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuditedAcquired {
+    /// Stops auditing this token, returning the underlying [`Acquired`].
+    ///
+    /// Its release will not be recorded.
+    pub fn into_inner(mut self) -> Acquired {
+        self.token.take().unwrap()
+    }
+}
+
+impl Drop for AuditedAcquired {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            let start = Instant::now();
+            drop(token);
+            record(&self.sink, "release", start.elapsed(), Ok(true));
+        }
+    }
+}
+
+impl fmt::Debug for AuditedAcquired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditedAcquired")
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+fn record(sink: &Sink, op: &str, elapsed: Duration, result: Result<bool, &io::Error>) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let mut line = format!(
+        "{{\"timestamp_ms\":{timestamp},\"thread\":\"{}\",\"op\":\"{op}\",\"wait_us\":{},\"success\":{}",
+        escape(&format!("{:?}", thread::current().id())),
+        elapsed.as_micros(),
+        result.as_ref().map_or(false, |s| *s),
+    );
+    if let Err(e) = result {
+        line.push_str(&format!(",\"error\":\"{}\"", escape(&e.to_string())));
+    }
+    line.push_str("}\n");
+
+    let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = sink.write_all(line.as_bytes());
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a\"b\\c\nd\u{1}"), "a\\\"b\\\\c\\nd\\u0001");
+    }
+}
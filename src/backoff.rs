@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+/// How long to sleep between attempts of
+/// [`Client::acquire_with_timeout_and_backoff`].
+///
+/// [`Client::acquire_with_timeout_and_backoff`]: crate::Client::acquire_with_timeout_and_backoff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BackoffStrategy {
+    /// Sleep for the same duration after each attempt.
+    Fixed(Duration),
+    /// Sleep for `initial` after the first attempt, doubling the duration
+    /// after each further attempt up to `max`.
+    Exponential {
+        /// Duration of the first sleep.
+        initial: Duration,
+        /// Upper bound for the duration of each sleep.
+        max: Duration,
+    },
+}
+
+impl BackoffStrategy {
+    /// Returns how long to sleep after the failed attempt number `attempt`,
+    /// counting from zero.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            BackoffStrategy::Fixed(delay) => delay,
+            BackoffStrategy::Exponential { initial, max } => initial
+                .checked_mul(1 << attempt.min(31))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+}
+
+impl Default for BackoffStrategy {
+    /// Exponential backoff from 1 millisecond up to 100 milliseconds.
+    fn default() -> BackoffStrategy {
+        BackoffStrategy::Exponential {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(100),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackoffStrategy;
+    use std::time::Duration;
+
+    #[test]
+    fn delay() {
+        let ms = Duration::from_millis;
+        assert_eq!(BackoffStrategy::Fixed(ms(5)).delay(10), ms(5));
+        let exp = BackoffStrategy::Exponential {
+            initial: ms(1),
+            max: ms(10),
+        };
+        let delays = (0..6).map(|i| exp.delay(i)).collect::<Vec<_>>();
+        assert_eq!(delays, [ms(1), ms(2), ms(4), ms(8), ms(10), ms(10)]);
+        assert_eq!(exp.delay(u32::MAX), ms(10));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{imp, Client, ClientInner};
+#[cfg(windows)]
+use std::ffi::c_void;
+use std::io;
+#[cfg(windows)]
+use std::ptr;
+use std::sync::Arc;
+
+/// A builder for creating a new jobserver with more options than
+/// [`Client::new`].
+///
+/// # Examples
+///
+/// ```
+/// use jobserver::ClientBuilder;
+///
+/// let client = ClientBuilder::new(4)
+///     .with_name_validator(|name| name.starts_with("__rust_jobserver"))
+///     .build()
+///     .expect("failed to create jobserver");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    limit: usize,
+    semaphore_max: Option<usize>,
+    name_validator: Option<fn(&str) -> bool>,
+    #[cfg(windows)]
+    security_attributes: SecurityAttributes,
+}
+
+/// Pointer to a `SECURITY_ATTRIBUTES`, see
+/// [`ClientBuilder::with_security_attributes`].
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug)]
+struct SecurityAttributes(*mut c_void);
+
+// The pointer is only dereferenced by `ClientBuilder::build`, and the caller
+// of `ClientBuilder::with_security_attributes` guarantees it's valid then.
+#[cfg(windows)]
+unsafe impl Send for SecurityAttributes {}
+#[cfg(windows)]
+unsafe impl Sync for SecurityAttributes {}
+
+impl ClientBuilder {
+    /// Creates a new builder for a jobserver with the given parallelism
+    /// limit, see [`Client::new`].
+    pub fn new(limit: usize) -> ClientBuilder {
+        ClientBuilder {
+            limit,
+            semaphore_max: None,
+            name_validator: None,
+            #[cfg(windows)]
+            security_attributes: SecurityAttributes(ptr::null_mut()),
+        }
+    }
+
+    /// Sets a function called with each candidate name for the jobserver.
+    ///
+    /// Names rejected by `validator` are skipped and another one is tried,
+    /// which allows enforcing naming schemes or namespace checks on top of
+    /// the random names generated by this crate.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// This only has an effect on Windows, where the jobserver is a named
+    /// semaphore. Jobservers on other platforms have no name to validate.
+    pub fn with_name_validator(mut self, validator: fn(&str) -> bool) -> ClientBuilder {
+        self.name_validator = Some(validator);
+        self
+    }
+
+    /// Sets the maximum number of tokens the jobserver can hold, which
+    /// defaults to the limit.
+    ///
+    /// This leaves room for adding tokens to the jobserver later on, beyond
+    /// the ones it's created with. See [`Client::max_capacity`].
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// This only has an effect on Windows, where the jobserver is a
+    /// semaphore with a fixed maximum count. Jobservers on other platforms
+    /// have no such maximum.
+    pub fn with_semaphore_max(mut self, max: usize) -> ClientBuilder {
+        self.semaphore_max = Some(max);
+        self
+    }
+
+    /// Sets the security attributes of the semaphore backing the jobserver.
+    ///
+    /// This allows security-conscious build systems to limit which processes
+    /// can open the semaphore with a security descriptor, preventing rogue
+    /// processes from acquiring tokens. The semaphore is created with
+    /// `CreateSemaphoreExW` if available, which is the case on Windows Vista
+    /// and later, and `CreateSemaphoreA` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// `attributes` must be null or point to a valid `SECURITY_ATTRIBUTES`
+    /// whenever [`ClientBuilder::build`] is called.
+    #[cfg(windows)]
+    pub unsafe fn with_security_attributes(mut self, attributes: *mut c_void) -> ClientBuilder {
+        self.security_attributes = SecurityAttributes(attributes);
+        self
+    }
+
+    /// Creates the jobserver configured by this builder.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any I/O error happens when attempting to create the
+    /// jobserver client, or if no valid name was found for it. An error of
+    /// the kind [`io::ErrorKind::InvalidInput`] is returned if the maximum set
+    /// through [`ClientBuilder::with_semaphore_max`] is below the limit.
+    pub fn build(&self) -> io::Result<Client> {
+        if self.semaphore_max.map_or(false, |max| max < self.limit) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "semaphore maximum is below the limit",
+            ));
+        }
+        #[cfg(windows)]
+        let imp = imp::Client::new_validated(
+            self.limit,
+            self.semaphore_max,
+            self.name_validator,
+            self.security_attributes.0,
+        )?;
+        #[cfg(not(windows))]
+        let imp = imp::Client::new(self.limit)?;
+        Ok(Client {
+            inner: Arc::new(ClientInner::with_limit(imp, self.limit)),
+        })
+    }
+}
@@ -0,0 +1,64 @@
+//! Sizing of jobservers after the CPU quota of a cgroup v2.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+
+/// Returns the number of CPUs the cgroup at `cgroup_path` may use.
+///
+/// Cgroups without a CPU quota fall back to the available parallelism of
+/// this process.
+pub(crate) fn cpu_limit(cgroup_path: &Path) -> io::Result<usize> {
+    let cpu_max = fs::read_to_string(cgroup_path.join("cpu.max"))?;
+    match parse_cpu_max(&cpu_max) {
+        Some(Some(cpus)) => Ok(cpus),
+        Some(None) => thread::available_parallelism().map(|n| n.get()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid cpu.max contents: {cpu_max:?}"),
+        )),
+    }
+}
+
+/// Parses `cpu.max`, which is formatted as `$MAX $PERIOD` where `$MAX` may
+/// be `max` for no limit.
+///
+/// Returns `Some(None)` if there is no limit, and rounds partial CPUs up so
+/// that any quota allows for at least one job.
+fn parse_cpu_max(cpu_max: &str) -> Option<Option<usize>> {
+    let mut parts = cpu_max.split_whitespace();
+    let quota = parts.next()?;
+    let period = match parts.next() {
+        Some(period) => period.parse::<u64>().ok()?,
+        None => 100_000,
+    };
+    if parts.next().is_some() || period == 0 {
+        return None;
+    }
+    if quota == "max" {
+        return Some(None);
+    }
+    let quota = quota.parse::<u64>().ok()?;
+    let cpus = quota / period + u64::from(quota % period != 0);
+    Some(Some(usize::try_from(cpus).unwrap_or(usize::MAX).max(1)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_cpu_max;
+
+    #[test]
+    fn cpu_max() {
+        assert_eq!(parse_cpu_max("max 100000\n"), Some(None));
+        assert_eq!(parse_cpu_max("max"), Some(None));
+        assert_eq!(parse_cpu_max("200000 100000\n"), Some(Some(2)));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(Some(2)));
+        assert_eq!(parse_cpu_max("1000 100000\n"), Some(Some(1)));
+        assert_eq!(parse_cpu_max("0 100000\n"), Some(Some(1)));
+        assert_eq!(parse_cpu_max(""), None);
+        assert_eq!(parse_cpu_max("max 0"), None);
+        assert_eq!(parse_cpu_max("lots 100000"), None);
+        assert_eq!(parse_cpu_max("1 2 3"), None);
+    }
+}
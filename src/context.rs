@@ -0,0 +1,118 @@
+//! Runtime-agnostic polling of tokens, see [`Client::acquire_with_context`].
+//!
+//! [`Client::acquire_with_context`]: crate::Client::acquire_with_context
+
+use crate::{imp, ClientInner};
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread::Builder;
+use std::time::Duration;
+
+/// How long the background thread waits for the jobserver at a time before
+/// checking whether the client is still alive.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tasks waiting for a token.
+///
+/// There's no portable way to register the jobserver with whatever reactor
+/// the caller uses, so a background thread waits for the jobserver to become
+/// readable instead and wakes all tasks once it is, which then acquire the
+/// token themselves. The thread doesn't acquire anything, so tasks which
+/// stop polling don't leave tokens behind. At most one such thread runs per
+/// jobserver client at a time.
+#[derive(Debug, Default)]
+pub(crate) struct WakerState {
+    lock: Mutex<WakerInner>,
+}
+
+#[derive(Debug, Default)]
+struct WakerInner {
+    wakers: Vec<Waker>,
+    thread_running: bool,
+}
+
+pub(crate) fn poll_acquire(
+    client: &Arc<ClientInner>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<imp::Acquired>> {
+    let mut lock = client.wakers.lock.lock().unwrap_or_else(|e| e.into_inner());
+    match client.try_acquire() {
+        Ok(Some(data)) => return Poll::Ready(Ok(data)),
+        Ok(None) => {}
+        Err(e) => return Poll::Ready(Err(e)),
+    }
+
+    if !lock.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+        lock.wakers.push(cx.waker().clone());
+    }
+    if !lock.thread_running {
+        // Only a weak reference, so that the thread doesn't keep the client
+        // alive while waiting.
+        let weak = Arc::downgrade(client);
+        let spawned = Builder::new().spawn(move || watch(weak));
+        if let Err(e) = spawned {
+            return Poll::Ready(Err(e));
+        }
+        lock.thread_running = true;
+    }
+    Poll::Pending
+}
+
+fn watch(weak: Weak<ClientInner>) {
+    loop {
+        let client = match weak.upgrade() {
+            Some(client) => client,
+            None => return,
+        };
+        let ready = match &client.imp {
+            Some(imp) => imp.wait_readable(WATCH_INTERVAL),
+            None => Ok(true),
+        };
+        // Errors are reported by the tasks trying to acquire again.
+        if let Ok(false) = ready {
+            continue;
+        }
+        let mut lock = client.wakers.lock.lock().unwrap_or_else(|e| e.into_inner());
+        lock.thread_running = false;
+        let wakers = std::mem::take(&mut lock.wakers);
+        drop(lock);
+        for waker in wakers {
+            waker.wake();
+        }
+        return;
+    }
+}
+
+/// Stream returned by [`Client::acquire_batch_async`].
+///
+/// [`Client::acquire_batch_async`]: crate::Client::acquire_batch_async
+#[cfg(feature = "futures")]
+pub(crate) struct AcquireBatch {
+    pub(crate) client: crate::Client,
+    pub(crate) remaining: usize,
+}
+
+#[cfg(feature = "futures")]
+impl futures_core::Stream for AcquireBatch {
+    type Item = io::Result<crate::Acquired>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let result = match self.client.acquire_with_context(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.remaining -= 1;
+        Poll::Ready(Some(result))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
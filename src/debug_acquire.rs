@@ -0,0 +1,66 @@
+//! Reporting of suspected deadlocks, see [`Client::debug_acquire`].
+//!
+//! [`Client::debug_acquire`]: crate::Client::debug_acquire
+
+// `std::backtrace` needs a newer Rust than the rest of this crate, which is
+// documented for the `debug-backtraces` feature.
+#![allow(clippy::incompatible_msrv)]
+
+use crate::{Acquired, Client};
+use std::backtrace::Backtrace;
+use std::env;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, Builder};
+use std::time::{Duration, Instant};
+
+/// Environment variable overriding after how many seconds of waiting for a
+/// token a deadlock is reported.
+const TIMEOUT_VAR: &str = "JOBSERVER_DEBUG_TIMEOUT";
+
+/// Default for [`TIMEOUT_VAR`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) fn acquire(client: &Client) -> io::Result<Acquired> {
+    let timeout = env::var(TIMEOUT_VAR)
+        .ok()
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs_f64);
+    // The watchdog can't capture the stack of another thread, so capture it
+    // here, where the interesting part is the caller waiting for a token.
+    let backtrace = Backtrace::force_capture();
+    let thread = thread::current();
+    let name = thread.name().unwrap_or("<unnamed>").to_string();
+
+    let done = Arc::new((Mutex::new(false), Condvar::new()));
+    let done2 = done.clone();
+    let watchdog = Builder::new().spawn(move || {
+        let (lock, cvar) = &*done2;
+        let start = Instant::now();
+        let mut done = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next = start + timeout;
+        while !*done {
+            let now = Instant::now();
+            if now < next {
+                done = cvar
+                    .wait_timeout(done, next - now)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+                continue;
+            }
+            next = now + timeout;
+            eprintln!(
+                "Jobserver deadlock suspected: thread '{name}' has been waiting for a token for {:?}\n{backtrace}",
+                start.elapsed(),
+            );
+        }
+    })?;
+
+    let result = client.acquire();
+    let (lock, cvar) = &*done;
+    *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+    cvar.notify_one();
+    drop(watchdog.join());
+    result
+}
@@ -0,0 +1,47 @@
+use crate::{Acquired, Client};
+use std::io;
+use std::mem;
+
+/// All tokens which were available in a jobserver, taken through
+/// [`Client::take_snapshot_and_drain`].
+///
+/// The tokens are held until [`DrainedClient::release_all`] is called or
+/// this is dropped, which releases all of them back to the jobserver.
+#[derive(Debug)]
+pub struct DrainedClient {
+    client: Client,
+    tokens: Vec<Acquired>,
+}
+
+impl DrainedClient {
+    pub(crate) fn new(client: Client, tokens: Vec<Acquired>) -> DrainedClient {
+        DrainedClient { client, tokens }
+    }
+
+    /// Returns the number of tokens which were drained.
+    pub fn count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns the jobserver the tokens were drained from.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Releases all drained tokens back to the jobserver.
+    ///
+    /// # Errors
+    ///
+    /// If releasing a token fails, the remaining tokens are still released
+    /// and the first error is returned.
+    pub fn release_all(mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for token in mem::take(&mut self.tokens) {
+            let released = token.release();
+            if result.is_ok() {
+                result = released;
+            }
+        }
+        result
+    }
+}
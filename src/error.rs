@@ -36,6 +36,11 @@ pub enum FromEnvErrorKind {
     NotAPipe,
     /// Jobserver inheritance is not supported on this platform.
     Unsupported,
+    /// The process expected to own the jobserver is no longer running, see
+    /// [`Client::from_env_checking_pid`].
+    ///
+    /// [`Client::from_env_checking_pid`]: crate::Client::from_env_checking_pid
+    OwnerProcessDead,
 }
 
 impl FromEnvError {
@@ -50,6 +55,7 @@ impl FromEnvError {
             FromEnvErrorInner::NegativeFd(..) => FromEnvErrorKind::NegativeFd,
             FromEnvErrorInner::NotAPipe(..) => FromEnvErrorKind::NotAPipe,
             FromEnvErrorInner::Unsupported => FromEnvErrorKind::Unsupported,
+            FromEnvErrorInner::OwnerProcessDead(_) => FromEnvErrorKind::OwnerProcessDead,
         }
     }
 }
@@ -66,6 +72,7 @@ impl std::fmt::Display for FromEnvError {
             FromEnvErrorInner::NotAPipe(fd, None) => write!(f, "file descriptor {fd} from the jobserver environment variable value is not a pipe"),
             FromEnvErrorInner::NotAPipe(fd, Some(err)) => write!(f, "file descriptor {fd} from the jobserver environment variable value is not a pipe: {err}"),
             FromEnvErrorInner::Unsupported => write!(f, "jobserver inheritance is not supported on this platform"),
+            FromEnvErrorInner::OwnerProcessDead(pid) => write!(f, "process {pid} owning the jobserver is no longer running"),
         }
     }
 }
@@ -92,4 +99,5 @@ pub(crate) enum FromEnvErrorInner {
     NegativeFd(RawFd),
     NotAPipe(RawFd, Option<std::io::Error>),
     Unsupported,
+    OwnerProcessDead(u32),
 }
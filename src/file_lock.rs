@@ -0,0 +1,163 @@
+use crate::{imp, BackoffStrategy};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A jobserver whose tokens are byte-range locks on a file, created through
+/// [`FileLockClient::open`].
+///
+/// Each of the first `limit` bytes of the file is one token slot, and a
+/// token is acquired by locking its byte. Locks are released by the OS when
+/// a process exits, so tokens can't leak even if a process crashes.
+///
+/// Since these tokens aren't backed by a pipe or semaphore, this is separate
+/// from [`Client`](crate::Client) and can't be passed to child processes
+/// through [`Client::configure`](crate::Client::configure). Other processes
+/// share the tokens by opening the same path with the same limit instead.
+#[derive(Clone)]
+pub struct FileLockClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    file: File,
+    path: PathBuf,
+    /// Slots locked by this process. Locks on Unix are per process, so they
+    /// don't exclude other threads of this process.
+    held: Mutex<Vec<bool>>,
+}
+
+/// A token acquired from a [`FileLockClient`], released when dropped.
+pub struct FileLockAcquired {
+    inner: Arc<Inner>,
+    slot: usize,
+}
+
+impl FileLockClient {
+    /// Opens a jobserver whose tokens are byte-range locks on the file at
+    /// `path`, creating the file if it doesn't exist.
+    ///
+    /// Some build systems coordinate through file locks rather than pipes or
+    /// semaphores: each of the first `limit` bytes of the file is one token
+    /// slot, and acquiring a token means locking its byte. All processes
+    /// using the same `path` and `limit` share these tokens.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this uses `fcntl` record locks, which are held by the process:
+    /// the lock file must not be opened and closed elsewhere in this process,
+    /// as that drops all of its locks. On Windows this uses `LockFile`. On
+    /// other platforms acquiring tokens fails with
+    /// [`io::ErrorKind::Unsupported`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be opened.
+    pub fn open(path: &Path, limit: usize) -> io::Result<FileLockClient> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(FileLockClient {
+            inner: Arc::new(Inner {
+                file,
+                path: path.to_path_buf(),
+                held: Mutex::new(vec![false; limit]),
+            }),
+        })
+    }
+
+    /// Acquires a token, blocking until one is available.
+    ///
+    /// There's no way to wait for any of several locks at once, so this
+    /// polls all slots with an exponential backoff in between.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if locking the file fails.
+    pub fn acquire(&self) -> io::Result<FileLockAcquired> {
+        let backoff = BackoffStrategy::default();
+        let mut attempt = 0u32;
+        loop {
+            if let Some(token) = self.try_acquire()? {
+                return Ok(token);
+            }
+            thread::sleep(backoff.delay(attempt));
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Acquires a token if one is available without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if locking the file fails.
+    pub fn try_acquire(&self) -> io::Result<Option<FileLockAcquired>> {
+        let mut held = self.inner.held();
+        for (slot, held) in held.iter_mut().enumerate() {
+            if !*held && imp::lock_byte(&self.inner.file, slot as u64)? {
+                *held = true;
+                return Ok(Some(FileLockAcquired {
+                    inner: self.inner.clone(),
+                    slot,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Returns the number of token slots.
+    pub fn limit(&self) -> usize {
+        self.inner.held().len()
+    }
+}
+
+impl Inner {
+    fn held(&self) -> std::sync::MutexGuard<'_, Vec<bool>> {
+        self.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for FileLockClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLockClient")
+            .field("path", &self.inner.path)
+            .field("limit", &self.limit())
+            .finish()
+    }
+}
+
+impl FileLockAcquired {
+    /// Returns the index of the byte locked for this token.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+}
+
+impl Drop for FileLockAcquired {
+    fn drop(&mut self) {
+        let mut held = self.inner.held();
+        if imp::unlock_byte(&self.inner.file, self.slot as u64).is_ok() {
+            held[self.slot] = false;
+        }
+    }
+}
+
+impl fmt::Debug for FileLockAcquired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLockAcquired")
+            .field("path", &self.inner.path)
+            .field("slot", &self.slot)
+            .finish()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{Acquired, Client};
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+/// A guard type wrapping tokens acquired through a [`TypedClient`].
+///
+/// This allows choosing the RAII behavior of tokens per use case, e.g.
+/// logging when they're released or tracking for how long they're held.
+/// The guard owns the [`Acquired`] token and releases it by dropping it.
+///
+/// Built-in guards are [`SimpleGuard`], [`TimedGuard`] and `LoggingGuard`
+/// (with the `log` feature).
+pub trait GuardTrait: Sized {
+    /// Wraps `token`, which was just acquired from `client`.
+    fn new(client: &Client, token: Acquired) -> Self;
+}
+
+/// A [`Client`] handing out tokens wrapped in the guard type `G`.
+///
+/// This is created through [`Client::with_guard_type`]. Methods not
+/// provided by [`TypedClient`] are passed through to the underlying
+/// [`Client`].
+pub struct TypedClient<G> {
+    client: Client,
+    _guard: PhantomData<fn() -> G>,
+}
+
+impl<G: GuardTrait> TypedClient<G> {
+    pub(crate) fn new(client: Client) -> TypedClient<G> {
+        TypedClient {
+            client,
+            _guard: PhantomData,
+        }
+    }
+
+    /// Same as [`Client::acquire`], wrapping the token in `G`.
+    pub fn acquire(&self) -> io::Result<G> {
+        let token = self.client.acquire()?;
+        Ok(G::new(&self.client, token))
+    }
+
+    /// Same as [`Client::try_acquire`], wrapping the token in `G`.
+    pub fn try_acquire(&self) -> io::Result<Option<G>> {
+        let token = self.client.try_acquire()?;
+        Ok(token.map(|token| G::new(&self.client, token)))
+    }
+
+    /// Returns the underlying [`Client`].
+    pub fn into_inner(self) -> Client {
+        self.client
+    }
+}
+
+impl<G> Deref for TypedClient<G> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl<G> Clone for TypedClient<G> {
+    fn clone(&self) -> TypedClient<G> {
+        TypedClient {
+            client: self.client.clone(),
+            _guard: PhantomData,
+        }
+    }
+}
+
+impl<G> fmt::Debug for TypedClient<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedClient")
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+/// A guard which just releases its token when dropped, like [`Acquired`].
+#[derive(Debug)]
+pub struct SimpleGuard {
+    token: Acquired,
+}
+
+impl SimpleGuard {
+    /// Returns the underlying [`Acquired`] token.
+    pub fn into_inner(self) -> Acquired {
+        self.token
+    }
+}
+
+impl GuardTrait for SimpleGuard {
+    fn new(_client: &Client, token: Acquired) -> SimpleGuard {
+        SimpleGuard { token }
+    }
+}
+
+/// A guard which keeps track of when its token was acquired.
+#[derive(Debug)]
+pub struct TimedGuard {
+    token: Acquired,
+    acquired_at: Instant,
+}
+
+impl TimedGuard {
+    /// Returns when the token was acquired.
+    pub fn acquired_at(&self) -> Instant {
+        self.acquired_at
+    }
+
+    /// Returns for how long the token has been held so far.
+    pub fn held(&self) -> Duration {
+        self.acquired_at.elapsed()
+    }
+
+    /// Returns the underlying [`Acquired`] token.
+    pub fn into_inner(self) -> Acquired {
+        self.token
+    }
+}
+
+impl GuardTrait for TimedGuard {
+    fn new(_client: &Client, token: Acquired) -> TimedGuard {
+        TimedGuard {
+            token,
+            acquired_at: Instant::now(),
+        }
+    }
+}
+
+/// A guard logging through the `log` crate at the debug level when its token
+/// is acquired and released, including for how long it was held.
+///
+/// This requires the `log` feature to be enabled.
+#[cfg(feature = "log")]
+#[derive(Debug)]
+pub struct LoggingGuard {
+    token: Option<Acquired>,
+    acquired_at: Instant,
+}
+
+#[cfg(feature = "log")]
+impl LoggingGuard {
+    /// Returns the underlying [`Acquired`] token.
+    ///
+    /// Its release will not be logged.
+    pub fn into_inner(mut self) -> Acquired {
+        self.token.take().unwrap()
+    }
+}
+
+#[cfg(feature = "log")]
+impl GuardTrait for LoggingGuard {
+    fn new(_client: &Client, token: Acquired) -> LoggingGuard {
+        log::debug!("acquired jobserver token");
+        LoggingGuard {
+            token: Some(token),
+            acquired_at: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            drop(token);
+            let held = self.acquired_at.elapsed();
+            log::debug!("released jobserver token after holding it for {held:?}");
+        }
+    }
+}
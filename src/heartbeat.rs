@@ -0,0 +1,65 @@
+use crate::Client;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{Builder, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Structure returned from [`Client::heartbeat`] to manage the lifetime of
+/// the heartbeat thread, see those associated docs for more info.
+#[derive(Debug)]
+pub struct HeartbeatHandle {
+    thread: Option<JoinHandle<()>>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl HeartbeatHandle {
+    pub(crate) fn spawn(
+        client: Client,
+        interval: Duration,
+        callback: Box<dyn Fn() + Send>,
+    ) -> io::Result<HeartbeatHandle> {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let stop2 = stop.clone();
+        let thread = Builder::new().spawn(move || {
+            let (lock, cvar) = &*stop2;
+            let mut stopped = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let mut next = Instant::now() + interval;
+            while !*stopped {
+                let now = Instant::now();
+                if now < next {
+                    stopped = cvar
+                        .wait_timeout(stopped, next - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                    continue;
+                }
+                next = now + interval;
+                if client.inner.inflight_acquires.load(Ordering::SeqCst) > 0 {
+                    // Don't block `stop` while the callback runs.
+                    drop(stopped);
+                    callback();
+                    stopped = lock.lock().unwrap_or_else(|e| e.into_inner());
+                }
+            }
+        })?;
+        Ok(HeartbeatHandle {
+            thread: Some(thread),
+            stop,
+        })
+    }
+
+    /// Stops the heartbeat, blocking until the heartbeat thread has exited.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for HeartbeatHandle {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.stop;
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        cvar.notify_one();
+        drop(self.thread.take().unwrap().join());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{Acquired, SpannedAcquired};
+use std::fmt;
+use std::panic::Location;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable overriding after how many seconds a token counts as
+/// held for too long.
+#[cfg(feature = "log")]
+const SLOW_TOKEN_THRESHOLD_VAR: &str = "JOBSERVER_SLOW_TOKEN_THRESHOLD";
+
+/// Default for [`SLOW_TOKEN_THRESHOLD_VAR`].
+#[cfg(feature = "log")]
+const DEFAULT_SLOW_TOKEN_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// An acquired token which records where and when it was acquired.
+///
+/// This is created through [`Client::acquire_instrumented`] and is a
+/// [`SpannedAcquired`] whose span is named `name`, additionally recording the
+/// source location and thread name. Its [`Debug`](fmt::Debug) output reads
+/// like `Token[name] acquired at src/main.rs:42 by thread 'main', held for
+/// 1.23s`. With the `log` feature enabled, its release is logged like for
+/// [`SpannedAcquired`], including the location, and as a warning if it was
+/// held for longer than the number of seconds in the
+/// `JOBSERVER_SLOW_TOKEN_THRESHOLD` environment variable, 60 by default.
+///
+/// [`Client::acquire_instrumented`]: crate::Client::acquire_instrumented
+pub struct InstrumentedAcquired {
+    span: SpannedAcquired,
+    location: &'static Location<'static>,
+    thread_name: Option<String>,
+}
+
+impl InstrumentedAcquired {
+    pub(crate) fn new(
+        token: Acquired,
+        name: &'static str,
+        location: &'static Location<'static>,
+    ) -> InstrumentedAcquired {
+        let mut span = SpannedAcquired::new(token, name);
+        span.set_location(location);
+        #[cfg(feature = "log")]
+        span.set_long_hold(slow_token_threshold());
+        InstrumentedAcquired {
+            span,
+            location,
+            thread_name: thread::current().name().map(str::to_string),
+        }
+    }
+
+    /// Returns the name this token was acquired with.
+    pub fn name(&self) -> &'static str {
+        self.span.span_name()
+    }
+
+    /// Returns the source location this token was acquired at.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Returns the name of the thread which acquired this token, if any.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread_name.as_deref()
+    }
+
+    /// Returns when this token was acquired.
+    pub fn acquired_at(&self) -> Instant {
+        self.span.acquired_at()
+    }
+
+    /// Returns for how long this token has been held so far.
+    pub fn elapsed(&self) -> Duration {
+        self.span.elapsed()
+    }
+
+    /// Returns the underlying [`SpannedAcquired`] token.
+    pub fn into_span(self) -> SpannedAcquired {
+        self.span
+    }
+
+    /// Returns the underlying [`Acquired`] token.
+    ///
+    /// Its release will not be logged.
+    pub fn into_inner(self) -> Acquired {
+        self.span.into_inner()
+    }
+}
+
+impl fmt::Debug for InstrumentedAcquired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Token[{}] acquired at {}:{} by thread '{}', held for {:.2}s",
+            self.name(),
+            self.location.file(),
+            self.location.line(),
+            self.thread_name().unwrap_or("<unnamed>"),
+            self.elapsed().as_secs_f64(),
+        )
+    }
+}
+
+#[cfg(feature = "log")]
+fn slow_token_threshold() -> Duration {
+    std::env::var(SLOW_TOKEN_THRESHOLD_VAR)
+        .ok()
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map_or(DEFAULT_SLOW_TOKEN_THRESHOLD, Duration::from_secs_f64)
+}
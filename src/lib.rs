@@ -82,12 +82,35 @@
 #![doc(html_root_url = "https://docs.rs/jobserver/0.1")]
 
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+#[cfg(feature = "tokio")]
+use std::future::Future;
 use std::io;
-use std::process::Command;
-use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::num::NonZeroUsize;
+#[cfg(unix)]
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::thread::{self, Builder, JoinHandle};
+use std::time::{Duration, Instant};
 
+mod audit;
+mod backoff;
+mod builder;
+#[cfg(target_os = "linux")]
+mod cgroup;
+mod context;
+#[cfg(feature = "debug-backtraces")]
+mod debug_acquire;
+mod drain;
 mod error;
+mod file_lock;
+mod guard;
+mod heartbeat;
 #[cfg(unix)]
 #[path = "unix.rs"]
 mod imp;
@@ -97,6 +120,18 @@ mod imp;
 #[cfg(not(any(unix, windows)))]
 #[path = "wasm.rs"]
 mod imp;
+mod instrumented;
+mod observe;
+mod overflow;
+mod pid_label;
+mod process_count;
+mod recursive;
+mod scope;
+mod span;
+mod starvation;
+mod state;
+mod throttle;
+mod worker;
 
 /// A client of a jobserver
 ///
@@ -111,9 +146,342 @@ mod imp;
 ///
 /// Note that a [`Client`] implements the [`Clone`] trait, and all instances of
 /// a [`Client`] refer to the same jobserver instance.
+///
+/// Clients which are never dropped, e.g. because the process exits through
+/// [`std::process::exit`], don't need any cleanup: the OS closes their file
+/// descriptors or semaphore handles once the process terminates. Tokens held
+/// at that point are lost though.
 #[derive(Clone, Debug)]
 pub struct Client {
-    inner: Arc<imp::Client>,
+    inner: Arc<ClientInner>,
+}
+
+/// An environment variable to pass the jobserver through, for
+/// [`Client::configure_with_flags`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MakeFlag {
+    /// `MAKEFLAGS`, read by GNU make and most other tools.
+    MakeFlags,
+    /// `MFLAGS`, read by old versions of BSD make and some other tools.
+    MFlags,
+    /// `CARGO_MAKEFLAGS`, read by Cargo build scripts.
+    CargoMakeFlags,
+    /// An environment variable with a custom name.
+    Custom(String),
+}
+
+impl MakeFlag {
+    fn name(&self) -> &str {
+        match self {
+            MakeFlag::MakeFlags => "MAKEFLAGS",
+            MakeFlag::MFlags => "MFLAGS",
+            MakeFlag::CargoMakeFlags => "CARGO_MAKEFLAGS",
+            MakeFlag::Custom(name) => name,
+        }
+    }
+}
+
+/// A thread together with the token it was spawned with, for
+/// [`Client::wait_all`].
+pub type SpawnedJob = (JoinHandle<()>, Acquired);
+
+/// Value of `--jobserver-auth=` set by [`Client::configure_noop`].
+const NOOP_JOBSERVER_AUTH: &str = "none";
+
+/// Default for [`Client::set_eintr_retries`], retrying forever.
+#[cfg(unix)]
+const DEFAULT_EINTR_RETRIES: usize = usize::MAX;
+
+#[derive(Debug)]
+struct ClientInner {
+    /// `None` for clients created through [`Client::new_unlimited`].
+    imp: Option<imp::Client>,
+    parked: ParkState,
+    soft_limit: AtomicUsize,
+    soft_held: AtomicUsize,
+    /// Number of tokens in the jobserver, known only if this process created
+    /// it.
+    limit: Mutex<Option<usize>>,
+    /// Whether the jobserver was created outside of this process, see
+    /// [`ClientState::is_inherited`].
+    inherited: bool,
+    #[cfg(unix)]
+    eintr_retries: AtomicUsize,
+    wakers: context::WakerState,
+    /// Serializes [`Client::try_acquire_many_nonblocking`].
+    multi_acquire: Mutex<()>,
+    /// Number of successful acquisitions, see [`Client::acquisition_count`].
+    acquisitions: AtomicUsize,
+    /// Number of blocking acquisitions currently in progress.
+    inflight_acquires: AtomicUsize,
+    /// Highest value of `inflight_acquires` so far.
+    max_inflight_acquires: AtomicUsize,
+    /// Number of tokens acquired minus the number of tokens released through
+    /// this client, see [`Client::acquire_nonblocking_count`]. This goes
+    /// negative if more tokens are released than were acquired, e.g. the
+    /// implicit token.
+    held: AtomicIsize,
+    /// Start times of the blocking acquisitions currently in progress, see
+    /// [`Client::with_on_starvation`]. Only tracked while
+    /// `starvation_monitors` is non-zero.
+    acquire_starts: Mutex<Vec<Instant>>,
+    /// Number of running starvation monitors.
+    starvation_monitors: AtomicUsize,
+    hooks: Mutex<Hooks>,
+    /// Whether any hook was ever set, so that clients without hooks don't
+    /// need to lock `hooks`.
+    has_hooks: AtomicBool,
+}
+
+/// A blocking acquisition in progress, see [`ClientInner::begin_acquire`].
+struct PendingAcquire<'a> {
+    inner: &'a ClientInner,
+    start: Instant,
+    /// Whether `start` was added to [`ClientInner::acquire_starts`].
+    track_start: bool,
+    post_acquire: Option<Arc<dyn Fn(Duration) + Send + Sync>>,
+}
+
+impl PendingAcquire<'_> {
+    /// Ends the acquisition after it acquired a token, running the
+    /// post-acquire hook and returning the ordinal of the acquisition.
+    fn acquired(mut self) -> usize {
+        let (inner, start, post_acquire) = (self.inner, self.start, self.post_acquire.take());
+        drop(self);
+        if let Some(hook) = post_acquire {
+            hook(start.elapsed());
+        }
+        inner.held.fetch_add(1, Ordering::Relaxed);
+        inner
+            .acquisitions
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1)
+    }
+}
+
+impl Drop for PendingAcquire<'_> {
+    fn drop(&mut self) {
+        self.inner.inflight_acquires.fetch_sub(1, Ordering::SeqCst);
+        if self.track_start {
+            let mut starts = self.inner.acquire_starts();
+            if let Some(i) = starts.iter().position(|&s| s == self.start) {
+                starts.swap_remove(i);
+            }
+        }
+    }
+}
+
+/// Lifecycle hooks, see [`Client::hook_pre_acquire`] and friends.
+#[derive(Default)]
+struct Hooks {
+    pre_acquire: Option<Arc<dyn Fn() + Send + Sync>>,
+    post_acquire: Option<Arc<dyn Fn(Duration) + Send + Sync>>,
+    post_release: Option<Arc<dyn Fn() + Send + Sync>>,
+    /// See [`Client::observe_releases`].
+    release_observer: Option<Arc<Mutex<ReleaseObserver>>>,
+}
+
+type ReleaseObserver = Box<dyn Fn(Duration) + Send>;
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("pre_acquire", &self.pre_acquire.is_some())
+            .field("post_acquire", &self.post_acquire.is_some())
+            .field("post_release", &self.post_release.is_some())
+            .field("release_observer", &self.release_observer.is_some())
+            .finish()
+    }
+}
+
+impl ClientInner {
+    /// For jobservers created outside of this process.
+    fn inherited(imp: imp::Client) -> ClientInner {
+        ClientInner::from_parts(Some(imp), None, true)
+    }
+
+    fn unlimited() -> ClientInner {
+        ClientInner::from_parts(None, None, false)
+    }
+
+    /// For jobservers created by this process.
+    fn with_limit(imp: imp::Client, limit: usize) -> ClientInner {
+        ClientInner::from_parts(Some(imp), Some(limit), false)
+    }
+
+    fn from_parts(imp: Option<imp::Client>, limit: Option<usize>, inherited: bool) -> ClientInner {
+        ClientInner {
+            imp,
+            parked: ParkState::default(),
+            soft_limit: AtomicUsize::new(0),
+            soft_held: AtomicUsize::new(0),
+            limit: Mutex::new(limit),
+            inherited,
+            #[cfg(unix)]
+            eintr_retries: AtomicUsize::new(DEFAULT_EINTR_RETRIES),
+            wakers: context::WakerState::default(),
+            multi_acquire: Mutex::new(()),
+            acquisitions: AtomicUsize::new(0),
+            inflight_acquires: AtomicUsize::new(0),
+            max_inflight_acquires: AtomicUsize::new(0),
+            held: AtomicIsize::new(0),
+            acquire_starts: Mutex::new(Vec::new()),
+            starvation_monitors: AtomicUsize::new(0),
+            hooks: Mutex::new(Hooks::default()),
+            has_hooks: AtomicBool::new(false),
+        }
+    }
+
+    fn hooks(&self) -> MutexGuard<'_, Hooks> {
+        self.hooks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Locks the hooks for setting one.
+    fn hooks_mut(&self) -> MutexGuard<'_, Hooks> {
+        self.has_hooks.store(true, Ordering::SeqCst);
+        self.hooks()
+    }
+
+    /// Locks the hooks, unless none were ever set.
+    fn hooks_if_set(&self) -> Option<MutexGuard<'_, Hooks>> {
+        if self.has_hooks.load(Ordering::SeqCst) {
+            Some(self.hooks())
+        } else {
+            None
+        }
+    }
+
+    fn acquire_starts(&self) -> MutexGuard<'_, Vec<Instant>> {
+        self.acquire_starts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the start time of the longest running blocking acquisition.
+    fn oldest_acquire(&self) -> Option<Instant> {
+        self.acquire_starts().iter().min().copied()
+    }
+
+    /// Returns the time a token is acquired at if its hold time needs to be
+    /// reported, see [`Client::observe_releases`].
+    fn observe_start(&self) -> Option<Instant> {
+        self.hooks_if_set()?
+            .release_observer
+            .as_ref()
+            .map(|_| Instant::now())
+    }
+
+    fn acquire(&self) -> io::Result<imp::Acquired> {
+        self.acquire_counted().map(|(data, _)| data)
+    }
+
+    /// Acquires a token, also returning its ordinal among all acquisitions.
+    fn acquire_counted(&self) -> io::Result<(imp::Acquired, usize)> {
+        let pending = self.begin_acquire();
+        let data = match &self.imp {
+            #[cfg(unix)]
+            Some(imp) => imp.acquire(self.eintr_retries.load(Ordering::Relaxed))?,
+            #[cfg(not(unix))]
+            Some(imp) => imp.acquire()?,
+            None => imp::Acquired::from_raw(0),
+        };
+        Ok((data, pending.acquired()))
+    }
+
+    /// Starts the bookkeeping for a blocking acquisition: runs the pre-acquire
+    /// hook and counts the acquisition as in progress until the returned
+    /// [`PendingAcquire`] is dropped.
+    fn begin_acquire(&self) -> PendingAcquire<'_> {
+        // Hooks are cloned out of the lock so that they may use the client.
+        let (pre_acquire, post_acquire) = match self.hooks_if_set() {
+            Some(hooks) => (hooks.pre_acquire.clone(), hooks.post_acquire.clone()),
+            None => (None, None),
+        };
+        if let Some(hook) = pre_acquire {
+            hook();
+        }
+        let start = Instant::now();
+        let track_start = self.starvation_monitors.load(Ordering::SeqCst) > 0;
+        if track_start {
+            self.acquire_starts().push(start);
+        }
+        let inflight = self.inflight_acquires.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_inflight_acquires
+            .fetch_max(inflight, Ordering::Relaxed);
+        PendingAcquire {
+            inner: self,
+            start,
+            track_start,
+            post_acquire,
+        }
+    }
+
+    fn try_acquire(&self) -> io::Result<Option<imp::Acquired>> {
+        let data = match &self.imp {
+            Some(imp) => imp.try_acquire()?,
+            None => Some(imp::Acquired::from_raw(0)),
+        };
+        if data.is_some() {
+            self.acquisitions.fetch_add(1, Ordering::Relaxed);
+            self.held.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(data)
+    }
+
+    /// Returns the number of tokens currently held through this client and
+    /// its clones, not counting the implicit token.
+    fn held_tokens(&self) -> usize {
+        self.held.load(Ordering::Relaxed).max(0) as usize
+    }
+
+    /// Updates the known number of tokens after handing some out or taking
+    /// some in.
+    ///
+    /// Tokens handed out were acquired before and are counted as held until
+    /// now. Tokens taken in were released through [`ClientInner::release`],
+    /// which counts them as no longer held, so that's undone.
+    fn adjust_limit(&self, removed: usize, added: usize) {
+        self.held
+            .fetch_add(added as isize - removed as isize, Ordering::Relaxed);
+        let mut limit = self.limit.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(limit) = limit.as_mut() {
+            *limit = (*limit + added).saturating_sub(removed);
+        }
+    }
+
+    fn release(&self, data: Option<&imp::Acquired>) -> io::Result<()> {
+        if let Some(imp) = &self.imp {
+            imp.release(data)?;
+        }
+        self.held.fetch_sub(1, Ordering::Relaxed);
+        let post_release = self
+            .hooks_if_set()
+            .and_then(|hooks| hooks.post_release.clone());
+        if let Some(hook) = post_release {
+            hook();
+        }
+        Ok(())
+    }
+
+    fn available(&self) -> io::Result<usize> {
+        match &self.imp {
+            Some(imp) => imp.available(),
+            None => Ok(usize::MAX),
+        }
+    }
+
+    fn is_cross_process(&self) -> bool {
+        match &self.imp {
+            Some(imp) => imp.is_cross_process(),
+            None => false,
+        }
+    }
+
+    fn configure(&self, cmd: &mut Command) {
+        if let Some(imp) = &self.imp {
+            imp.configure(cmd);
+        }
+    }
 }
 
 /// An acquired token from a jobserver.
@@ -122,9 +490,14 @@ pub struct Client {
 /// otherwise represents the ability to spawn off another thread of work.
 #[derive(Debug)]
 pub struct Acquired {
-    client: Arc<imp::Client>,
+    client: Arc<ClientInner>,
     data: imp::Acquired,
     disabled: bool,
+    /// Whether this token was acquired through [`Client::soft_acquire`]
+    /// without touching the jobserver.
+    soft: bool,
+    /// Set if a release observer was installed when this token was acquired.
+    acquired_at: Option<Instant>,
 }
 
 impl Acquired {
@@ -135,8 +508,112 @@ impl Acquired {
     ///
     /// You'll typically want to follow this up with a call to
     /// [`Client::release_raw`] or similar to actually release the token later on.
+    ///
+    /// Tokens acquired through [`Client::soft_acquire`] which didn't come from
+    /// the jobserver are returned to the soft budget, as there's nothing to
+    /// release later on.
     pub fn drop_without_releasing(mut self) {
         self.disabled = true;
+        if self.soft {
+            self.client.soft_held.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Releases this token like dropping it does, but reporting errors.
+    fn release(mut self) -> io::Result<()> {
+        if self.disabled {
+            return Ok(());
+        }
+        self.disabled = true;
+        if self.soft {
+            self.client.soft_held.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            self.client.release(Some(&self.data))?;
+        }
+        self.observe_release();
+        Ok(())
+    }
+
+    /// Reports how long this token was held to the release observer, see
+    /// [`Client::observe_releases`].
+    fn observe_release(&self) {
+        let acquired_at = match self.acquired_at {
+            Some(acquired_at) => acquired_at,
+            None => return,
+        };
+        let observer = self.client.hooks().release_observer.clone();
+        if let Some(observer) = observer {
+            let observer = observer.lock().unwrap_or_else(|e| e.into_inner());
+            observer(acquired_at.elapsed());
+        }
+    }
+
+    /// Consumes the [`Acquired`] token, returning an opaque [`RawToken`]
+    /// which can be handed over to foreign code.
+    ///
+    /// The token is not released, that's done later on by either
+    /// [`Client::release_raw_token`] or dropping the [`Acquired`] returned by
+    /// [`Acquired::from_raw`].
+    pub fn into_raw(mut self) -> RawToken {
+        self.disabled = true;
+        RawToken::new(self.data.to_raw(), self.soft)
+    }
+
+    /// Reconstructs an [`Acquired`] token from a [`RawToken`] previously
+    /// returned by [`Acquired::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `raw` must come from a token acquired from the same jobserver as
+    /// `client`, and must not be used again afterwards. Otherwise more tokens
+    /// than were acquired would be released, corrupting the jobserver.
+    pub unsafe fn from_raw(client: &Client, raw: RawToken) -> Acquired {
+        Acquired {
+            client: client.inner.clone(),
+            data: imp::Acquired::from_raw(raw.data()),
+            disabled: false,
+            soft: raw.is_soft(),
+            acquired_at: client.inner.observe_start(),
+        }
+    }
+}
+
+/// An opaque integer representing an [`Acquired`] token, suitable for passing
+/// across FFI boundaries.
+///
+/// This is created through [`Acquired::into_raw`] and converts to and from
+/// [`u64`] for the foreign side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct RawToken(u64);
+
+impl RawToken {
+    /// Set for soft tokens, see [`Client::soft_acquire`], which must be
+    /// returned to the soft budget rather than the jobserver.
+    const SOFT: u64 = 1 << 63;
+
+    fn new(data: u64, soft: bool) -> RawToken {
+        RawToken(if soft { data | RawToken::SOFT } else { data })
+    }
+
+    fn data(self) -> u64 {
+        self.0 & !RawToken::SOFT
+    }
+
+    fn is_soft(self) -> bool {
+        self.0 & RawToken::SOFT != 0
+    }
+}
+
+impl From<RawToken> for u64 {
+    fn from(raw: RawToken) -> u64 {
+        raw.0
+    }
+}
+
+impl From<u64> for RawToken {
+    fn from(raw: u64) -> RawToken {
+        RawToken(raw)
     }
 }
 
@@ -149,12 +626,73 @@ struct HelperState {
 #[derive(Default, Debug)]
 struct HelperInner {
     requests: usize,
+    /// Limit on `requests` for [`HelperThread::try_request`], if any.
+    max_pending: Option<usize>,
     producer_done: bool,
     consumer_done: bool,
 }
 
+/// Threads blocked in [`Client::acquire_park`] along with the tokens handed
+/// over to them through [`Client::unpark_one`].
+#[derive(Default)]
+struct ParkState {
+    lock: Mutex<ParkInner>,
+    cvar: Condvar,
+}
+
+#[derive(Default)]
+struct ParkInner {
+    waiters: usize,
+    // Always shorter than `waiters`, so that each token here has a thread
+    // about to pick it up.
+    tokens: Vec<Acquired>,
+}
+
+impl ParkState {
+    fn lock(&self) -> MutexGuard<'_, ParkInner> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl std::fmt::Debug for ParkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Tokens refer back to the client itself, so only print counts here
+        // to avoid recursing.
+        let lock = self.lock();
+        f.debug_struct("ParkState")
+            .field("waiters", &lock.waiters)
+            .field("tokens", &lock.tokens.len())
+            .finish()
+    }
+}
+
+pub use audit::{AuditClient, AuditedAcquired};
+pub use backoff::BackoffStrategy;
+pub use builder::ClientBuilder;
+pub use drain::DrainedClient;
 use error::FromEnvErrorInner;
 pub use error::{FromEnvError, FromEnvErrorKind};
+pub use file_lock::{FileLockAcquired, FileLockClient};
+#[cfg(feature = "log")]
+pub use guard::LoggingGuard;
+pub use guard::{GuardTrait, SimpleGuard, TimedGuard, TypedClient};
+pub use heartbeat::HeartbeatHandle;
+pub use instrumented::InstrumentedAcquired;
+#[cfg(feature = "log")]
+pub use observe::LogObserver;
+pub use observe::{
+    ChainObserver, ClientStats, MetricsObserver, ObservableClient, ObservedAcquired, Observer,
+};
+pub use overflow::OverflowProtectedClient;
+pub use pid_label::{PidLabeledAcquired, PidLabeledClient};
+pub use process_count::ProcessCountClient;
+pub use recursive::RecursiveJobsLimit;
+pub use scope::ClientScope;
+pub use span::SpannedAcquired;
+pub use starvation::StarvationMonitorHandle;
+pub use state::ClientState;
+pub use throttle::ThrottledClient;
+pub use worker::WorkerHandle;
 
 /// Return type for [`Client::from_env_ext`] function.
 #[derive(Debug)]
@@ -205,12 +743,309 @@ impl Client {
     ///
     /// Returns an error if any I/O error happens when attempting to create the
     /// jobserver client.
+    ///
+    /// See [`ClientBuilder`] for more options.
     pub fn new(limit: usize) -> io::Result<Client> {
         Ok(Client {
-            inner: Arc::new(imp::Client::new(limit)?),
+            inner: Arc::new(ClientInner::with_limit(imp::Client::new(limit)?, limit)),
         })
     }
 
+    /// Creates a new jobserver with one token per CPU.
+    ///
+    /// This is a shorthand for [`Client::new`] with the number of tokens
+    /// given by [`std::thread::available_parallelism`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`std::thread::available_parallelism`] fails or
+    /// if creating the jobserver fails.
+    pub fn new_limited_by_cpu() -> io::Result<Client> {
+        Client::new(thread::available_parallelism()?.get())
+    }
+
+    /// Creates a new jobserver with one token per CPU of the system.
+    ///
+    /// Unlike [`Client::new_limited_by_cpu`], which takes CPU affinity and
+    /// quotas of this process into account, this counts the CPUs of the
+    /// whole machine, for build systems that want to fully use the hardware.
+    /// NUMA topology isn't taken into account.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Linux the online CPUs are read from
+    /// `/sys/devices/system/cpu/online`, falling back to `/proc/cpuinfo`. On
+    /// macOS `sysctl hw.logicalcpu` is used and on Windows `GetSystemInfo`.
+    /// Other Unix platforms use `sysconf(_SC_NPROCESSORS_ONLN)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the CPUs can't be counted, with
+    /// [`io::ErrorKind::Unsupported`] on platforms other than Unix and
+    /// Windows, or if creating the jobserver fails.
+    pub fn new_from_cpuinfo() -> io::Result<Client> {
+        Client::new(imp::online_cpus()?)
+    }
+
+    /// Same as [`Client::new_from_cpuinfo`], but with no more than `max`
+    /// tokens.
+    pub fn new_from_cpuinfo_capped(max: usize) -> io::Result<Client> {
+        Client::new(imp::online_cpus()?.min(max))
+    }
+
+    /// Returns the upper bound the system recommends for the number of
+    /// tokens in a jobserver, if any.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this is the soft `RLIMIT_NPROC` resource limit, the maximum
+    /// number of processes the current user may create, and `None` if that's
+    /// unlimited. Note that the limit applies to all processes of the user,
+    /// not just children of this process. On Windows, which has no such
+    /// limit, this is the number of processors as reported by
+    /// `GetSystemInfo`. Other platforms return `None`.
+    pub fn global_limit() -> Option<usize> {
+        imp::process_limit()
+    }
+
+    /// Creates a new jobserver with `desired` tokens, but no more than
+    /// [`Client::global_limit`].
+    ///
+    /// This prevents creating jobservers with more tokens than the system
+    /// can support, e.g. more jobs than the user may run processes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if creating the jobserver fails, see [`Client::new`].
+    pub fn new_within_system_limits(desired: usize) -> io::Result<Client> {
+        Client::new(Client::global_limit().map_or(desired, |limit| limit.min(desired)))
+    }
+
+    /// Creates a new jobserver sized to keep the system load at
+    /// `target_load`.
+    ///
+    /// The number of tokens is the headroom between `target_load` and the
+    /// current load average over the last minute, capped at
+    /// [`std::thread::available_parallelism`] and at least 1, so that a
+    /// build tool can make progress without overloading a busy machine.
+    /// Passing the number of CPUs as `target_load` leaves room for as many
+    /// jobs as there are idle CPUs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with [`io::ErrorKind::Unsupported`] on platforms
+    /// without a load average, such as Windows, or if reading the load
+    /// average or creating the jobserver fails.
+    pub fn new_limited_by_load(target_load: f64) -> io::Result<Client> {
+        let cpus = thread::available_parallelism()?.get();
+        let headroom = (target_load - imp::load_average()?).floor();
+        Client::new((headroom as usize).clamp(1, cpus))
+    }
+
+    /// Creates a new jobserver with one token per live process in `pid_list`.
+    ///
+    /// Processes which aren't running anymore are ignored, and the token of
+    /// each remaining process is held on its behalf: the jobserver starts out
+    /// with no tokens available. A monitoring thread periodically checks the
+    /// processes and releases a token once one of them exits, for "one token
+    /// per live worker" semantics in process pool managers, e.g. to spawn a
+    /// replacement for each worker which exited.
+    ///
+    /// On Linux processes are checked through procfs, counting zombies as
+    /// exited, and on Windows through `OpenProcess`.
+    /// If releasing the token of an exited process fails, the process is
+    /// checked again later on to retry, and the error is logged with the
+    /// `log` feature enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if creating the jobserver or spawning the monitoring
+    /// thread fails.
+    pub fn new_from_process_count(pid_list: &[u32]) -> io::Result<ProcessCountClient> {
+        let pids = pid_list
+            .iter()
+            .copied()
+            .filter(|&pid| imp::process_is_alive(pid))
+            .collect::<Vec<_>>();
+        // The semaphore must have room for the tokens released later on.
+        #[cfg(windows)]
+        let imp = imp::Client::new_validated(0, Some(pids.len()), None, std::ptr::null_mut())?;
+        #[cfg(not(windows))]
+        let imp = imp::Client::new(0)?;
+        let client = Client {
+            inner: Arc::new(ClientInner::with_limit(imp, pids.len())),
+        };
+        ProcessCountClient::spawn(client, pids)
+    }
+
+    /// Creates a new jobserver with the number of tokens saved by
+    /// [`Client::checkpoint`] in `path`, but no more than `limit`.
+    ///
+    /// This allows a build system restarting after a crash to continue with
+    /// the tokens that were available at the time of the checkpoint, rather
+    /// than a full pool, since some tokens may have been consumed by work
+    /// which partially completed. If there is no checkpoint at `path` the
+    /// jobserver starts out with `limit` tokens.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the checkpoint fails, with
+    /// [`io::ErrorKind::InvalidData`] if it doesn't contain a number, or if
+    /// creating the jobserver fails.
+    pub fn restore_checkpoint(path: &Path, limit: usize) -> io::Result<Client> {
+        let saved = match fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse::<usize>().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid jobserver checkpoint: {contents:?}"),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => limit,
+            Err(e) => return Err(e),
+        };
+        Client::new(saved.min(limit))
+    }
+
+    /// Creates a client which never limits parallelism.
+    ///
+    /// Acquiring a token from the returned client always succeeds immediately
+    /// and releasing one does nothing. There is no jobserver behind it, so
+    /// [`Client::configure`] and friends leave child processes untouched and
+    /// [`Client::available`] returns [`usize::MAX`]. Use
+    /// [`Client::is_unlimited`] to tell these clients apart.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    ///
+    /// let client = Client::new_unlimited();
+    /// assert!(client.is_unlimited());
+    /// drop(client.acquire().unwrap()); // never blocks
+    /// ```
+    pub fn new_unlimited() -> Client {
+        Client {
+            inner: Arc::new(ClientInner::unlimited()),
+        }
+    }
+
+    /// Creates a new jobserver like [`Client::new`], wrapped in an [`Arc`].
+    ///
+    /// See [`Client::into_arc`] for more info.
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::new`] are propagated.
+    pub fn new_arc(limit: usize) -> io::Result<Arc<Client>> {
+        Client::new(limit).map(Client::into_arc)
+    }
+
+    /// Wraps this client in an [`Arc`] for shared ownership.
+    ///
+    /// All methods of [`Client`] can be called on the returned [`Arc`]
+    /// directly through auto-deref. Note that [`Client`] is cheap to clone
+    /// already, with clones sharing the same jobserver, so this is only
+    /// useful for APIs requiring an [`Arc`].
+    pub fn into_arc(self) -> Arc<Client> {
+        Arc::new(self)
+    }
+
+    /// Creates a new jobserver limited to the CPU quota of a cgroup.
+    ///
+    /// `cgroup_path` is the directory of a cgroup v2, for example
+    /// `/sys/fs/cgroup/build.slice`, whose `cpu.max` determines the number
+    /// of tokens: partial CPUs are rounded up, and cgroups without a quota
+    /// use [`std::thread::available_parallelism`].
+    ///
+    /// Use [`Client::adjust_to_cgroup`] to follow later changes of the quota.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cpu.max` can't be read or parsed, or if creating
+    /// the jobserver fails.
+    #[cfg(target_os = "linux")]
+    pub fn configure_cgroup(cgroup_path: &Path) -> io::Result<Client> {
+        Client::new(cgroup::cpu_limit(cgroup_path)?)
+    }
+
+    /// Resizes this jobserver to the current CPU quota of a cgroup.
+    ///
+    /// The quota is determined the same way as in [`Client::configure_cgroup`].
+    /// Growing the jobserver happens immediately. Shrinking it is limited to
+    /// the tokens which aren't currently acquired by anyone, so the jobserver
+    /// may remain larger than the quota, in which case calling this again
+    /// later continues to shrink it.
+    ///
+    /// This does nothing for clients created through
+    /// [`Client::new_unlimited`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cpu.max` can't be read or parsed, if an I/O
+    /// error happens while resizing, or with [`io::ErrorKind::Unsupported`]
+    /// if this jobserver wasn't created by this process, as its size is
+    /// unknown then.
+    #[cfg(target_os = "linux")]
+    pub fn adjust_to_cgroup(&self, cgroup_path: &Path) -> io::Result<()> {
+        let target = cgroup::cpu_limit(cgroup_path)?;
+        let imp = match &self.inner.imp {
+            Some(imp) => imp,
+            None => return Ok(()),
+        };
+        let mut limit = self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        let current = limit.as_mut().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot resize a jobserver of unknown size",
+            )
+        })?;
+        while *current < target {
+            imp.release(None)?;
+            *current += 1;
+        }
+        while *current > target {
+            match imp.try_acquire()? {
+                Some(_) => *current -= 1,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes this client's jobserver right away, reporting any errors.
+    ///
+    /// Dropping a [`Client`] closes its file descriptors or semaphore handle
+    /// as well, but ignores errors doing so. This is meant for long-lived
+    /// processes which create and destroy many clients, to release their
+    /// resources deterministically and notice failures.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if closing fails, e.g. `close` or `CloseHandle`
+    /// reporting an error. The resources can only be closed once all clones
+    /// of this client and all tokens acquired from it were dropped, and the
+    /// background thread of [`Client::acquire_with_context`] exited, so an
+    /// error of the kind [`io::ErrorKind::Other`] is returned otherwise,
+    /// dropping just this client.
+    pub fn close(self) -> io::Result<()> {
+        let mut inner = Arc::try_unwrap(self.inner).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "client is still used by clones or tokens",
+            )
+        })?;
+        match inner.imp.take() {
+            Some(imp) => imp.close(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether this client was created through
+    /// [`Client::new_unlimited`].
+    pub fn is_unlimited(&self) -> bool {
+        self.inner.imp.is_none()
+    }
+
     /// Attempts to connect to the jobserver specified in this process's
     /// environment.
     ///
@@ -260,37 +1095,177 @@ impl Client {
             None => return FromEnv::new_err(FromEnvErrorInner::NoEnvVar, "", Default::default()),
         };
 
-        let var = match var_os.to_str() {
-            Some(var) => var,
-            None => {
-                let err = FromEnvErrorInner::CannotParse("not valid UTF-8".to_string());
-                return FromEnv::new_err(err, env, var_os);
-            }
-        };
-
-        let s = match find_jobserver_auth(var) {
-            Some(s) => s,
-            None => return FromEnv::new_err(FromEnvErrorInner::NoJobserver, env, var_os),
-        };
-        match imp::Client::open(s, check_pipe) {
-            Ok(c) => FromEnv::new_ok(Client { inner: Arc::new(c) }, env, var_os),
+        match Self::from_flags(&var_os, check_pipe) {
+            Ok(client) => FromEnv::new_ok(client, env, var_os),
             Err(err) => FromEnv::new_err(err, env, var_os),
         }
     }
 
-    /// Attempts to connect to the jobserver specified in this process's
-    /// environment.
+    /// Attempts to connect to the jobserver specified in the first of `vars`
+    /// which contains a usable one.
     ///
-    /// Wraps [`Client::from_env_ext`] and discards error details.
+    /// Each environment variable is parsed like `MAKEFLAGS` in
+    /// [`Client::from_env_ext`] without pipe checks, so tools with their own
+    /// naming conventions can reuse the same parsing. Variables which aren't
+    /// set or fail to connect are skipped in favor of the next one. Use
+    /// [`Client::configure_as_env_var`] to pass a jobserver through such a
+    /// variable.
     ///
-    /// # Safety
+    /// # Errors
     ///
-    /// This function is `unsafe` to call on Unix specifically as it
-    /// transitively requires usage of the `from_raw_fd` function, which is
-    /// itself unsafe in some circumstances.
+    /// If none of `vars` yields a client, the error for the first variable
+    /// which is set is returned, or [`FromEnvErrorKind::NoEnvVar`] if none of
+    /// them is.
     ///
-    /// It's recommended to call this function very early in the lifetime of a
-    /// program before any other file descriptors are opened. That way you can
+    /// # Safety
+    ///
+    /// See [`Client::from_env_ext`].
+    pub unsafe fn from_env_vars(vars: &[&str]) -> Result<Client, FromEnvError> {
+        let mut first_err = None;
+        for var_os in vars.iter().filter_map(env::var_os) {
+            match Self::from_flags(&var_os, false) {
+                Ok(client) => return Ok(client),
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        Err(FromEnvError {
+            inner: first_err.unwrap_or(FromEnvErrorInner::NoEnvVar),
+        })
+    }
+
+    /// Attempts to connect to the jobserver passed by Ninja, or through
+    /// [`Client::configure_for_ninja`].
+    ///
+    /// Ninja 1.13 and later pass their jobserver through `MAKEFLAGS` in the
+    /// format of GNU make 4.4: `--jobserver-auth=fifo:PATH` on Unix, where
+    /// Ninja doesn't support anonymous pipes, and
+    /// `--jobserver-auth=NAME` with the name of a semaphore on Windows. The
+    /// last `--jobserver-auth=` flag wins. Since no file descriptors are
+    /// inherited, this is safe to call unlike [`Client::from_env`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `MAKEFLAGS` isn't set or doesn't contain a
+    /// jobserver in this format, or if connecting to it fails.
+    pub fn from_ninja_env() -> Result<Client, FromEnvError> {
+        let err = |inner| FromEnvError { inner };
+        let var_os = env::var_os("MAKEFLAGS").ok_or(err(FromEnvErrorInner::NoEnvVar))?;
+        let var = var_os.to_str().ok_or_else(|| {
+            err(FromEnvErrorInner::CannotParse(
+                "not valid UTF-8".to_string(),
+            ))
+        })?;
+        let s = var
+            .rsplit_once("--jobserver-auth=")
+            .and_then(|(_, s)| s.split(' ').next())
+            .ok_or(err(FromEnvErrorInner::NoJobserver))?;
+        if cfg!(unix) && !s.starts_with("fifo:") {
+            return Err(err(FromEnvErrorInner::CannotParse(format!(
+                "expected `fifo:PATH`, found `{s}`"
+            ))));
+        }
+        // SAFETY: named fifos and semaphores are opened by name, so no
+        // inherited file descriptors are taken ownership of.
+        match unsafe { imp::Client::open(s, false) } {
+            Ok(c) => Ok(Client {
+                inner: Arc::new(ClientInner::inherited(c)),
+            }),
+            Err(inner) => Err(err(inner)),
+        }
+    }
+
+    /// Connects to the jobserver in `var_os`, a value like `MAKEFLAGS`.
+    unsafe fn from_flags(var_os: &OsStr, check_pipe: bool) -> Result<Client, FromEnvErrorInner> {
+        let var = var_os
+            .to_str()
+            .ok_or_else(|| FromEnvErrorInner::CannotParse("not valid UTF-8".to_string()))?;
+        let s = find_jobserver_auth(var).ok_or(FromEnvErrorInner::NoJobserver)?;
+        if s == NOOP_JOBSERVER_AUTH {
+            return Ok(Client::new_unlimited());
+        }
+        let c = imp::Client::open(s, check_pipe)?;
+        Ok(Client {
+            inner: Arc::new(ClientInner::inherited(c)),
+        })
+    }
+
+    /// Attempts to connect to the jobserver specified in this process's
+    /// environment, verifying that the process `expected_pid` owning it is
+    /// still running.
+    ///
+    /// Long-running processes such as daemons may inherit jobserver
+    /// information from an environment whose jobserver has long exited, in
+    /// which case the file descriptors or semaphore names may be stale or
+    /// even refer to something else entirely. This fails with
+    /// [`FromEnvErrorKind::OwnerProcessDead`] if `expected_pid` doesn't exist
+    /// anymore, otherwise behaving like [`Client::from_env_ext`] without
+    /// pipe checks.
+    ///
+    /// On Linux, zombie processes are considered dead as well.
+    ///
+    /// # Safety
+    ///
+    /// See [`Client::from_env_ext`].
+    pub unsafe fn from_env_checking_pid(expected_pid: u32) -> FromEnv {
+        let mut from_env = Self::from_env_ext(false);
+        if from_env.client.is_ok() && !imp::process_is_alive(expected_pid) {
+            from_env.client = Err(FromEnvError {
+                inner: FromEnvErrorInner::OwnerProcessDead(expected_pid),
+            });
+        }
+        from_env
+    }
+
+    /// Attempts to connect to the jobserver passed through
+    /// [`Client::configure_env_prefix`] with the same `prefix`.
+    ///
+    /// This reads the `{prefix}_JOBSERVER_AUTH` environment variable instead
+    /// of `MAKEFLAGS` and friends, allowing several independent jobservers to
+    /// be passed to a process at once. A value of `none` makes this return a
+    /// client like [`Client::new_unlimited`], the same as for
+    /// [`Client::configure_noop`].
+    ///
+    /// # Safety
+    ///
+    /// See [`Client::from_env_ext`].
+    pub unsafe fn from_env_prefix(prefix: &str) -> Result<Client, FromEnvError> {
+        let var_os = match env::var_os(format!("{prefix}_JOBSERVER_AUTH")) {
+            Some(var_os) => var_os,
+            None => {
+                return Err(FromEnvError {
+                    inner: FromEnvErrorInner::NoEnvVar,
+                })
+            }
+        };
+        let s = var_os.to_str().ok_or_else(|| FromEnvError {
+            inner: FromEnvErrorInner::CannotParse("not valid UTF-8".to_string()),
+        })?;
+        if s == NOOP_JOBSERVER_AUTH {
+            return Ok(Client::new_unlimited());
+        }
+        match imp::Client::open(s, false) {
+            Ok(c) => Ok(Client {
+                inner: Arc::new(ClientInner::inherited(c)),
+            }),
+            Err(inner) => Err(FromEnvError { inner }),
+        }
+    }
+
+    /// Attempts to connect to the jobserver specified in this process's
+    /// environment.
+    ///
+    /// Wraps [`Client::from_env_ext`] and discards error details.
+    ///
+    /// # Safety
+    ///
+    /// This function is `unsafe` to call on Unix specifically as it
+    /// transitively requires usage of the `from_raw_fd` function, which is
+    /// itself unsafe in some circumstances.
+    ///
+    /// It's recommended to call this function very early in the lifetime of a
+    /// program before any other file descriptors are opened. That way you can
     /// make sure to take ownership properly of the file descriptors passed
     /// down, if any.
     ///
@@ -299,6 +1274,72 @@ impl Client {
         Self::from_env_ext(false).client.ok()
     }
 
+    /// Attempts to connect to the jobserver specified in this process's
+    /// environment, falling back to the client created by `f` if there is
+    /// none.
+    ///
+    /// `f` is only called if there's no jobserver in the environment, i.e.
+    /// if [`Client::from_env_ext`] fails with [`FromEnvErrorKind::NoEnvVar`]
+    /// or [`FromEnvErrorKind::NoJobserver`]. This allows arbitrary fallback
+    /// strategies, e.g. reading the limit from a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Any other error connecting to the jobserver in the environment is
+    /// returned without calling `f`, wrapping the [`FromEnvError`]. Errors
+    /// returned by `f` are passed through.
+    ///
+    /// # Safety
+    ///
+    /// See [`Client::from_env`].
+    pub unsafe fn from_env_with_fallback<F>(f: F) -> io::Result<Client>
+    where
+        F: FnOnce() -> io::Result<Client>,
+    {
+        match Self::from_env_ext(false).client {
+            Ok(client) => Ok(client),
+            Err(e) => match e.kind() {
+                FromEnvErrorKind::NoEnvVar | FromEnvErrorKind::NoJobserver => f(),
+                _ => Err(io::Error::new(io::ErrorKind::Other, e)),
+            },
+        }
+    }
+
+    /// Attempts to connect to the jobserver specified in this process's
+    /// environment, falling back to an unlimited client.
+    ///
+    /// This is intended for contexts like build scripts where failing to
+    /// connect to a jobserver shouldn't be fatal. Any failure of
+    /// [`Client::from_env`] results in a client created through
+    /// [`Client::new_unlimited`], which can be detected with
+    /// [`Client::is_unlimited`].
+    ///
+    /// # Safety
+    ///
+    /// See [`Client::from_env`].
+    pub unsafe fn from_env_lossy() -> Client {
+        Self::from_env().unwrap_or_else(Client::new_unlimited)
+    }
+
+    /// Attempts to connect to the jobserver specified in this process's
+    /// environment, falling back to a new jobserver with `parallelism`
+    /// tokens.
+    ///
+    /// This never fails: if there's no usable jobserver in the environment
+    /// and creating a new one with [`Client::new`] fails as well, a client
+    /// like [`Client::new_unlimited`] is returned. It's intended for code
+    /// treating the jobserver as a best-effort optimization which shouldn't
+    /// fail the overall operation.
+    ///
+    /// # Safety
+    ///
+    /// See [`Client::from_env`].
+    pub unsafe fn from_env_or_default(parallelism: NonZeroUsize) -> Client {
+        Self::from_env()
+            .or_else(|| Client::new(parallelism.get()).ok())
+            .unwrap_or_else(Client::new_unlimited)
+    }
+
     /// Acquires a token from this jobserver client.
     ///
     /// This function will block the calling thread until a new token can be
@@ -321,190 +1362,2838 @@ impl Client {
             client: self.inner.clone(),
             data,
             disabled: false,
+            soft: false,
+            acquired_at: self.inner.observe_start(),
+        })
+    }
+
+    /// Blocks until a token is available from any of `clients`, acquiring it
+    /// from whichever has one first.
+    ///
+    /// This is the `select` primitive for processes participating in several
+    /// jobservers, e.g. one per build system. The index of the client that
+    /// provided the token is returned along with the token. On Unix all
+    /// jobservers are waited on with `poll`, and on Windows with
+    /// `WaitForMultipleObjects`. Unlimited clients always provide a token
+    /// right away.
+    ///
+    /// While waiting, this counts as a blocking acquisition on each of
+    /// `clients` like [`Client::acquire`], e.g. for their pre-acquire hooks,
+    /// [`Client::pending_acquires`], [`Client::heartbeat`] and
+    /// [`Client::with_on_starvation`]. Only the client which provided the
+    /// token runs its post-acquire hook.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with [`io::ErrorKind::InvalidInput`] if `clients` is
+    /// empty, or on Windows if it has more than 64 elements. Errors waiting
+    /// for or acquiring a token are propagated. Tokens are acquired through
+    /// [`Client::try_acquire`] after waiting, so waiting for more than one
+    /// client fails with [`io::ErrorKind::Unsupported`] on platforms where
+    /// the jobserver can't be read without blocking.
+    pub fn acquire_select(clients: &[&Client]) -> io::Result<(usize, Acquired)> {
+        if clients.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot select from no jobservers",
+            ));
+        }
+        let imps = match clients
+            .iter()
+            .map(|client| client.inner.imp.as_ref())
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(imps) => imps,
+            None => {
+                let i = clients.iter().position(|c| c.is_unlimited()).unwrap();
+                return Ok((i, clients[i].acquire()?));
+            }
+        };
+        // The wait counts as a blocking acquisition on every client, ending
+        // with a token acquired for the one which provided it.
+        let mut pending = clients
+            .iter()
+            .map(|client| client.inner.begin_acquire())
+            .collect::<Vec<_>>();
+        let (i, data) = imp::acquire_select(&imps)?;
+        let acquired = pending.swap_remove(i);
+        drop(pending);
+        acquired.acquired();
+        let inner = &clients[i].inner;
+        Ok((
+            i,
+            Acquired {
+                client: inner.clone(),
+                data,
+                disabled: false,
+                soft: false,
+                acquired_at: inner.observe_start(),
+            },
+        ))
+    }
+
+    /// Opens the jobserver semaphore `name`, creating it with `limit` tokens
+    /// if it doesn't exist yet.
+    ///
+    /// The returned `bool` is `true` if the semaphore was created by this
+    /// call and `false` if an existing one was opened, in which case `limit`
+    /// is ignored. This allows a pool of processes to share a jobserver by
+    /// name, with whichever process starts first initializing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` contains a nul byte or if any I/O error
+    /// happens when creating or opening the semaphore.
+    #[cfg(windows)]
+    pub fn open_or_create(name: &str, limit: usize) -> io::Result<(Client, bool)> {
+        let (imp, created) = imp::Client::open_or_create(name, limit)?;
+        let inner = if created {
+            ClientInner::with_limit(imp, limit)
+        } else {
+            ClientInner::inherited(imp)
+        };
+        Ok((
+            Client {
+                inner: Arc::new(inner),
+            },
+            created,
+        ))
+    }
+
+    /// Creates a new jobserver semaphore `name` with `limit` tokens, which
+    /// stays alive after this client is dropped.
+    ///
+    /// A named semaphore exists as long as any process holds a handle to it.
+    /// The handle of the returned client is never closed, not even once
+    /// all of its clones were dropped, so the semaphore outlives the client
+    /// and remains available to processes opening it by name, e.g. through
+    /// [`Client::open_or_create`]. Windows closes all handles of a process
+    /// once it exits though, so the semaphore only survives this process if
+    /// other processes, like children configured with [`Client::configure`],
+    /// still hold handles to it. [`Client::close`] closes the handle anyway.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` contains a nul byte or if any I/O error
+    /// happens when creating the semaphore. An error of the kind
+    /// [`io::ErrorKind::AlreadyExists`] is returned if a semaphore with this
+    /// name exists already.
+    #[cfg(windows)]
+    pub fn new_detached(limit: usize, name: &str) -> io::Result<Client> {
+        let imp = imp::Client::new_detached(limit, name)?;
+        Ok(Client {
+            inner: Arc::new(ClientInner::with_limit(imp, limit)),
+        })
+    }
+
+    /// Sets whether the semaphore handle of this client is inherited by
+    /// child processes.
+    ///
+    /// Children spawned through [`Client::configure`] open the semaphore by
+    /// name, so they don't need the handle to be inherited. This is about
+    /// processes spawned through other means which control handle
+    /// inheritance explicitly, or which rely on inherited handles.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `SetHandleInformation` fails, or with
+    /// [`io::ErrorKind::Unsupported`] for clients created through
+    /// [`Client::new_unlimited`], which have no semaphore.
+    #[cfg(windows)]
+    pub fn set_inherit(&self, inherit: bool) -> io::Result<()> {
+        self.windows_imp()?.set_inherit(inherit)
+    }
+
+    /// Returns whether the semaphore handle of this client is inherited by
+    /// child processes, see [`Client::set_inherit`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `GetHandleInformation` fails, or with
+    /// [`io::ErrorKind::Unsupported`] for clients created through
+    /// [`Client::new_unlimited`], which have no semaphore.
+    #[cfg(windows)]
+    pub fn is_inheritable(&self) -> io::Result<bool> {
+        self.windows_imp()?.is_inheritable()
+    }
+
+    #[cfg(windows)]
+    fn windows_imp(&self) -> io::Result<&imp::Client> {
+        self.inner.imp.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unlimited clients have no semaphore",
+            )
+        })
+    }
+
+    /// Sets whether the file descriptors of this jobserver are closed when
+    /// this process execs.
+    ///
+    /// Jobserver file descriptors are close-on-exec by default so that
+    /// unrelated programs don't inherit them by accident, and
+    /// [`Client::configure`] only clears the flag within the child it
+    /// configures. This instead toggles the flag directly through
+    /// `fcntl(F_SETFD)`, for processes spawned without [`Command`], e.g.
+    /// through a raw `fork` and `exec`. Remember to set it again afterwards
+    /// if other children shouldn't inherit the jobserver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fcntl` fails, or with
+    /// [`io::ErrorKind::Unsupported`] for clients created through
+    /// [`Client::new_unlimited`], which have no file descriptors.
+    #[cfg(unix)]
+    pub fn set_close_on_exec(&self, close: bool) -> io::Result<()> {
+        match &self.inner.imp {
+            Some(imp) => imp.set_close_on_exec(close),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unlimited clients have no file descriptors",
+            )),
+        }
+    }
+
+    /// Allows the jobserver's file descriptors to be inherited by the program
+    /// this process is about to exec.
+    ///
+    /// This is [`Client::configure`] for direct calls of `execve` and
+    /// friends, without a [`Command`]: the close-on-exec flag is cleared on
+    /// the file descriptors right away, in this process. The environment
+    /// variables telling the new program about the jobserver still need to
+    /// be passed to `execve`. If the exec fails,
+    /// [`Client::unconfigure_on_unix_exec`] restores the flag. This is the
+    /// same as [`Client::set_close_on_exec`] with `false`, except that it
+    /// does nothing for clients created through [`Client::new_unlimited`].
+    ///
+    /// Note that until the exec happens or the flag is restored, any process
+    /// spawned by any thread of this process inherits the jobserver as well,
+    /// and may then hold on to its file descriptors. To avoid this, call this
+    /// right before `execve` in a single-threaded process or a freshly forked
+    /// child.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fcntl` fails.
+    #[cfg(unix)]
+    pub fn configure_on_unix_exec(&self) -> io::Result<()> {
+        match &self.inner.imp {
+            Some(imp) => imp.set_close_on_exec(false),
+            None => Ok(()),
+        }
+    }
+
+    /// Restores the close-on-exec flag on the jobserver's file descriptors
+    /// after [`Client::configure_on_unix_exec`], e.g. if the exec failed.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fcntl` fails.
+    #[cfg(unix)]
+    pub fn unconfigure_on_unix_exec(&self) -> io::Result<()> {
+        match &self.inner.imp {
+            Some(imp) => imp.set_close_on_exec(true),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets how often blocking acquisitions are retried when interrupted by a
+    /// signal.
+    ///
+    /// Reading a token from the jobserver pipe fails with `EINTR` if a signal
+    /// is delivered meanwhile. [`Client::acquire`] and friends then retry up
+    /// to `max` times before returning an [`io::ErrorKind::Interrupted`]
+    /// error, which allows signal handlers to interrupt a blocked
+    /// acquisition. `max = 0` returns the error on the first interruption,
+    /// whereas `max = usize::MAX` retries forever, like `SA_RESTART` does.
+    /// The default is `usize::MAX`, so acquisitions are only interrupted
+    /// after opting in.
+    ///
+    /// This setting is shared by all clones of this client.
+    #[cfg(unix)]
+    pub fn set_eintr_retries(&self, max: usize) {
+        self.inner.eintr_retries.store(max, Ordering::Relaxed);
+    }
+
+    /// Builder-style variant of [`Client::set_eintr_retries`].
+    #[cfg(unix)]
+    pub fn with_eintr_retries(self, max: usize) -> Client {
+        self.set_eintr_retries(max);
+        self
+    }
+
+    /// Same as [`Client::acquire`], also returning the ordinal of this
+    /// acquisition.
+    ///
+    /// Every successful acquisition of a token from the jobserver through
+    /// this client or any of its clones increments a counter, and the value
+    /// after incrementing it for this acquisition is returned, so the first
+    /// acquisition returns 1. This is intended for debugging, e.g. for
+    /// tracking down leaked tokens. The counter wraps around on overflow.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Client::acquire`], in which case the counter is unchanged.
+    pub fn acquire_counted(&self) -> io::Result<(Acquired, usize)> {
+        let (data, count) = self.inner.acquire_counted()?;
+        let token = Acquired {
+            client: self.inner.clone(),
+            data,
+            disabled: false,
+            soft: false,
+            acquired_at: self.inner.observe_start(),
+        };
+        Ok((token, count))
+    }
+
+    /// Returns the number of tokens acquired so far through this client and
+    /// its clones, see [`Client::acquire_counted`].
+    pub fn acquisition_count(&self) -> usize {
+        self.inner.acquisitions.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of tokens available in this jobserver, as far as
+    /// this process knows, without any system calls.
+    ///
+    /// This is the number of tokens the jobserver was created with minus the
+    /// number of tokens currently held through this client and its clones.
+    /// Only acquisitions and releases within this process are counted, so
+    /// this is only exact if no other process acquires tokens from the same
+    /// jobserver, e.g. for a build system running all jobs in-process. Use
+    /// [`Client::available`] to ask the OS instead.
+    ///
+    /// Returns `None` if the number of tokens in the jobserver isn't known,
+    /// as is the case for inherited jobservers. Clients created through
+    /// [`Client::new_unlimited`] return [`usize::MAX`].
+    pub fn acquire_nonblocking_count(&self) -> Option<usize> {
+        if self.is_unlimited() {
+            return Some(usize::MAX);
+        }
+        let capacity = (*self.inner.limit.lock().unwrap_or_else(|e| e.into_inner()))?;
+        let held = self.inner.held.load(Ordering::Relaxed);
+        Some(if held >= 0 {
+            capacity.saturating_sub(held as usize)
+        } else {
+            capacity.saturating_add(held.unsigned_abs())
         })
     }
 
-    /// Acquires a token from this jobserver client in a non-blocking way.
-    ///
-    /// # Return value
+    /// Returns the number of threads currently blocked in
+    /// [`Client::acquire`] on this client and its clones.
+    ///
+    /// This is a simple measure of contention for tokens within this
+    /// process. Threads waiting in other processes aren't counted.
+    pub fn pending_acquires(&self) -> usize {
+        self.inner.inflight_acquires.load(Ordering::SeqCst)
+    }
+
+    /// Returns the highest value of [`Client::pending_acquires`] so far,
+    /// e.g. for alerting on contention after the fact.
+    pub fn max_pending_acquires(&self) -> usize {
+        self.inner.max_inflight_acquires.load(Ordering::Relaxed)
+    }
+
+    /// Acquires a token from this jobserver client in a non-blocking way.
+    ///
+    /// # Return value
+    ///
+    /// On successful acquisition of a token an instance of [`Acquired`] is
+    /// returned. This structure, when dropped, will release the token back to
+    /// the jobserver. It's recommended to avoid leaking this value.
+    ///
+    /// # Errors
+    ///
+    /// If an I/O error happens while acquiring a token then this function will
+    /// return immediately with the error. If an error is returned then a token
+    /// was not acquired.
+    ///
+    /// If non-blocking acquire is not supported, the return error will have its `kind()`
+    /// set to [`io::ErrorKind::Unsupported`].
+    pub fn try_acquire(&self) -> io::Result<Option<Acquired>> {
+        let ret = self.inner.try_acquire()?;
+
+        Ok(ret.map(|data| Acquired {
+            client: self.inner.clone(),
+            data,
+            disabled: false,
+            soft: false,
+            acquired_at: self.inner.observe_start(),
+        }))
+    }
+
+    /// Acquires either exactly `n` tokens or none at all, without blocking.
+    ///
+    /// Tokens are acquired one by one through [`Client::try_acquire`], and
+    /// if any of them isn't available, the ones acquired so far are released
+    /// again and an empty vector is returned.
+    ///
+    /// Concurrent calls of this function on clones of this client are
+    /// serialized, so two of them never end up splitting the available tokens
+    /// between themselves with neither getting `n`. This is a best-effort,
+    /// in-process guarantee only: other processes, as well as other ways of
+    /// acquiring tokens in this process, may still take tokens
+    /// in between, leading to a spurious empty result.
+    ///
+    /// # Errors
+    ///
+    /// If an I/O error happens while acquiring a token then the tokens
+    /// acquired so far are released and the error is returned.
+    pub fn try_acquire_many_nonblocking(&self, n: usize) -> io::Result<Vec<Acquired>> {
+        let _lock = self
+            .inner
+            .multi_acquire
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut tokens = Vec::with_capacity(n);
+        while tokens.len() < n {
+            match self.try_acquire()? {
+                Some(token) => tokens.push(token),
+                None => return Ok(Vec::new()),
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Acquires all tokens currently available in the jobserver, without
+    /// blocking.
+    ///
+    /// This zeroes out the available tokens, e.g. once a build phase is
+    /// complete and the jobserver is about to be reconfigured. The returned
+    /// [`DrainedClient`] records how many tokens were taken and releases them
+    /// again once dropped or through [`DrainedClient::release_all`].
+    ///
+    /// # Errors
+    ///
+    /// If an I/O error happens while acquiring a token then the tokens
+    /// acquired so far are released and the error is returned. This includes
+    /// [`io::ErrorKind::Unsupported`] on platforms where
+    /// [`Client::try_acquire`] isn't supported. Clients created through
+    /// [`Client::new_unlimited`] can't be drained either and return an error
+    /// of the same kind.
+    pub fn take_snapshot_and_drain(&self) -> io::Result<DrainedClient> {
+        if self.is_unlimited() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot drain an unlimited jobserver",
+            ));
+        }
+        let mut tokens = Vec::new();
+        while let Some(token) = self.try_acquire()? {
+            tokens.push(token);
+        }
+        Ok(DrainedClient::new(self.clone(), tokens))
+    }
+
+    /// Shrinks the tokens available in the jobserver down to `n`, without
+    /// blocking.
+    ///
+    /// The tokens available beyond `n` are acquired all at once through
+    /// [`Client::try_acquire_many_nonblocking`] and held permanently, which
+    /// helps e.g. memory-constrained build systems reduce their parallelism.
+    /// Returns `true` if the jobserver was shrunk or already had at most `n`
+    /// tokens available, and `false` if the tokens couldn't be acquired
+    /// because other processes took some meanwhile, in which case calling
+    /// this again later may succeed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the available tokens can't be determined, as with
+    /// [`Client::available`], or if an I/O error happens while acquiring
+    /// them. Clients created through [`Client::new_unlimited`] can't be
+    /// shrunk and return an error of the kind
+    /// [`io::ErrorKind::Unsupported`].
+    pub fn try_downgrade_to_token_count(&self, n: usize) -> io::Result<bool> {
+        if self.is_unlimited() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot shrink an unlimited jobserver",
+            ));
+        }
+        let excess = self.available()?.saturating_sub(n);
+        if excess == 0 {
+            return Ok(true);
+        }
+        let tokens = self.try_acquire_many_nonblocking(excess)?;
+        if tokens.is_empty() {
+            return Ok(false);
+        }
+        for token in tokens {
+            token.drop_without_releasing();
+        }
+        self.inner.adjust_limit(excess, 0);
+        Ok(true)
+    }
+
+    /// Returns the file descriptor tokens are read from, which becomes
+    /// readable whenever a token may be available.
+    ///
+    /// This allows waiting for tokens with generic readiness APIs, for example
+    /// `poll` or an event loop. Note that the file descriptor may be shared
+    /// with other processes and shouldn't be used for anything else than
+    /// reading tokens.
+    ///
+    /// Returns `None` for clients created through [`Client::new_unlimited`],
+    /// which don't have a file descriptor.
+    #[cfg(unix)]
+    pub fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.inner.imp.as_ref().map(|imp| imp.as_fd())
+    }
+
+    /// Same as [`Client::as_fd`], returning the raw file descriptor.
+    #[cfg(unix)]
+    pub fn as_raw_fd(&self) -> Option<RawFd> {
+        self.as_fd().map(|fd| fd.as_raw_fd())
+    }
+
+    /// Registers the jobserver with a [`mio`] event loop.
+    ///
+    /// Events for `token` are then delivered once a token may be available,
+    /// upon which it should be acquired through [`Client::complete_acquire`].
+    /// Use [`mio::Registry::deregister`] with this client's file descriptor,
+    /// from [`Client::as_raw_fd`], to stop receiving events.
+    ///
+    /// This requires the `mio` feature to be enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registration fails, or an error of the kind
+    /// [`io::ErrorKind::Unsupported`] for clients created through
+    /// [`Client::new_unlimited`], which have no file descriptor to register.
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn register_with_mio(&self, registry: &mio::Registry, token: mio::Token) -> io::Result<()> {
+        let fd = self.as_raw_fd().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unlimited clients have no file descriptor",
+            )
+        })?;
+        registry.register(
+            &mut mio::unix::SourceFd(&fd),
+            token,
+            mio::Interest::READABLE,
+        )
+    }
+
+    /// Acquires a token after `event` reported the jobserver registered
+    /// through [`Client::register_with_mio`] as readable.
+    ///
+    /// Returns `Ok(None)` if `event` isn't a read event or if the token was
+    /// taken by someone else in the meantime. As `mio` readiness events
+    /// are edge-triggered, the jobserver should be considered readable until
+    /// this function returns `Ok(None)`.
+    ///
+    /// This requires the `mio` feature to be enabled.
+    ///
+    /// # Errors
+    ///
+    /// Same as for [`Client::try_acquire`], which is used to acquire the
+    /// token, so this may fail with [`io::ErrorKind::Unsupported`] on
+    /// platforms where the jobserver can't be read without blocking.
+    #[cfg(all(unix, feature = "mio"))]
+    pub fn complete_acquire(&self, event: &mio::event::Event) -> io::Result<Option<Acquired>> {
+        if !event.is_readable() && !event.is_read_closed() {
+            return Ok(None);
+        }
+        self.try_acquire()
+    }
+
+    /// Attempts to acquire a token, registering the current task for wakeup
+    /// if none is available.
+    ///
+    /// This is the building block for implementing a [`Future`] acquiring a
+    /// token, without depending on any particular async runtime: it returns
+    /// [`Poll::Pending`] if no token is available right now, in which case
+    /// the waker of `cx` will be woken once acquiring it should be attempted
+    /// again.
+    ///
+    /// Waiting happens on a background thread waiting for the jobserver to
+    /// become readable, of which there's at most one per client at a time.
+    /// That thread doesn't acquire any tokens itself, the woken tasks do so
+    /// when calling this function again, so tasks may stop polling at any
+    /// time without losing tokens. It doesn't keep the client alive either,
+    /// exiting within a fraction of a second after the last clone of this
+    /// client was dropped.
+    ///
+    /// [`Future`]: std::future::Future
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if acquiring a token fails or if the background
+    /// thread couldn't be spawned. Tokens are acquired through
+    /// [`Client::try_acquire`], so this fails with
+    /// [`io::ErrorKind::Unsupported`] on platforms where the jobserver can't
+    /// be read without blocking.
+    pub fn acquire_with_context(&self, cx: &mut Context<'_>) -> Poll<io::Result<Acquired>> {
+        context::poll_acquire(&self.inner, cx).map_ok(|data| Acquired {
+            client: self.inner.clone(),
+            data,
+            disabled: false,
+            soft: false,
+            acquired_at: self.inner.observe_start(),
+        })
+    }
+
+    /// Returns a stream acquiring `n` tokens one after another.
+    ///
+    /// Each token is awaited like with [`Client::acquire_with_context`], so
+    /// this works with any async runtime. The stream ends after yielding
+    /// exactly `n` items, so collecting it waits for all `n` tokens.
+    ///
+    /// This function requires the `futures` feature to be enabled.
+    ///
+    /// # Errors
+    ///
+    /// Errors from acquiring a token are yielded from the stream, which
+    /// still counts them towards the `n` items.
+    #[cfg(feature = "futures")]
+    pub fn acquire_batch_async(
+        &self,
+        n: usize,
+    ) -> impl futures_core::Stream<Item = io::Result<Acquired>> + Send + Unpin {
+        context::AcquireBatch {
+            client: self.clone(),
+            remaining: n,
+        }
+    }
+
+    /// Acquires a token on a Tokio blocking thread and runs `f` with it.
+    ///
+    /// Calling [`Client::acquire`] from an async context blocks the executor,
+    /// so this instead spawns a task with [`tokio::task::spawn_blocking`]
+    /// which acquires a token and then calls `f` with it. The token is
+    /// released once `f` drops the provided [`Acquired`], typically when it
+    /// returns.
+    ///
+    /// This function requires the `tokio` feature to be enabled, and the
+    /// returned future must be polled from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// If an I/O error happens while acquiring a token then `f` isn't called
+    /// and the error is returned. An error is also returned if the blocking
+    /// task is cancelled because the runtime is shutting down. If `f` panics
+    /// the panic is propagated to the caller.
+    #[cfg(feature = "tokio")]
+    pub fn acquire_or_spawn_blocking<F, R>(&self, f: F) -> impl Future<Output = io::Result<R>>
+    where
+        F: FnOnce(Acquired) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let client = self.clone();
+        async move {
+            match tokio::task::spawn_blocking(move || client.acquire().map(f)).await {
+                Ok(ret) => ret,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+    }
+
+    /// Acquires a token without blocking the async executor.
+    ///
+    /// On Unix the jobserver's read end is registered with Tokio's reactor
+    /// and tokens are taken with [`Client::try_acquire`] once it becomes
+    /// readable. This is cancel safe: dropping the future before it resolves
+    /// deregisters the file descriptor without taking a token. Elsewhere, and
+    /// on Unix platforms where [`Client::try_acquire`] isn't supported for
+    /// the jobserver, the token is acquired on a Tokio blocking thread like
+    /// in [`Client::acquire_or_spawn_blocking`]. Dropping the future then
+    /// only releases the token once the blocking acquisition finished.
+    ///
+    /// This function requires the `tokio` feature to be enabled, and the
+    /// returned future must be polled from within a Tokio runtime with I/O
+    /// enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an I/O error happens while acquiring a token or
+    /// registering with the reactor, or if the blocking task is cancelled
+    /// because the runtime is shutting down.
+    #[cfg(feature = "tokio")]
+    pub fn acquire_async_tokio(&self) -> impl Future<Output = io::Result<Acquired>> + Send {
+        let client = self.clone();
+        async move {
+            #[cfg(unix)]
+            match client.try_acquire() {
+                Ok(Some(token)) => return Ok(token),
+                Ok(None) => {
+                    // Unlimited clients always have a token available.
+                    let fd = client.inner.imp.as_ref().unwrap().async_fd()?;
+                    loop {
+                        let mut guard = fd.readable().await?;
+                        match client.try_acquire()? {
+                            Some(token) => return Ok(token),
+                            None => guard.clear_ready(),
+                        }
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+                Err(e) => return Err(e),
+            }
+            match tokio::task::spawn_blocking(move || client.acquire()).await {
+                Ok(ret) => ret,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+    }
+
+    /// Acquires a token while running the calling thread with the real-time
+    /// `SCHED_FIFO` policy at `priority`.
+    ///
+    /// This is a userspace approximation of priority inheritance: a
+    /// high-priority task waiting for a token is scheduled ahead of the
+    /// low-priority tasks holding them, so that it gets to run as soon as a
+    /// token is released. The previous scheduling policy and priority of
+    /// the thread are restored once this returns.
+    ///
+    /// Raising the priority requires the `CAP_SYS_NICE` capability or a
+    /// sufficient `RLIMIT_RTPRIO`, e.g. through `ulimit -r`. This function
+    /// requires the `priority-inheritance` feature to be enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::InvalidInput`] if
+    /// `priority` is outside the range supported for `SCHED_FIFO`, usually
+    /// 1 to 99, or [`io::ErrorKind::PermissionDenied`] if the thread may not
+    /// raise its priority, in which case no token is acquired. Errors from
+    /// [`Client::acquire`] are propagated.
+    #[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+    pub fn acquire_with_priority_inheritance(&self, priority: u8) -> io::Result<Acquired> {
+        let _raised = imp::RaisedPriority::new(priority)?;
+        self.acquire()
+    }
+
+    /// Acquires a token by repeatedly polling [`Client::try_acquire`] instead
+    /// of blocking in the OS.
+    ///
+    /// This is the same as [`Client::acquire_yielding_with`] with 100 spins
+    /// and a sleep of 1 millisecond afterwards.
+    pub fn acquire_yielding(&self) -> io::Result<Acquired> {
+        self.acquire_yielding_with(100, Duration::from_millis(1))
+    }
+
+    /// Acquires a token by repeatedly polling [`Client::try_acquire`] instead
+    /// of blocking in the OS.
+    ///
+    /// Between attempts the calling thread yields with
+    /// [`std::thread::yield_now`], giving other ready threads a chance to run.
+    /// After `max_spins` failed attempts it instead sleeps for `sleep` between
+    /// attempts. This is a pragmatic middle ground between a blocking call and
+    /// a true async implementation, for example for cooperative runtimes.
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::try_acquire`] are propagated, except when
+    /// non-blocking acquisition isn't supported in which case this falls back
+    /// to [`Client::acquire`].
+    pub fn acquire_yielding_with(&self, max_spins: usize, sleep: Duration) -> io::Result<Acquired> {
+        match self.poll_acquire_while(max_spins, sleep, || true) {
+            Ok(token) => Ok(token.expect("polling never gives up")),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => self.acquire(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Polls [`Client::try_acquire`] like [`Client::acquire_yielding_with`],
+    /// giving up with `Ok(None)` once `keep_going` returns `false` after a
+    /// failed attempt.
+    ///
+    /// All errors are propagated, including [`io::ErrorKind::Unsupported`].
+    fn poll_acquire_while(
+        &self,
+        max_spins: usize,
+        sleep: Duration,
+        mut keep_going: impl FnMut() -> bool,
+    ) -> io::Result<Option<Acquired>> {
+        let mut spins = 0;
+        loop {
+            if let Some(token) = self.try_acquire()? {
+                return Ok(Some(token));
+            }
+            if !keep_going() {
+                return Ok(None);
+            }
+            if spins < max_spins {
+                spins += 1;
+                thread::yield_now();
+            } else {
+                thread::sleep(sleep);
+            }
+        }
+    }
+
+    /// Acquires a token by repeatedly polling [`Client::try_acquire`], giving
+    /// up after `timeout`.
+    ///
+    /// Between attempts the calling thread sleeps as determined by `backoff`,
+    /// instead of blocking in the OS for the whole `timeout`. This lets other
+    /// threads make progress in the meantime and is the recommended strategy
+    /// for high-contention environments. The last sleep is cut short so that
+    /// a final attempt happens right at the deadline.
+    ///
+    /// Returns `Ok(None)` if no token could be acquired within `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::try_acquire`] are propagated, including
+    /// [`io::ErrorKind::Unsupported`] on platforms without non-blocking
+    /// acquisition.
+    pub fn acquire_with_timeout_and_backoff(
+        &self,
+        timeout: Duration,
+        backoff: BackoffStrategy,
+    ) -> io::Result<Option<Acquired>> {
+        let deadline = Instant::now() + timeout;
+        let mut attempt = 0;
+        loop {
+            if let Some(token) = self.try_acquire()? {
+                return Ok(Some(token));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(backoff.delay(attempt).min(deadline - now));
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Moves `count` tokens from this jobserver to `other`.
+    ///
+    /// This acquires `count` tokens from this jobserver, blocking as needed,
+    /// and then releases that many tokens to `other` instead, permanently
+    /// shrinking this jobserver and growing `other`. This is the building
+    /// block for balancing load between independent jobservers.
+    ///
+    /// If acquiring fails partway through, the tokens acquired so far are
+    /// released back to this jobserver. Other users of this process see
+    /// either none or all of the tokens moved, but there's no such guarantee
+    /// for other processes.
+    ///
+    /// Note that this blocks forever if this jobserver never has `count`
+    /// tokens available at once, see [`Client::transfer_timeout`] for a
+    /// variant giving up eventually.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if acquiring a token from this jobserver or
+    /// releasing one to `other` fails. In the latter case tokens which
+    /// weren't moved yet are released back to this jobserver.
+    pub fn transfer(&self, other: &Client, count: usize) -> io::Result<()> {
+        let tokens = (0..count)
+            .map(|_| self.acquire())
+            .collect::<io::Result<Vec<_>>>()?;
+        self.hand_over(other, tokens)
+    }
+
+    /// Same as [`Client::transfer`], except that this gives up once `timeout`
+    /// has elapsed without acquiring all `count` tokens.
+    ///
+    /// Returns `false` if it timed out, in which case the tokens acquired so
+    /// far are released back to this jobserver and nothing is moved. Tokens
+    /// are polled through [`Client::try_acquire`], like in
+    /// [`Client::acquire_yielding`].
+    ///
+    /// # Errors
+    ///
+    /// Same as for [`Client::transfer`] and [`Client::try_acquire`], including
+    /// [`io::ErrorKind::Unsupported`] on platforms where tokens can't be
+    /// acquired without blocking.
+    pub fn transfer_timeout(
+        &self,
+        other: &Client,
+        count: usize,
+        timeout: Duration,
+    ) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        let mut tokens = Vec::with_capacity(count);
+        while tokens.len() < count {
+            let before_deadline = || Instant::now() < deadline;
+            match self.poll_acquire_while(100, Duration::from_millis(1), before_deadline)? {
+                Some(token) => tokens.push(token),
+                None => return Ok(false),
+            }
+        }
+        self.hand_over(other, tokens)?;
+        Ok(true)
+    }
+
+    fn hand_over(&self, other: &Client, tokens: Vec<Acquired>) -> io::Result<()> {
+        // On errors the remaining tokens are dropped and thus released back
+        // to us.
+        for token in tokens {
+            other.inner.release(None)?;
+            token.drop_without_releasing();
+            self.inner.adjust_limit(1, 0);
+            other.inner.adjust_limit(0, 1);
+        }
+        Ok(())
+    }
+
+    /// Processes `items` one after another, each while holding a token.
+    ///
+    /// For every item a token is acquired, blocking until one is available,
+    /// then `worker` is called with the item and the token is released
+    /// again. The token is released even if `worker` panics. Since this runs
+    /// `worker` on the calling thread, at most one item is in flight per
+    /// call; spawn threads calling this to process items in parallel, with
+    /// the jobserver bounding how many of them run at once.
+    ///
+    /// # Errors
+    ///
+    /// If acquiring a token fails the error is returned, leaving the
+    /// remaining items unprocessed.
+    pub fn batch_acquire_release<I, F>(&self, items: I, mut worker: F) -> io::Result<()>
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item),
+    {
+        for item in items {
+            let _token = self.acquire()?;
+            worker(item);
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on each of `items` in parallel, each while holding a token.
+    ///
+    /// At most `max_parallelism` threads are spawned, and no more than the
+    /// tokens currently available if that's known. Each thread repeatedly
+    /// takes the next item, acquires a token, calls `f` with the item and
+    /// releases the token again, so the number of concurrent calls of `f` is
+    /// bounded by both the jobserver and `max_parallelism`. All threads are
+    /// joined before this returns.
+    ///
+    /// # Errors
+    ///
+    /// If acquiring a token or `f` fails, no further items are started and
+    /// the first error is returned once all threads have finished. An error
+    /// of the kind [`io::ErrorKind::InvalidInput`] is returned if
+    /// `max_parallelism` is 0.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the panic is propagated once all threads have finished.
+    pub fn for_each_parallel<I, F>(&self, items: I, max_parallelism: usize, f: F) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::IntoIter: Send,
+        I::Item: Send,
+        F: Fn(I::Item) -> io::Result<()> + Sync,
+    {
+        if max_parallelism == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_parallelism must be greater than 0",
+            ));
+        }
+        let threads = match self.available() {
+            Ok(available) => max_parallelism.min(available.max(1)),
+            Err(_) => max_parallelism,
+        };
+        let items = Mutex::new(items.into_iter());
+        let error = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    if error.lock().unwrap_or_else(|e| e.into_inner()).is_some() {
+                        break;
+                    }
+                    let item = match items.lock().unwrap_or_else(|e| e.into_inner()).next() {
+                        Some(item) => item,
+                        None => break,
+                    };
+                    if let Err(e) = self.acquire().and_then(|_token| f(item)) {
+                        error
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .get_or_insert(e);
+                    }
+                });
+            }
+        });
+        match error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Acquires a token like [`Client::acquire`], labeling it with
+    /// `span_name`.
+    ///
+    /// The returned [`SpannedAcquired`] records the span name, the acquiring
+    /// thread and the time of acquisition, and logs how long the token was
+    /// held once released if the `log` feature is enabled. This helps
+    /// finding out which code holds tokens for too long.
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::acquire`] are propagated.
+    pub fn acquire_with_span(&self, span_name: &'static str) -> io::Result<SpannedAcquired> {
+        Ok(SpannedAcquired::new(self.acquire()?, span_name))
+    }
+
+    /// Same as [`Client::acquire`], except that a suspected deadlock is
+    /// reported to stderr while waiting for a token for long.
+    ///
+    /// Every 30 seconds while the calling thread is blocked, a message with
+    /// a "Jobserver deadlock suspected" header and its backtrace is printed,
+    /// which helps tracking down threads waiting forever for a token held by
+    /// another thread. The interval can be changed by setting the
+    /// `JOBSERVER_DEBUG_TIMEOUT` environment variable to a number of
+    /// seconds. The backtrace is captured before blocking, regardless of
+    /// `RUST_BACKTRACE`, and printed from a watchdog thread spawned for the
+    /// duration of the call, so this is only meant for debugging.
+    ///
+    /// This function requires the `debug-backtraces` feature to be enabled,
+    /// which needs Rust 1.65 or later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the watchdog thread couldn't be spawned. Errors
+    /// from [`Client::acquire`] are propagated.
+    #[cfg(feature = "debug-backtraces")]
+    pub fn debug_acquire(&self) -> io::Result<Acquired> {
+        debug_acquire::acquire(self)
+    }
+
+    /// Acquires a token labeled with `name`, recording where, when and by
+    /// which thread it was acquired.
+    ///
+    /// This is meant for debugging builds slowed down by starvation for
+    /// tokens: printing the returned [`InstrumentedAcquired`] with `{:?}`
+    /// shows who holds it and since when, and with the `log` feature
+    /// enabled tokens held for suspiciously long are logged as warnings on
+    /// release. The source location is that of the caller of this function.
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::acquire`] are propagated.
+    #[track_caller]
+    pub fn acquire_instrumented(&self, name: &'static str) -> io::Result<InstrumentedAcquired> {
+        let location = std::panic::Location::caller();
+        Ok(InstrumentedAcquired::new(self.acquire()?, name, location))
+    }
+
+    /// Acquires a token, runs `f` and releases the token again, returning the
+    /// result of `f`.
+    ///
+    /// This is the simplest way to limit the parallelism of some work: the
+    /// token isn't exposed to `f` at all. It's released once `f` returns, or
+    /// while unwinding if `f` panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    ///
+    /// let client = Client::new(1).unwrap();
+    /// let sum = client.acquire_and_run(|| 1 + 1).unwrap();
+    /// assert_eq!(sum, 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::acquire`] are propagated, in which case `f`
+    /// isn't called.
+    pub fn acquire_and_run<F, R>(&self, f: F) -> io::Result<R>
+    where
+        F: FnOnce() -> R,
+    {
+        let _token = self.acquire()?;
+        Ok(f())
+    }
+
+    /// Runs `f` like [`Client::acquire_and_run`] if a token is available
+    /// right away, without blocking.
+    ///
+    /// Returns `None` without calling `f` if no token is available, for
+    /// opportunistic parallelism where the work has a natural alternative,
+    /// such as batching instead of splitting it up. `Some(Err(_))` is
+    /// returned if [`Client::try_acquire`] fails.
+    pub fn try_acquire_and_run<F, R>(&self, f: F) -> Option<io::Result<R>>
+    where
+        F: FnOnce() -> R,
+    {
+        match self.try_acquire() {
+            Ok(Some(_token)) => Some(Ok(f())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Acquires a token like [`Client::acquire`], calling `pre` right before
+    /// and `post` with the time spent waiting right after.
+    ///
+    /// Unlike [`Client::hook_pre_acquire`] and [`Client::hook_post_acquire`]
+    /// these hooks only apply to this acquisition and aren't stored in the
+    /// client, which allows instrumenting specific call sites. `post` is only
+    /// called if a token was acquired.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    ///
+    /// let client = Client::new(1).unwrap();
+    /// let token = client
+    ///     .acquire_with_hook(|| println!("waiting"), |d| println!("waited {d:?}"))
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::acquire`] are propagated.
+    pub fn acquire_with_hook<Pre, Post>(&self, pre: Pre, post: Post) -> io::Result<Acquired>
+    where
+        Pre: FnOnce(),
+        Post: FnOnce(Duration),
+    {
+        pre();
+        let start = Instant::now();
+        let token = self.acquire()?;
+        post(start.elapsed());
+        Ok(token)
+    }
+
+    /// Acquires a token like [`Client::acquire`], then signals `cv`.
+    ///
+    /// Once the token was acquired, the flag in `cv` is set to `true` and one
+    /// thread waiting on the condition variable is notified. This allows
+    /// another thread to wait for the acquisition to complete without
+    /// polling, using nothing but `std`. The flag isn't reset by this
+    /// function, that's up to the waiting thread.
+    ///
+    /// # Errors
+    ///
+    /// If acquiring the token fails the error is returned and `cv` isn't
+    /// signaled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    /// use std::sync::{Arc, Condvar, Mutex};
+    /// use std::thread;
+    ///
+    /// let client = Client::new(1).unwrap();
+    /// let cv = Arc::new((Mutex::new(false), Condvar::new()));
+    /// let cv2 = cv.clone();
+    /// let acquirer = thread::spawn(move || client.acquire_notifying(cv2).unwrap());
+    ///
+    /// let (lock, cvar) = &*cv;
+    /// let _acquired = cvar.wait_while(lock.lock().unwrap(), |done| !*done).unwrap();
+    /// drop(acquirer.join().unwrap());
+    /// ```
+    pub fn acquire_notifying(&self, cv: Arc<(Mutex<bool>, Condvar)>) -> io::Result<Acquired> {
+        let token = self.acquire()?;
+        let (lock, cvar) = &*cv;
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        cvar.notify_one();
+        Ok(token)
+    }
+
+    /// Acquires a token as long as `predicate` returns `true`.
+    ///
+    /// `predicate` is evaluated before each attempt to acquire a token, and
+    /// as soon as it returns `false` this gives up and returns `Ok(None)`.
+    /// This allows cancelling the wait for a token from another thread, e.g.
+    /// by setting a flag once there's no work left, without a race between
+    /// checking the flag and acquiring the token. Attempts are made like in
+    /// [`Client::acquire_yielding`].
+    ///
+    /// # Errors
+    ///
+    /// Errors from [`Client::try_acquire`] are propagated, except when
+    /// non-blocking acquisition isn't supported in which case this falls back
+    /// to [`Client::acquire`], after which `predicate` isn't evaluated
+    /// anymore.
+    pub fn conditional_acquire<P: Fn() -> bool>(
+        &self,
+        predicate: P,
+    ) -> io::Result<Option<Acquired>> {
+        if !predicate() {
+            return Ok(None);
+        }
+        match self.poll_acquire_while(100, Duration::from_millis(1), predicate) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => self.acquire().map(Some),
+            result => result,
+        }
+    }
+
+    /// Sets the number of soft tokens available through
+    /// [`Client::soft_acquire`].
+    ///
+    /// Soft tokens are tracked purely in-process, on top of the tokens of the
+    /// jobserver itself. They're meant for trusted callers within this process
+    /// for which the cost of a round-trip to the jobserver is unacceptable.
+    /// Setting a limit lower than the number of soft tokens currently held
+    /// doesn't revoke them, but no more are handed out until enough are
+    /// released. The default limit is zero.
+    ///
+    /// The limit is shared by all clones of this [`Client`].
+    pub fn soft_limit(&self, n: usize) {
+        self.inner.soft_limit.store(n, Ordering::SeqCst);
+    }
+
+    /// Acquires a soft token if one is available, otherwise falls back to
+    /// acquiring a token from the jobserver.
+    ///
+    /// See [`Client::soft_limit`] for more info. Soft tokens are returned to
+    /// the soft budget, not the jobserver, when the [`Acquired`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Soft acquisitions never fail, otherwise errors from
+    /// [`Client::acquire`] are propagated.
+    pub fn soft_acquire(&self) -> io::Result<Acquired> {
+        let limit = self.inner.soft_limit.load(Ordering::SeqCst);
+        let soft = self
+            .inner
+            .soft_held
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |held| {
+                if held < limit {
+                    Some(held + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok();
+        if !soft {
+            return self.acquire();
+        }
+        Ok(Acquired {
+            client: self.inner.clone(),
+            data: imp::Acquired::from_raw(0),
+            disabled: false,
+            soft: true,
+            acquired_at: self.inner.observe_start(),
+        })
+    }
+
+    /// Returns the number of soft tokens currently held, see
+    /// [`Client::soft_limit`].
+    pub fn soft_held(&self) -> usize {
+        self.inner.soft_held.load(Ordering::SeqCst)
+    }
+
+    /// Blocks the current thread until another thread of this process hands
+    /// over a token through [`Client::unpark_one`].
+    ///
+    /// This never touches the underlying jobserver, making it a cheap way of
+    /// passing tokens between threads of the same process: one thread acting
+    /// as a dispenser acquires tokens and hands them over, while the other
+    /// threads wait here. The returned token is released back to the
+    /// jobserver when dropped as usual.
+    ///
+    /// Note that tokens released back to the jobserver, or acquired by other
+    /// means, never wake up threads blocked here.
+    pub fn acquire_park(&self) -> Acquired {
+        let parked = &self.inner.parked;
+        let mut lock = parked.lock();
+        lock.waiters += 1;
+        loop {
+            if let Some(token) = lock.tokens.pop() {
+                lock.waiters -= 1;
+                return token;
+            }
+            lock = parked.cvar.wait(lock).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Hands over `token` to one of the threads blocked in
+    /// [`Client::acquire_park`], waking it up.
+    ///
+    /// # Return value
+    ///
+    /// If no thread is waiting for a token, or if `token` was acquired from an
+    /// unrelated jobserver, then the token is given back as `Err`.
+    pub fn unpark_one(&self, token: Acquired) -> Result<(), Acquired> {
+        if !Arc::ptr_eq(&self.inner, &token.client) {
+            return Err(token);
+        }
+        let parked = &self.inner.parked;
+        let mut lock = parked.lock();
+        if lock.tokens.len() == lock.waiters {
+            return Err(token);
+        }
+        lock.tokens.push(token);
+        drop(lock);
+        parked.cvar.notify_one();
+        Ok(())
+    }
+
+    /// Returns amount of tokens in the read-side pipe.
+    ///
+    /// # Return value
+    ///
+    /// Number of bytes available to be read from the jobserver pipe
+    ///
+    /// # Errors
+    ///
+    /// Underlying errors from the ioctl will be passed up.
+    pub fn available(&self) -> io::Result<usize> {
+        self.inner.available()
+    }
+
+    /// Waits up to `timeout` for all tokens to be back in this jobserver,
+    /// i.e. for [`Client::available`] to reach its capacity.
+    ///
+    /// This is a barrier for waiting until all parallel jobs are done, e.g.
+    /// before starting the next phase of a build. There's no way to be
+    /// notified of tokens being released, so this polls
+    /// [`Client::available`] with an exponential backoff in between.
+    ///
+    /// Returns `false` if not all tokens were released within `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::Unsupported`] if the
+    /// capacity of this jobserver isn't known, as is the case for inherited
+    /// and unlimited jobservers. Errors from [`Client::available`] are
+    /// propagated.
+    pub fn wait_for_all_released(&self, timeout: Duration) -> io::Result<bool> {
+        self.wait_for_all_released_until(Some(Instant::now() + timeout))
+    }
+
+    /// Same as [`Client::wait_for_all_released`], except that this waits
+    /// without a timeout.
+    pub fn wait_for_all_released_blocking(&self) -> io::Result<()> {
+        self.wait_for_all_released_until(None).map(drop)
+    }
+
+    fn wait_for_all_released_until(&self, deadline: Option<Instant>) -> io::Result<bool> {
+        let capacity = *self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        let capacity = match capacity {
+            Some(capacity) if self.inner.imp.is_some() => capacity,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "capacity of the jobserver is unknown",
+                ))
+            }
+        };
+        let backoff = BackoffStrategy::default();
+        let mut attempt = 0;
+        loop {
+            if self.available()? >= capacity {
+                return Ok(true);
+            }
+            let delay = backoff.delay(attempt);
+            let delay = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(false);
+                    }
+                    delay.min(deadline - now)
+                }
+                None => delay,
+            };
+            thread::sleep(delay);
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Returns a snapshot of the configuration and state of this client.
+    ///
+    /// This is intended for diagnostics, e.g. for build tools reporting
+    /// their jobserver configuration in verbose mode. See [`ClientState`]
+    /// for the details included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of available tokens couldn't be
+    /// determined, other than for platforms where that's unsupported.
+    pub fn dump_state(&self) -> io::Result<ClientState> {
+        let imp = match &self.inner.imp {
+            Some(imp) => imp,
+            None => {
+                return Ok(ClientState {
+                    available: None,
+                    capacity: None,
+                    platform: "unlimited".to_string(),
+                    identifier: String::new(),
+                    is_inherited: false,
+                })
+            }
+        };
+        let available = match imp.available() {
+            Ok(available) => Some(available),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => None,
+            Err(e) => return Err(e),
+        };
+        let capacity = *self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        let (platform, identifier) = imp.describe();
+        Ok(ClientState {
+            available,
+            capacity,
+            platform: platform.to_string(),
+            identifier,
+            is_inherited: self.inner.inherited,
+        })
+    }
+
+    /// Saves the number of tokens currently available in this jobserver to
+    /// `path`, to be restored with [`Client::restore_checkpoint`].
+    ///
+    /// The checkpoint is a plain text file containing a single integer. It's
+    /// first written to a temporary file next to `path` which is then renamed
+    /// over `path`, so a crash while checkpointing never leaves a corrupted
+    /// checkpoint behind.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Client::available`] or writing the checkpoint
+    /// fails.
+    pub fn checkpoint(&self, path: &Path) -> io::Result<()> {
+        use std::io::Write;
+
+        let available = self.available()?;
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        let tmp = Path::new(&tmp);
+        {
+            let mut file = fs::File::create(tmp)?;
+            writeln!(file, "{available}")?;
+            file.sync_all()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    /// Creates a new, independent jobserver with as many tokens as are
+    /// currently available in this one.
+    ///
+    /// Unlike clones of a [`Client`], which share the same jobserver, the
+    /// duplicate is backed by a new pipe or semaphore, so acquiring tokens
+    /// from one doesn't affect the other. This allows snapshotting the
+    /// current state of the pool, e.g. to run speculative work without
+    /// affecting the main pool. Duplicates of unlimited clients are
+    /// unlimited as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Client::available`] or creating the new
+    /// jobserver fails.
+    pub fn duplicate(&self) -> io::Result<Client> {
+        if self.is_unlimited() {
+            return Ok(Client::new_unlimited());
+        }
+        Client::new(self.available()?)
+    }
+
+    /// Returns the number of jobs worth running in parallel right now.
+    ///
+    /// This is the lower of [`Client::available`] and
+    /// [`std::thread::available_parallelism`], falling back to the latter
+    /// if the number of available tokens can't be determined or if this
+    /// client is unlimited. Like [`Client::available`], this doesn't count
+    /// the implicit token of the process, so it may return 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`std::thread::available_parallelism`] fails.
+    pub fn effective_parallelism(&self) -> io::Result<usize> {
+        let cpus = thread::available_parallelism()?.get();
+        if self.is_unlimited() {
+            return Ok(cpus);
+        }
+        Ok(self.available().map_or(cpus, |tokens| tokens.min(cpus)))
+    }
+
+    /// Partitions the tokens currently available in this jobserver into
+    /// `parts` new, independent jobservers.
+    ///
+    /// The sum of the returned clients' limits equals [`Client::available`]
+    /// at the time of the call, distributed as evenly as possible. Each
+    /// returned [`Client`] is a jobserver of its own: tokens acquired from or
+    /// released to one of them never affect this jobserver or any of the
+    /// other parts.
+    ///
+    /// Splitting an unlimited client results in unlimited clients.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if `parts` is
+    /// zero, otherwise any I/O error from querying this jobserver or creating
+    /// the new ones is propagated.
+    pub fn split_n(&self, parts: usize) -> io::Result<Vec<Client>> {
+        if parts == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot split a jobserver into zero parts",
+            ));
+        }
+        if self.is_unlimited() {
+            return Ok((0..parts).map(|_| Client::new_unlimited()).collect());
+        }
+        let available = self.available()?;
+        (0..parts)
+            .map(|i| Client::new(available / parts + usize::from(i < available % parts)))
+            .collect()
+    }
+
+    /// Returns the maximum number of tokens this jobserver can hold.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Windows this is the maximum count of the semaphore, which is the
+    /// initial limit unless raised by [`ClientBuilder::with_semaphore_max`].
+    /// It's only known for semaphores created by this process and `None`
+    /// otherwise.
+    ///
+    /// On other platforms tokens have no fixed maximum and this always
+    /// returns `None`.
+    pub fn max_capacity(&self) -> Option<usize> {
+        self.inner.imp.as_ref().and_then(|imp| imp.max_capacity())
+    }
+
+    /// Returns whether this jobserver can be opened by processes which are
+    /// not descendants of the one that created it.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this returns `true` for jobservers backed by a named fifo
+    /// (`--jobserver-auth=fifo:PATH`), which can be opened by path, and
+    /// `false` for anonymous pipes, which can only be inherited through file
+    /// descriptors.
+    ///
+    /// On Windows this always returns `true` since semaphores are opened by
+    /// name.
+    ///
+    /// On platforms other than Unix and Windows this always returns `false`.
+    pub fn is_cross_process(&self) -> bool {
+        self.inner.is_cross_process()
+    }
+
+    /// Configures a child process to have access to this client's jobserver as
+    /// well.
+    ///
+    /// This function is required to be called to ensure that a jobserver is
+    /// properly inherited to a child process. If this function is *not* called
+    /// then this [`Client`] will not be accessible in the child process. In
+    /// other words, if not called, then [`Client::from_env`] will return `None`
+    /// in the child process (or the equivalent of [`Client::from_env`] that
+    /// `make` uses).
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix and Windows this will clobber the `CARGO_MAKEFLAGS` environment
+    /// variables for the child process, and on Unix this will also allow the
+    /// two file descriptors for this client to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure(&self, cmd: &mut Command) {
+        if self.is_unlimited() {
+            return;
+        }
+        cmd.env("CARGO_MAKEFLAGS", self.mflags_env());
+        self.inner.configure(cmd);
+    }
+
+    /// Checks that this client's jobserver can still be passed to child
+    /// processes.
+    ///
+    /// On Unix this checks that the file descriptors of the jobserver are
+    /// still open, and for jobservers backed by a named fifo that the fifo
+    /// still exists. On Windows this checks that the semaphore handle is
+    /// still valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error encountered while checking the jobserver. Clients
+    /// created through [`Client::new_unlimited`] have no jobserver to pass
+    /// on, so an error of the kind [`io::ErrorKind::Unsupported`] is
+    /// returned for them, as well as on platforms other than Unix and
+    /// Windows.
+    pub fn verify(&self) -> io::Result<()> {
+        match &self.inner.imp {
+            Some(imp) => imp.verify(),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unlimited clients have no jobserver to pass on",
+            )),
+        }
+    }
+
+    /// Same as [`Client::configure`], except that this fails instead of
+    /// letting the child run without a working jobserver.
+    ///
+    /// This is meant for build orchestrators which require all sub-builds
+    /// to run under their jobserver, and should fail fast rather than let a
+    /// sub-build run with unlimited parallelism if the jobserver is broken.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Client::verify`] fails, in which case `cmd` is
+    /// left untouched. This includes clients created through
+    /// [`Client::new_unlimited`].
+    pub fn configure_strict(&self, cmd: &mut Command) -> io::Result<()> {
+        self.verify()?;
+        self.configure(cmd);
+        Ok(())
+    }
+
+    /// Configures a child process to have access to this client's jobserver as
+    /// well.
+    ///
+    /// This function is required to be called to ensure that a jobserver is
+    /// properly inherited to a child process. If this function is *not* called
+    /// then this [`Client`] will not be accessible in the child process. In
+    /// other words, if not called, then [`Client::from_env`] will return `None`
+    /// in the child process (or the equivalent of [`Client::from_env`] that
+    /// `make` uses).
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix and Windows this will clobber the `CARGO_MAKEFLAGS`,
+    /// `MAKEFLAGS` and `MFLAGS` environment variables for the child process,
+    /// and on Unix this will also allow the two file descriptors for
+    /// this client to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_make(&self, cmd: &mut Command) {
+        if self.is_unlimited() {
+            return;
+        }
+        let value = self.mflags_env();
+        cmd.env("CARGO_MAKEFLAGS", &value);
+        cmd.env("MAKEFLAGS", &value);
+        cmd.env("MFLAGS", &value);
+        self.inner.configure(cmd);
+    }
+
+    /// Configures a child Cargo invocation to have access to this client's
+    /// jobserver.
+    ///
+    /// This sets both `CARGO_MAKEFLAGS` and `MAKEFLAGS`, which Cargo reads,
+    /// e.g. for a build script running a nested `cargo build`. Additionally
+    /// `CARGO_BUILD_JOBS` is set to the number of tokens currently available,
+    /// but at least 1, unless it's already set for the child or in the
+    /// environment of this process, or the available tokens are unknown.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_cargo_build(&self, cmd: &mut Command) {
+        if self.is_unlimited() {
+            return;
+        }
+        self.configure_with_flags(cmd, &[MakeFlag::MakeFlags, MakeFlag::CargoMakeFlags]);
+        let jobs_set = cmd.get_envs().any(|(key, _)| key == "CARGO_BUILD_JOBS")
+            || std::env::var_os("CARGO_BUILD_JOBS").is_some();
+        if !jobs_set {
+            if let Ok(available) = self.available() {
+                cmd.env("CARGO_BUILD_JOBS", available.max(1).to_string());
+            }
+        }
+    }
+
+    /// Configures a child process like [`Client::configure`], additionally
+    /// closing all file descriptors in it except for stdio, `fds_to_keep`
+    /// and the jobserver's.
+    ///
+    /// This gives the child a clean file descriptor table, which is a
+    /// security best practice: descriptors accidentally inherited without
+    /// `CLOEXEC`, e.g. ones created by C libraries, don't leak into it. The
+    /// descriptors are closed right before the child execs, by iterating over
+    /// all possible descriptors up to `getdtablesize()`, so this may slow down
+    /// spawning processes with a very high limit on open files.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this doesn't
+    /// configure a jobserver, but still closes the file descriptors.
+    #[cfg(unix)]
+    pub fn configure_inherit_exact(&self, cmd: &mut Command, fds_to_keep: &[RawFd]) {
+        self.configure(cmd);
+        let mut keep = fds_to_keep.to_vec();
+        if let Some(imp) = &self.inner.imp {
+            keep.extend(imp.inherited_fds());
+        }
+        imp::close_fds_except(cmd, keep);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// through both `MAKEFLAGS` and `MFLAGS`.
+    ///
+    /// Old versions of BSD make and some other tools only read `MFLAGS`. This
+    /// sets both environment variables to the same value, leaving
+    /// `CARGO_MAKEFLAGS` untouched. See [`Client::configure_with_flags`] to
+    /// choose the variables freely.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_mflags(&self, cmd: &mut Command) {
+        self.configure_with_flags(cmd, &[MakeFlag::MakeFlags, MakeFlag::MFlags]);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// through exactly the environment variables in `flags`.
+    ///
+    /// Each variable is set to the same value [`Client::configure_make`]
+    /// uses, and no other variables are touched. With empty `flags` only the
+    /// inheritance of the jobserver itself is set up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::{Client, MakeFlag};
+    /// use std::process::Command;
+    ///
+    /// let client = Client::new(2).unwrap();
+    /// let mut cmd = Command::new("ninja");
+    /// client.configure_with_flags(
+    ///     &mut cmd,
+    ///     &[MakeFlag::MakeFlags, MakeFlag::Custom("NINJA_FLAGS".to_string())],
+    /// );
+    /// ```
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_with_flags(&self, cmd: &mut Command, flags: &[MakeFlag]) {
+        if self.is_unlimited() {
+            return;
+        }
+        let value = self.mflags_env();
+        for flag in flags {
+            cmd.env(flag.name(), &value);
+        }
+        self.inner.configure(cmd);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// through the environment variable `var_name`.
+    ///
+    /// The variable is set to the same value [`Client::configure_make`] uses
+    /// for `MAKEFLAGS`, to be read by [`Client::from_env_vars`] in the child.
+    /// No other environment variables are set. This is the same as
+    /// [`Client::configure_with_flags`] with a single [`MakeFlag::Custom`].
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_as_env_var(&self, var_name: &str, cmd: &mut Command) {
+        self.configure_with_flags(cmd, &[MakeFlag::Custom(var_name.to_string())]);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// through a namespaced environment variable.
+    ///
+    /// Instead of `MAKEFLAGS` and friends, this sets only the
+    /// `{prefix}_JOBSERVER_AUTH` environment variable, to be read by
+    /// [`Client::from_env_prefix`] in the child. Different prefixes allow
+    /// passing multiple independent jobservers to the same child, e.g. one
+    /// limiting compile jobs and one limiting link jobs, without them
+    /// interfering with each other or with `make`.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this also allows the file descriptors for this client to be
+    /// inherited to the child, and on platforms other than Unix and Windows
+    /// this panics, like [`Client::configure`].
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_env_prefix(&self, prefix: &str, cmd: &mut Command) {
+        if self.is_unlimited() {
+            return;
+        }
+        let value = self.inner.imp.as_ref().unwrap().string_arg();
+        cmd.env(format!("{prefix}_JOBSERVER_AUTH"), value);
+        self.inner.configure(cmd);
+    }
+
+    /// Configures a child process to run without any limit on parallelism,
+    /// while still signaling that it runs in a jobserver context.
+    ///
+    /// This sets the `CARGO_MAKEFLAGS` and `MAKEFLAGS` environment variables
+    /// of the child process to `--jobserver-auth=none`. [`Client::from_env`]
+    /// in the child recognizes this sentinel and returns a client like
+    /// [`Client::new_unlimited`], whose acquisitions always succeed without
+    /// blocking. Other jobserver implementations wishing to support this
+    /// need to treat an auth value of `none` the same way, since `make`
+    /// itself doesn't know about it.
+    pub fn configure_noop(cmd: &mut Command) {
+        let value = format!("--jobserver-auth={NOOP_JOBSERVER_AUTH}");
+        cmd.env("CARGO_MAKEFLAGS", &value);
+        cmd.env("MAKEFLAGS", &value);
+    }
+
+    /// Configures a recursive `make` invocation like [`Client::configure_make`],
+    /// additionally reserving `extra_jobs` tokens for it.
+    ///
+    /// This blocks until `extra_jobs` tokens have been acquired from this
+    /// jobserver and returns them. If the number of tokens this jobserver was
+    /// created with is known, no more tokens are acquired than that minus the
+    /// ones already held through this client and its clones, which could
+    /// otherwise never all be acquired at once. Tokens are acquired all at once through
+    /// [`Client::try_acquire_many_nonblocking`], backing off in between, so
+    /// that concurrent callers don't each end up holding part of the tokens
+    /// while waiting for the rest. On platforms where that isn't supported,
+    /// tokens are acquired one by one instead, with concurrent calls in this
+    /// process serialized. The caller is expected to keep them while
+    /// spawning the child and to drop them right afterwards, which releases
+    /// them back to the pool as an initial supply for the child which is
+    /// just starting up. This way the child can start at least `extra_jobs`
+    /// jobs even if the pool was drained in the meantime, instead of competing
+    /// for tokens from the start. Note that other processes using this
+    /// jobserver could still grab the released tokens first.
+    ///
+    /// # Errors
+    ///
+    /// If an I/O error happens while acquiring the tokens then the ones
+    /// acquired so far are released and the error is returned, leaving `cmd`
+    /// untouched.
+    pub fn configure_make_recursive(
+        &self,
+        cmd: &mut Command,
+        extra_jobs: usize,
+    ) -> io::Result<Vec<Acquired>> {
+        let capacity = *self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        let count = capacity.map_or(extra_jobs, |capacity| {
+            extra_jobs.min(capacity.saturating_sub(self.inner.held_tokens()))
+        });
+        let tokens = self.acquire_many_with_backoff(count)?;
+        self.configure_make(cmd);
+        Ok(tokens)
+    }
+
+    /// Acquires `n` tokens without holding any of them while waiting for the
+    /// rest, see [`Client::configure_make_recursive`].
+    fn acquire_many_with_backoff(&self, n: usize) -> io::Result<Vec<Acquired>> {
+        let backoff = BackoffStrategy::default();
+        let mut attempt = 0;
+        loop {
+            match self.try_acquire_many_nonblocking(n) {
+                Ok(tokens) if tokens.len() == n => return Ok(tokens),
+                Ok(_) => {}
+                // The first `try_acquire` failed, so nothing is held yet.
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    let _lock = self
+                        .inner
+                        .multi_acquire
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner());
+                    return (0..n).map(|_| self.acquire()).collect();
+                }
+                Err(e) => return Err(e),
+            }
+            thread::sleep(backoff.delay(attempt));
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Configures a recursive `make` invocation to run at most `max` jobs, so
+    /// that it can't starve sibling processes of tokens.
+    ///
+    /// Instead of this jobserver, `cmd` is configured like
+    /// [`Client::configure_make`] with a new jobserver of `max` tokens. First
+    /// `pool_size - max` tokens are reserved in this jobserver, where
+    /// `pool_size` is the number of tokens it was created with, blocking
+    /// until they're available. Tokens already held through this client and
+    /// its clones count towards that, so fewer are reserved while the caller
+    /// holds some. Nothing is reserved if `max` is at least `pool_size`, or if
+    /// the size of this jobserver isn't known, e.g. for jobservers inherited
+    /// from the environment. Tokens are acquired like in
+    /// [`Client::configure_make_recursive`], so concurrent callers don't
+    /// deadlock each other.
+    ///
+    /// The reserved tokens are held by the returned [`RecursiveJobsLimit`]
+    /// and released when it's dropped. Pass the spawned child to
+    /// [`RecursiveJobsLimit::release_on_exit`] to release them once it exits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if acquiring the tokens or creating the new jobserver
+    /// fails, in which case the tokens acquired so far are released and
+    /// `cmd` is left untouched.
+    pub fn configure_recursive_jobs_limit(
+        &self,
+        cmd: &mut Command,
+        max: usize,
+    ) -> io::Result<RecursiveJobsLimit> {
+        let capacity = *self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        let reserve = capacity.map_or(0, |capacity| {
+            capacity
+                .saturating_sub(max)
+                .saturating_sub(self.inner.held_tokens())
+        });
+        let reserved = self.acquire_many_with_backoff(reserve)?;
+        let client = Client::new(max)?;
+        client.configure_make(cmd);
+        Ok(RecursiveJobsLimit::new(client, reserved))
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// as well, merging the jobserver into its existing `MAKEFLAGS`.
+    ///
+    /// Unlike [`Client::configure_make`], which clobbers `MAKEFLAGS`, this
+    /// keeps all the other flags the child may need (`-r`, `-s`, etc.). The
+    /// existing value is taken from the environment already configured on
+    /// `cmd`, or otherwise inherited from this process. Any
+    /// `--jobserver-auth=` or `--jobserver-fds=` flags in it are removed and
+    /// replaced with the ones for this client. No other environment variables
+    /// are set.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_without_extra_env(&self, cmd: &mut Command) {
+        self.configure_env_inherit_filter(|_, _| true, cmd);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// like [`Client::configure_without_extra_env`], keeping only the
+    /// existing `MAKEFLAGS` flags accepted by `filter`.
+    ///
+    /// `filter` is called with the name and value of each flag in the
+    /// existing `MAKEFLAGS`, split at the first `=`, e.g. `("--debug", "b")`
+    /// for `--debug=b` and `("-r", "")` for `-r`. The flags it returns `true`
+    /// for are kept, and the jobserver flags are appended to them.
+    /// `--jobserver-auth=` and `--jobserver-fds=` flags are always replaced.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_env_inherit_filter<F>(&self, filter: F, cmd: &mut Command)
+    where
+        F: Fn(&str, &str) -> bool,
+    {
+        if self.is_unlimited() {
+            return;
+        }
+        let existing = cmd
+            .get_envs()
+            .find(|(key, _)| *key == "MAKEFLAGS")
+            .map(|(_, value)| value.map(|v| v.to_os_string()))
+            .unwrap_or_else(|| env::var_os("MAKEFLAGS"))
+            .unwrap_or_default();
+        let value =
+            merge_jobserver_args(&existing.to_string_lossy(), &self.jobserver_args(), filter);
+        cmd.env("MAKEFLAGS", value);
+        self.inner.configure(cmd);
+    }
+
+    /// Configures a child process to have access to this client's jobserver
+    /// like [`Client::configure_make`], but preserving the other flags in the
+    /// `MAKEFLAGS` of this process.
+    ///
+    /// This is the right choice for recursive `make` invocations, which
+    /// should see the same flags (`-r`, `-s`, etc.) as their parent. Unlike
+    /// [`Client::configure_without_extra_env`], the existing flags are always
+    /// taken from the environment of this process, ignoring any `MAKEFLAGS`
+    /// already configured on `cmd`, and `CARGO_MAKEFLAGS` and `MFLAGS` are
+    /// set as well. Any `--jobserver-auth=` or `--jobserver-fds=` flags are
+    /// replaced with the ones for this client.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// On Unix this will also allow the two file descriptors for this client
+    /// to be inherited to the child.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    pub fn configure_preserve_makeflags(&self, cmd: &mut Command) {
+        if self.is_unlimited() {
+            return;
+        }
+        let existing = env::var_os("MAKEFLAGS").unwrap_or_default();
+        let value = merge_jobserver_args(
+            &existing.to_string_lossy(),
+            &self.jobserver_args(),
+            |_, _| true,
+        );
+        let mflags = self.mflags_env();
+        cmd.env("CARGO_MAKEFLAGS", &mflags);
+        cmd.env("MAKEFLAGS", value);
+        cmd.env("MFLAGS", &mflags);
+        self.inner.configure(cmd);
+    }
+
+    /// Configures a child process to have access to this client's jobserver,
+    /// additionally passing `--jobs=<N>` where `N` is the number of tokens
+    /// currently available.
+    ///
+    /// Some tools, like `cmake`, want an explicit `--jobs` argument to know
+    /// their parallelism budget. Note that this is inherently racy: tokens may
+    /// be acquired or released between this call and the child reading its
+    /// arguments, so the count is only informational and shouldn't be relied
+    /// upon for strict enforcement. Use [`Client::configure_job_count_exact`]
+    /// to pass a specific count instead.
+    ///
+    /// The environment is configured as with [`Client::configure`]. For
+    /// clients created through [`Client::new_unlimited`] this does nothing,
+    /// as there's no meaningful count to pass.
+    ///
+    /// # Errors
+    ///
+    /// Any error from [`Client::available`] is propagated, in which case
+    /// `cmd` is left untouched.
+    pub fn configure_job_count(&self, cmd: &mut Command) -> io::Result<()> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+        let n = self.available()?;
+        self.configure_job_count_exact(cmd, n);
+        Ok(())
+    }
+
+    /// Configures a child process to have access to this client's jobserver,
+    /// additionally passing `--jobs=<n>`.
+    ///
+    /// See [`Client::configure_job_count`] for more info. Unlike there,
+    /// `--jobs=<n>` is passed for clients created through
+    /// [`Client::new_unlimited`] as well, while the environment is left
+    /// untouched as with [`Client::configure`].
+    pub fn configure_job_count_exact(&self, cmd: &mut Command, n: usize) {
+        self.configure(cmd);
+        cmd.arg(format!("--jobs={n}"));
+    }
+
+    /// Configures a `make` child process to have access to this client's
+    /// jobserver, additionally passing `-j<N>` where `N` is the number of
+    /// tokens in the jobserver.
+    ///
+    /// Older versions of `make` ignore the jobserver unless they're also
+    /// passed `-j`. `N` is only known for jobservers created by this process,
+    /// and is 1 otherwise. No `-j` is added if `cmd` already has a `-j` or
+    /// `--jobs` argument. Use [`Client::configure_make_j_flag_with_count`] to
+    /// pass a specific count instead.
+    ///
+    /// The environment is configured as with [`Client::configure_make`].
+    pub fn configure_make_j_flag(&self, cmd: &mut Command) {
+        let limit = *self.inner.limit.lock().unwrap_or_else(|e| e.into_inner());
+        self.configure_make_j_flag_with_count(cmd, limit.unwrap_or(1));
+    }
+
+    /// Configures a `make` child process to have access to this client's
+    /// jobserver, additionally passing `-j<n>`.
+    ///
+    /// See [`Client::configure_make_j_flag`] for more info.
+    pub fn configure_make_j_flag_with_count(&self, cmd: &mut Command, n: usize) {
+        if self.is_unlimited() {
+            return;
+        }
+        self.configure_make(cmd);
+        let has_jobs = cmd.get_args().any(|arg| {
+            let arg = arg.to_string_lossy();
+            arg.starts_with("-j") || arg == "--jobs" || arg.starts_with("--jobs=")
+        });
+        if !has_jobs {
+            cmd.arg(format!("-j{n}"));
+        }
+    }
+
+    /// Configures a child Ninja process to have access to this client's
+    /// jobserver.
+    ///
+    /// Ninja 1.13 and later act as jobserver clients, reading the jobserver
+    /// from `MAKEFLAGS` in the format of GNU make 4.4, see
+    /// [`Client::from_ninja_env`]. This sets `MAKEFLAGS` to
+    /// `-j --jobserver-auth=<auth>` accordingly, leaving out the
+    /// `--jobserver-fds=` flag for older versions of make.
+    ///
+    /// For clients created through [`Client::new_unlimited`] this does
+    /// nothing.
+    ///
+    /// ## Platform-specific behavior
+    ///
+    /// Ninja doesn't support anonymous pipes, so on Unix this only works for
+    /// jobservers backed by a named fifo, e.g. one inherited from GNU make
+    /// 4.4 started with `--jobserver-style=fifo`.
+    ///
+    /// On platforms other than Unix and Windows this panics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::Unsupported`] on Unix if
+    /// the jobserver is backed by an anonymous pipe, in which case `cmd` is
+    /// left untouched.
+    pub fn configure_for_ninja(&self, cmd: &mut Command) -> io::Result<()> {
+        let imp = match &self.inner.imp {
+            Some(imp) => imp,
+            None => return Ok(()),
+        };
+        cmd.env(
+            "MAKEFLAGS",
+            format!("-j --jobserver-auth={}", imp.ninja_arg()?),
+        );
+        self.inner.configure(cmd);
+        Ok(())
+    }
+
+    fn mflags_env(&self) -> String {
+        format!("-j {}", self.jobserver_args())
+    }
+
+    fn jobserver_args(&self) -> String {
+        // Unlimited clients are never configured for a child process, so they
+        // never get here.
+        let arg = self.inner.imp.as_ref().unwrap().string_arg();
+        // Older implementations of make use `--jobserver-fds` and newer
+        // implementations use `--jobserver-auth`, pass both to try to catch
+        // both implementations.
+        format!("--jobserver-fds={0} --jobserver-auth={0}", arg)
+    }
+
+    /// Converts this [`Client`] into a helper thread to deal with a blocking
+    /// [`Client::acquire`] function a little more easily.
+    ///
+    /// The fact that the [`Client::acquire`] isn't always the easiest to work
+    /// with. Typically you're using a jobserver to manage running other events
+    /// in parallel! This means that you need to either (a) wait for an existing
+    /// job to finish or (b) wait for a new token to become available.
+    ///
+    /// Unfortunately the blocking in [`Client::acquire`] happens at the
+    /// implementation layer of jobservers. On Unix this requires a blocking
+    /// call to `read` and on Windows this requires one of the `WaitFor*`
+    /// functions. Both of these situations aren't the easiest to deal with:
+    ///
+    /// * On Unix there's basically only one way to wake up a `read` early, and
+    ///   that's through a signal. This is what the `make` implementation
+    ///   itself uses, relying on `SIGCHLD` to wake up a blocking acquisition
+    ///   of a new job token. Unfortunately nonblocking I/O is not an option
+    ///   here, so it means that "waiting for one of two events" means that
+    ///   the latter event must generate a signal! This is not always the case
+    ///   on unix for all jobservers.
+    ///
+    /// * On Windows you'd have to basically use the `WaitForMultipleObjects`
+    ///   which means that you've got to canonicalize all your event sources
+    ///   into a `HANDLE` which also isn't the easiest thing to do
+    ///   unfortunately.
+    ///
+    /// This function essentially attempts to ease these limitations by
+    /// converting this [`Client`] into a helper thread spawned into this
+    /// process. The application can then request that the helper thread
+    /// acquires tokens and the provided closure will be invoked for each token
+    /// acquired.
+    ///
+    /// The intention is that this function can be used to translate the event
+    /// of a token acquisition into an arbitrary user-defined event.
+    ///
+    /// # Arguments
+    ///
+    /// This function will consume the [`Client`] provided to be transferred to
+    /// the helper thread that is spawned. Additionally a closure `f` is
+    /// provided to be invoked whenever a token is acquired.
+    ///
+    /// This closure is only invoked after calls to
+    /// [`HelperThread::request_token`] have been made and a token itself has
+    /// been acquired. If an error happens while acquiring the token then
+    /// an error will be yielded to the closure as well.
+    ///
+    /// # Return Value
+    ///
+    /// This function will return an instance of the [`HelperThread`] structure
+    /// which is used to manage the helper thread associated with this client.
+    /// Through the [`HelperThread`] you'll request that tokens are acquired.
+    /// When acquired, the closure provided here is invoked.
+    ///
+    /// When the [`HelperThread`] structure is returned it will be gracefully
+    /// torn down, and the calling thread will be blocked until the thread is
+    /// torn down (which should be prompt).
+    ///
+    /// # Errors
+    ///
+    /// This function may fail due to creation of the helper thread or
+    /// auxiliary I/O objects to manage the helper thread. In any of these
+    /// situations the error is propagated upwards.
+    ///
+    /// # Platform-specific behavior
+    ///
+    /// On Windows this function behaves pretty normally as expected, but on
+    /// Unix the implementation is... a little heinous. As mentioned above
+    /// we're forced into blocking I/O for token acquisition, namely a blocking
+    /// call to `read`. We must be able to unblock this, however, to tear down
+    /// the helper thread gracefully!
+    ///
+    /// Essentially what happens is that we'll send a signal to the helper
+    /// thread spawned and rely on `EINTR` being returned to wake up the helper
+    /// thread. This involves installing a global `SIGUSR1` handler that does
+    /// nothing along with sending signals to that thread. This may cause
+    /// odd behavior in some applications, so it's recommended to review and
+    /// test thoroughly before using this.
+    pub fn into_helper_thread<F>(self, f: F) -> io::Result<HelperThread>
+    where
+        F: FnMut(io::Result<Acquired>) + Send + 'static,
+    {
+        self.into_helper_thread_with(None, f)
+    }
+
+    /// Same as [`Client::into_helper_thread`], except that the helper thread
+    /// is given the provided name.
+    ///
+    /// The name shows up in profilers, debuggers and other tools like `top`,
+    /// which helps diagnosing builds hanging while waiting for tokens. Note
+    /// that some platforms, for example Linux, truncate thread names to 15
+    /// bytes at the OS level. The full name is always available through
+    /// [`HelperThread::thread_name`] and [`std::thread::Thread::name`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a nul byte.
+    pub fn into_helper_thread_named<F>(self, name: &str, f: F) -> io::Result<HelperThread>
+    where
+        F: FnMut(io::Result<Acquired>) + Send + 'static,
+    {
+        self.into_helper_thread_with(Some(name.to_string()), f)
+    }
+
+    fn into_helper_thread_with<F>(self, name: Option<String>, f: F) -> io::Result<HelperThread>
+    where
+        F: FnMut(io::Result<Acquired>) + Send + 'static,
+    {
+        let mut builder = Builder::new();
+        if let Some(name) = &name {
+            builder = builder.name(name.clone());
+        }
+        let state = Arc::new(HelperState::default());
+        if self.is_unlimited() {
+            let state2 = state.clone();
+            let mut f = f;
+            let thread = builder.spawn(move || {
+                state2.for_each_request(|_| f(self.acquire()));
+            })?;
+            return Ok(HelperThread {
+                inner: None,
+                unlimited: Some(thread),
+                name,
+                state,
+            });
+        }
+        Ok(HelperThread {
+            inner: Some(imp::spawn_helper(
+                builder,
+                self,
+                state.clone(),
+                Box::new(f),
+            )?),
+            unlimited: None,
+            name,
+            state,
+        })
+    }
+
+    /// Spawns a thread which releases a token back to this jobserver whenever
+    /// one of the given file descriptors is closed.
+    ///
+    /// Some protocols, typically implemented by C libraries wrapped by the
+    /// caller, don't explicitly release tokens but instead tie them to the
+    /// lifetime of a file descriptor such as a lock file. This function
+    /// bridges those protocols by watching each of `monitor_fds` with `poll`.
+    /// As soon as one of them reports `POLLHUP`, `POLLERR` or `POLLNVAL` a
+    /// token is released with [`Client::release_raw`] and that file
+    /// descriptor is no longer watched.
+    ///
+    /// Monitoring continues until all file descriptors have been closed or
+    /// the returned [`AutoReleaseHandle`] is cancelled or dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function may fail due to creation of the monitoring thread or the
+    /// pipe used to wake it up when cancelled.
+    #[cfg(unix)]
+    pub fn with_auto_release(&self, monitor_fds: &[RawFd]) -> io::Result<AutoReleaseHandle> {
+        Ok(AutoReleaseHandle {
+            inner: Some(imp::spawn_auto_release(self.clone(), monitor_fds)?),
+        })
+    }
+
+    /// Sends this client's jobserver to another process over a Unix domain
+    /// socket.
+    ///
+    /// The file descriptors of the jobserver are passed as `SCM_RIGHTS`
+    /// ancillary data over `socket_fd`, which must be a connected Unix domain
+    /// socket. The process on the other end receives them through
+    /// [`Client::recv_fd_passing`]. Unlike [`Client::configure`], this works
+    /// between unrelated processes, without going through `MAKEFLAGS`.
+    ///
+    /// Clients created through [`Client::new_unlimited`] are sent as well,
+    /// and received as unlimited clients.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sending the message fails.
+    #[cfg(unix)]
+    pub fn configure_fd_passing(&self, socket_fd: RawFd) -> io::Result<()> {
+        imp::send_client(socket_fd, self.inner.imp.as_ref())
+    }
+
+    /// Receives a jobserver sent through [`Client::configure_fd_passing`] by
+    /// another process.
+    ///
+    /// This blocks until a message is received from `socket_fd`, which must
+    /// be a connected Unix domain socket. The received file descriptors are
+    /// owned by the returned client and configured with `CLOEXEC`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if receiving the message fails, with
+    /// [`io::ErrorKind::UnexpectedEof`] if the socket was closed, or with
+    /// [`io::ErrorKind::InvalidData`] if the message didn't contain a
+    /// jobserver.
+    #[cfg(unix)]
+    pub fn recv_fd_passing(socket_fd: RawFd) -> io::Result<Client> {
+        let inner = match imp::recv_client(socket_fd)? {
+            Some(imp) => ClientInner::inherited(imp),
+            None => ClientInner::unlimited(),
+        };
+        Ok(Client {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Spawns a thread calling `callback` every `interval` while any blocking
+    /// acquisition of a token is in progress.
+    ///
+    /// Some build systems terminate workers which haven't produced any
+    /// output for a while, which may happen to a process waiting for a token
+    /// of a busy jobserver. The callback can be used to keep such a process
+    /// alive, e.g. by printing a message to stderr. Acquisitions through
+    /// this client and all of its clones are taken into account, while
+    /// non-blocking ones like [`Client::try_acquire`] are not.
+    ///
+    /// The heartbeat continues until the returned [`HeartbeatHandle`] is
+    /// stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the heartbeat thread couldn't be spawned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn heartbeat(
+        &self,
+        interval: Duration,
+        callback: Box<dyn Fn() + Send>,
+    ) -> io::Result<HeartbeatHandle> {
+        assert!(!interval.is_zero(), "heartbeat interval must not be zero");
+        HeartbeatHandle::spawn(self.clone(), interval, callback)
+    }
+
+    /// Spawns a thread calling `callback` whenever a blocking acquisition of
+    /// a token has been waiting for longer than `threshold`.
+    ///
+    /// Such starvation usually means the jobserver is oversubscribed, and
+    /// the callback typically logs a warning or records a metric. The
+    /// monitor checks the longest running acquisition through this client
+    /// and all of its clones every half `threshold`, so `callback` is called
+    /// repeatedly for as long as the starvation lasts. Non-blocking
+    /// acquisitions like [`Client::try_acquire`] are not taken into account.
+    ///
+    /// The monitor continues until the returned [`StarvationMonitorHandle`]
+    /// is stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the monitor thread couldn't be spawned.
+    pub fn with_on_starvation(
+        &self,
+        threshold: Duration,
+        callback: Box<dyn Fn() + Send>,
+    ) -> io::Result<StarvationMonitorHandle> {
+        StarvationMonitorHandle::spawn(self.clone(), threshold, callback)
+    }
+
+    /// Acquires a token from within the rayon thread pool `pool`, without
+    /// blocking its worker thread.
+    ///
+    /// If no token is available right away, the blocking acquisition is
+    /// performed by a dedicated helper thread rather than a rayon job, which
+    /// would take a worker away from the pool for as long as it blocks.
+    /// Meanwhile the rayon worker running this keeps executing other pending
+    /// jobs of `pool` through [`rayon::ThreadPool::yield_now`], so that work
+    /// stealing isn't starved by threads waiting for tokens. When called from
+    /// outside of `pool`, this blocks the calling thread until a token was
+    /// acquired, with the waiting done on a worker of `pool`.
+    ///
+    /// This requires the `rayon` feature to be enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the helper thread couldn't be spawned or if
+    /// acquiring the token fails.
+    #[cfg(feature = "rayon")]
+    pub fn acquire_in_rayon_thread_pool(&self, pool: &rayon::ThreadPool) -> io::Result<Acquired> {
+        match self.try_acquire() {
+            Ok(Some(token)) => return Ok(token),
+            Ok(None) => {}
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+            Err(e) => return Err(e),
+        }
+
+        // If we stop waiting, the token sent afterwards is dropped along
+        // with the channel and thus released.
+        let (tx, rx) = mpsc::channel();
+        let client = self.clone();
+        Builder::new().spawn(move || drop(tx.send(client.acquire())))?;
+
+        pool.install(move || {
+            loop {
+                if pool.yield_now() == Some(rayon::Yield::Executed) {
+                    match rx.try_recv() {
+                        Ok(result) => return result,
+                        Err(mpsc::TryRecvError::Empty) => {}
+                        Err(mpsc::TryRecvError::Disconnected) => break,
+                    }
+                } else {
+                    // Nothing else to do, so wait for the token for a bit
+                    // before checking for other jobs again.
+                    match rx.recv_timeout(Duration::from_millis(1)) {
+                        Ok(result) => return result,
+                        Err(mpsc::RecvTimeoutError::Timeout) => {}
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            }
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "jobserver acquisition thread exited unexpectedly",
+            ))
+        })
+    }
+
+    /// Adapts this client to channels, for code structured around
+    /// [`std::sync::mpsc`].
+    ///
+    /// This spawns a background thread which, for every `()` sent through the
+    /// returned [`SyncSender`], acquires a token and sends it through the
+    /// returned [`Receiver`]. Requests are processed in order, and up to
+    /// `capacity` of them can be queued without blocking the sender.
+    ///
+    /// The background thread exits once the [`SyncSender`] and all its clones
+    /// are dropped. Dropping the [`Receiver`] is only noticed when the next
+    /// token can't be delivered: the thread then exits after releasing that
+    /// token, so until the next request it stays alive, and while waiting
+    /// for that token it still blocks in [`Client::acquire`]. Drop the
+    /// [`SyncSender`] as well so that it exits once no request is pending.
+    /// The thread also exits if acquiring a token fails, which disconnects
+    /// the [`Receiver`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the background thread couldn't be spawned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    ///
+    /// let (requests, tokens) = Client::new(2).unwrap().into_channel(1).unwrap();
+    /// requests.send(()).unwrap();
+    /// let token = tokens.recv().unwrap();
+    /// drop(token);
+    /// ```
+    pub fn into_channel(
+        self,
+        capacity: usize,
+    ) -> io::Result<(mpsc::SyncSender<()>, mpsc::Receiver<Acquired>)> {
+        let (request_tx, request_rx) = mpsc::sync_channel(capacity);
+        let (token_tx, token_rx) = mpsc::channel();
+        Builder::new().spawn(move || {
+            for () in request_rx {
+                let token = match self.acquire() {
+                    Ok(token) => token,
+                    Err(_) => break,
+                };
+                if token_tx.send(token).is_err() {
+                    break;
+                }
+            }
+        })?;
+        Ok((request_tx, token_rx))
+    }
+
+    /// Spawns a thread which acquires a token and then runs `f` with it.
+    ///
+    /// The spawned thread blocks until a token is available, so the work in
+    /// `f` only starts once it is allowed to run by the jobserver. The token
+    /// is released once `f` drops the provided [`Acquired`], typically when
+    /// it returns.
+    ///
+    /// The returned [`JoinHandle`] yields the result of `f`, or the I/O error
+    /// that happened while acquiring the token, in which case `f` was never
+    /// called.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be spawned.
+    pub fn acquire_in_thread<F, R>(&self, f: F) -> io::Result<JoinHandle<io::Result<R>>>
+    where
+        F: FnOnce(Acquired) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let client = self.clone();
+        Builder::new().spawn(move || client.acquire().map(f))
+    }
+
+    /// Joins all `handles`, releasing each thread's token once it finished.
+    ///
+    /// This replaces manually pairing join handles with their tokens: each
+    /// thread is joined in order and its token released right afterwards,
+    /// whether the thread returned normally or panicked.
+    ///
+    /// # Errors
+    ///
+    /// If releasing a token fails, the remaining threads are still joined and
+    /// their tokens released, and the first error is returned.
+    ///
+    /// # Panics
+    ///
+    /// If any of the threads panicked, its panic is propagated once all
+    /// threads have been joined and all tokens released. With several
+    /// panicking threads the first one's payload is propagated.
+    pub fn wait_all(&self, handles: Vec<SpawnedJob>) -> io::Result<()> {
+        let mut panic = None;
+        let mut result = Ok(());
+        for (handle, token) in handles {
+            let joined = handle.join();
+            let released = token.release();
+            if let Err(payload) = joined {
+                panic.get_or_insert(payload);
+            }
+            if result.is_ok() {
+                result = released;
+            }
+        }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+        result
+    }
+
+    /// Creates a scope for spawning threads which each hold a token of this
+    /// jobserver while running.
+    ///
+    /// This wraps [`std::thread::scope`]: threads spawned through
+    /// [`ClientScope::spawn`] may borrow non-`'static` data, and all of them
+    /// are joined before this function returns. Each of them only starts
+    /// once a token was acquired for it, which is released once it's done.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jobserver::Client;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let client = Client::new(2).unwrap();
+    /// let sum = AtomicUsize::new(0);
+    /// client.scope(|s| {
+    ///     for i in 1..=4 {
+    ///         let sum = &sum;
+    ///         s.spawn(move || sum.fetch_add(i, Ordering::SeqCst)).unwrap();
+    ///     }
+    /// });
+    /// assert_eq!(sum.into_inner(), 10);
+    /// ```
+    pub fn scope<'env, F, T>(&'env self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&ClientScope<'scope, 'env>) -> T,
+    {
+        thread::scope(|scope| f(&ClientScope::new(self, scope)))
+    }
+
+    /// Acquires a token and then spawns a thread with `thread_builder` which
+    /// runs `f` with it.
     ///
-    /// On successful acquisition of a token an instance of [`Acquired`] is
-    /// returned. This structure, when dropped, will release the token back to
-    /// the jobserver. It's recommended to avoid leaking this value.
+    /// Unlike [`Client::acquire_in_thread`] the calling thread blocks until a
+    /// token is acquired, and the token is moved into the spawned thread
+    /// without ever being released in between. The token is released once `f`
+    /// drops the provided [`Acquired`], typically when it returns.
     ///
     /// # Errors
     ///
-    /// If an I/O error happens while acquiring a token then this function will
-    /// return immediately with the error. If an error is returned then a token
-    /// was not acquired.
-    ///
-    /// If non-blocking acquire is not supported, the return error will have its `kind()`
-    /// set to [`io::ErrorKind::Unsupported`].
-    pub fn try_acquire(&self) -> io::Result<Option<Acquired>> {
-        let ret = self.inner.try_acquire()?;
-
-        Ok(ret.map(|data| Acquired {
-            client: self.inner.clone(),
-            data,
-            disabled: false,
-        }))
+    /// Returns an error if acquiring the token or spawning the thread fails.
+    /// In the latter case the token is released before returning.
+    pub fn acquire_then_spawn<F, R>(
+        &self,
+        thread_builder: Builder,
+        f: F,
+    ) -> io::Result<JoinHandle<R>>
+    where
+        F: FnOnce(Acquired) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let token = self.acquire()?;
+        thread_builder.spawn(move || f(token))
     }
 
-    /// Returns amount of tokens in the read-side pipe.
-    ///
-    /// # Return value
+    /// Acquires a token and then spawns a worker thread running `f`.
     ///
-    /// Number of bytes available to be read from the jobserver pipe
+    /// The calling thread blocks until a token is acquired. The token is held
+    /// by the returned [`WorkerHandle`] and released once the worker was
+    /// joined through it. `f` is passed a flag which is set when the worker
+    /// is asked to stop through [`WorkerHandle::abort`], and should check it
+    /// regularly to return early.
     ///
     /// # Errors
     ///
-    /// Underlying errors from the ioctl will be passed up.
-    pub fn available(&self) -> io::Result<usize> {
-        self.inner.available()
+    /// Returns an error if acquiring the token or spawning the thread fails.
+    /// In the latter case the token is released before returning.
+    pub fn spawn_worker<F, R>(&self, f: F) -> io::Result<WorkerHandle<R>>
+    where
+        F: FnOnce(&AtomicBool) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        WorkerHandle::spawn(self.acquire()?, f)
     }
 
-    /// Configures a child process to have access to this client's jobserver as
-    /// well.
-    ///
-    /// This function is required to be called to ensure that a jobserver is
-    /// properly inherited to a child process. If this function is *not* called
-    /// then this [`Client`] will not be accessible in the child process. In
-    /// other words, if not called, then [`Client::from_env`] will return `None`
-    /// in the child process (or the equivalent of [`Client::from_env`] that
-    /// `make` uses).
+    /// Acquires a token and then spawns a thread which runs `f` with it.
     ///
-    /// ## Platform-specific behavior
+    /// This is [`Client::acquire_then_spawn`] with a default thread
+    /// [`Builder`]. Calling it in a loop spawns exactly as many threads as
+    /// there are tokens, since each call blocks until the next token is
+    /// available.
     ///
-    /// On Unix and Windows this will clobber the `CARGO_MAKEFLAGS` environment
-    /// variables for the child process, and on Unix this will also allow the
-    /// two file descriptors for this client to be inherited to the child.
+    /// # Errors
     ///
-    /// On platforms other than Unix and Windows this panics.
-    pub fn configure(&self, cmd: &mut Command) {
-        cmd.env("CARGO_MAKEFLAGS", &self.mflags_env());
-        self.inner.configure(cmd);
+    /// Returns an error if acquiring the token or spawning the thread fails.
+    /// In the latter case the token is released before returning.
+    pub fn acquire_or_create_thread<F, R>(&self, f: F) -> io::Result<JoinHandle<R>>
+    where
+        F: FnOnce(Acquired) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.acquire_then_spawn(Builder::new(), f)
     }
 
-    /// Configures a child process to have access to this client's jobserver as
-    /// well.
+    /// Acquires a token, spawns `cmd` with access to this jobserver and
+    /// releases the token once the child exits.
     ///
-    /// This function is required to be called to ensure that a jobserver is
-    /// properly inherited to a child process. If this function is *not* called
-    /// then this [`Client`] will not be accessible in the child process. In
-    /// other words, if not called, then [`Client::from_env`] will return `None`
-    /// in the child process (or the equivalent of [`Client::from_env`] that
-    /// `make` uses).
+    /// This is the most common use of a jobserver by build systems reduced to
+    /// a single call: the calling thread blocks until a token is acquired,
+    /// then `cmd` is configured with [`Client::configure`] and spawned. A
+    /// thread named `thread_name` waits for the child to exit, releases the
+    /// token and yields the child's exit status.
     ///
-    /// ## Platform-specific behavior
+    /// # Errors
     ///
-    /// On Unix and Windows this will clobber the `CARGO_MAKEFLAGS`,
-    /// `MAKEFLAGS` and `MFLAGS` environment variables for the child process,
-    /// and on Unix this will also allow the two file descriptors for
-    /// this client to be inherited to the child.
+    /// Returns an error if acquiring the token, spawning the child or
+    /// spawning the thread fails. In the latter case the child is left
+    /// running but the token is still released before returning.
     ///
-    /// On platforms other than Unix and Windows this panics.
-    pub fn configure_make(&self, cmd: &mut Command) {
-        let value = self.mflags_env();
-        cmd.env("CARGO_MAKEFLAGS", &value);
-        cmd.env("MAKEFLAGS", &value);
-        cmd.env("MFLAGS", &value);
-        self.inner.configure(cmd);
+    /// # Panics
+    ///
+    /// The returned [`JoinHandle`] yields a panic if waiting for the child
+    /// fails.
+    pub fn configure_spawn_on_thread(
+        &self,
+        thread_name: &str,
+        mut cmd: Command,
+    ) -> io::Result<JoinHandle<ExitStatus>> {
+        let token = self.acquire()?;
+        self.configure(&mut cmd);
+        let mut child = cmd.spawn()?;
+        Builder::new().name(thread_name.to_string()).spawn(move || {
+            let status = child.wait().expect("failed to wait for child");
+            drop(token);
+            status
+        })
     }
 
-    fn mflags_env(&self) -> String {
-        let arg = self.inner.string_arg();
-        // Older implementations of make use `--jobserver-fds` and newer
-        // implementations use `--jobserver-auth`, pass both to try to catch
-        // both implementations.
-        format!("-j --jobserver-fds={0} --jobserver-auth={0}", arg)
+    /// Wraps this client so that tokens are acquired at most `max_per_second`
+    /// times per second.
+    ///
+    /// Some jobservers have fairness policies penalizing processes which
+    /// acquire tokens too quickly. The returned [`ThrottledClient`] uses a
+    /// token bucket to space out calls to [`ThrottledClient::acquire`],
+    /// sleeping the calling thread when the rate would otherwise be exceeded.
+    /// The rate can be adjusted later on with [`ThrottledClient::set_rate`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_per_second` isn't a positive finite number.
+    pub fn throttle(&self, max_per_second: f64) -> ThrottledClient {
+        ThrottledClient::new(self.clone(), max_per_second)
     }
 
-    /// Converts this [`Client`] into a helper thread to deal with a blocking
-    /// [`Client::acquire`] function a little more easily.
+    /// Wraps this client so that all operations are reported to `observer`.
     ///
-    /// The fact that the [`Client::acquire`] isn't always the easiest to work
-    /// with. Typically you're using a jobserver to manage running other events
-    /// in parallel! This means that you need to either (a) wait for an existing
-    /// job to finish or (b) wait for a new token to become available.
+    /// See [`Observer`] for the events which are reported. Built-in
+    /// observers include [`MetricsObserver`], collecting statistics, and
+    /// `LogObserver` (with the `log` feature), logging each event. Observers
+    /// can be combined through [`ChainObserver`].
     ///
-    /// Unfortunately the blocking in [`Client::acquire`] happens at the
-    /// implementation layer of jobservers. On Unix this requires a blocking
-    /// call to `read` and on Windows this requires one of the `WaitFor*`
-    /// functions. Both of these situations aren't the easiest to deal with:
+    /// # Examples
     ///
-    /// * On Unix there's basically only one way to wake up a `read` early, and
-    ///   that's through a signal. This is what the `make` implementation
-    ///   itself uses, relying on `SIGCHLD` to wake up a blocking acquisition
-    ///   of a new job token. Unfortunately nonblocking I/O is not an option
-    ///   here, so it means that "waiting for one of two events" means that
-    ///   the latter event must generate a signal! This is not always the case
-    ///   on unix for all jobservers.
+    /// ```
+    /// use jobserver::{Client, MetricsObserver};
     ///
-    /// * On Windows you'd have to basically use the `WaitForMultipleObjects`
-    ///   which means that you've got to canonicalize all your event sources
-    ///   into a `HANDLE` which also isn't the easiest thing to do
-    ///   unfortunately.
+    /// let client = Client::new(4).unwrap().with_observability(MetricsObserver::new());
+    /// drop(client.acquire().unwrap());
+    /// assert_eq!(client.observer().stats().acquires, 1);
+    /// assert_eq!(client.observer().stats().releases, 1);
+    /// ```
+    pub fn with_observability<O: Observer>(&self, observer: O) -> ObservableClient<O> {
+        ObservableClient::new(self.clone(), observer)
+    }
+
+    /// Sets a hook called right before each blocking acquisition of a token.
     ///
-    /// This function essentially attempts to ease these limitations by
-    /// converting this [`Client`] into a helper thread spawned into this
-    /// process. The application can then request that the helper thread
-    /// acquires tokens and the provided closure will be invoked for each token
-    /// acquired.
+    /// The hook is called on the thread acquiring the token, before it
+    /// potentially blocks in [`Client::acquire`] or any of the other blocking
+    /// acquisition functions built on top of it. It replaces any hook set
+    /// before. Hooks are shared by all clones of this client.
     ///
-    /// The intention is that this function can be used to translate the event
-    /// of a token acquisition into an arbitrary user-defined event.
+    /// Together with [`Client::hook_post_acquire`] and
+    /// [`Client::hook_post_release`] this allows integrating the jobserver
+    /// with logging, metrics or progress reporting of the application.
+    pub fn hook_pre_acquire(&self, f: Box<dyn Fn() + Send + Sync>) {
+        self.inner.hooks_mut().pre_acquire = Some(Arc::from(f));
+    }
+
+    /// Sets a hook called right after each successful blocking acquisition
+    /// of a token, with the time spent waiting for it.
     ///
-    /// # Arguments
+    /// See [`Client::hook_pre_acquire`] for more info.
+    pub fn hook_post_acquire(&self, g: Box<dyn Fn(Duration) + Send + Sync>) {
+        self.inner.hooks_mut().post_acquire = Some(Arc::from(g));
+    }
+
+    /// Sets a hook called right after each successful release of a token to
+    /// the jobserver.
     ///
-    /// This function will consume the [`Client`] provided to be transferred to
-    /// the helper thread that is spawned. Additionally a closure `f` is
-    /// provided to be invoked whenever a token is acquired.
+    /// This covers tokens released by dropping an [`Acquired`] as well as
+    /// through [`Client::release_raw`]. See [`Client::hook_pre_acquire`] for
+    /// more info.
+    pub fn hook_post_release(&self, h: Box<dyn Fn() + Send + Sync>) {
+        self.inner.hooks_mut().post_release = Some(Arc::from(h));
+    }
+
+    /// Sets a callback called with the time each token was held for, from
+    /// its acquisition until it was released.
     ///
-    /// This closure is only invoked after calls to
-    /// [`HelperThread::request_token`] have been made and a token itself has
-    /// been acquired. If an error happens while acquiring the token then
-    /// an error will be yielded to the closure as well.
+    /// Only tokens acquired after the callback was set are reported, and only
+    /// once they're released through their [`Acquired`], tokens released
+    /// through [`Client::release_raw`] don't carry a timestamp. It replaces
+    /// any callback set before, and is shared by all clones of this client.
+    /// Together with [`Client::acquire_with_span`] this can be used to gather
+    /// hold time statistics for performance analysis.
+    pub fn observe_releases(&self, callback: Box<dyn Fn(Duration) + Send>) {
+        self.inner.hooks_mut().release_observer = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    /// Removes the callback set by [`Client::observe_releases`].
+    pub fn clear_observe(&self) {
+        self.inner.hooks().release_observer = None;
+    }
+
+    /// Wraps this client so that acquired tokens are wrapped in the guard type
+    /// `G`.
     ///
-    /// # Return Value
+    /// This allows customizing the RAII behavior of tokens, see
+    /// [`GuardTrait`] for the built-in guards. Since the guard type is a type
+    /// parameter, this doesn't add any overhead over using [`Acquired`]
+    /// directly.
     ///
-    /// This function will return an instance of the [`HelperThread`] structure
-    /// which is used to manage the helper thread associated with this client.
-    /// Through the [`HelperThread`] you'll request that tokens are acquired.
-    /// When acquired, the closure provided here is invoked.
+    /// # Examples
     ///
-    /// When the [`HelperThread`] structure is returned it will be gracefully
-    /// torn down, and the calling thread will be blocked until the thread is
-    /// torn down (which should be prompt).
+    /// ```
+    /// use jobserver::{Client, TimedGuard};
     ///
-    /// # Errors
+    /// let client = Client::new(1).unwrap().with_guard_type::<TimedGuard>();
+    /// let guard = client.acquire().unwrap();
+    /// println!("held for {:?}", guard.held());
+    /// ```
+    pub fn with_guard_type<G: GuardTrait>(&self) -> TypedClient<G> {
+        TypedClient::new(self.clone())
+    }
+
+    /// Wraps this client so that every acquire and release is recorded to
+    /// `sink`.
     ///
-    /// This function may fail due to creation of the helper thread or
-    /// auxiliary I/O objects to manage the helper thread. In any of these
-    /// situations the error is propagated upwards.
+    /// Records are written as newline-delimited JSON, see [`AuditClient`] for
+    /// the format. This allows reconstructing the exact sequence of token
+    /// acquisitions after the fact, e.g. to diagnose contention in a build.
     ///
-    /// # Platform-specific behavior
+    /// # Examples
     ///
-    /// On Windows this function behaves pretty normally as expected, but on
-    /// Unix the implementation is... a little heinous. As mentioned above
-    /// we're forced into blocking I/O for token acquisition, namely a blocking
-    /// call to `read`. We must be able to unblock this, however, to tear down
-    /// the helper thread gracefully!
+    /// ```no_run
+    /// use jobserver::Client;
+    /// use std::fs::File;
     ///
-    /// Essentially what happens is that we'll send a signal to the helper
-    /// thread spawned and rely on `EINTR` being returned to wake up the helper
-    /// thread. This involves installing a global `SIGUSR1` handler that does
-    /// nothing along with sending signals to that thread. This may cause
-    /// odd behavior in some applications, so it's recommended to review and
-    /// test thoroughly before using this.
-    pub fn into_helper_thread<F>(self, f: F) -> io::Result<HelperThread>
-    where
-        F: FnMut(io::Result<Acquired>) + Send + 'static,
-    {
-        let state = Arc::new(HelperState::default());
-        Ok(HelperThread {
-            inner: Some(imp::spawn_helper(self, state.clone(), Box::new(f))?),
-            state,
-        })
+    /// let sink = File::create("audit.log").unwrap();
+    /// let client = Client::new(4).unwrap().audit_log(Box::new(sink));
+    /// drop(client.acquire().unwrap());
+    /// ```
+    pub fn audit_log(&self, sink: Box<dyn std::io::Write + Send>) -> AuditClient {
+        AuditClient::new(self.clone(), sink)
+    }
+
+    /// Returns a wrapper of this client which labels every token it acquires
+    /// with the ID of the acquiring process.
+    ///
+    /// This is meant for debugging contention between processes sharing a
+    /// jobserver: [`PidLabeledClient::dump_holders`] lists which processes
+    /// hold tokens acquired through the wrapper, and since when. Nothing is
+    /// recorded for tokens acquired through this client directly, so there's
+    /// no cost unless the wrapper is used.
+    pub fn with_pid_labeling(&self) -> PidLabeledClient {
+        PidLabeledClient::new(self.clone())
+    }
+
+    /// Returns a wrapper of this client which catches releasing more tokens
+    /// than were acquired, e.g. releasing a token twice.
+    ///
+    /// Releasing too many tokens silently grows a jobserver on Unix, while
+    /// on Windows `ReleaseSemaphore` fails with an opaque error once the
+    /// maximum count of the semaphore would be exceeded. The wrapper tracks
+    /// the tokens acquired through [`OverflowProtectedClient::acquire_raw`]
+    /// and fails releases beyond those with [`io::ErrorKind::InvalidInput`]
+    /// instead. `max_release` tokens may be released on top, typically 1 for
+    /// temporarily releasing the implicit token of this process.
+    pub fn with_overflow_protection(&self, max_release: usize) -> OverflowProtectedClient {
+        OverflowProtectedClient::new(self.clone(), max_release)
     }
 
     /// Blocks the current thread until a token is acquired.
@@ -526,13 +4215,96 @@ impl Client {
         self.inner.release(None)?;
         Ok(())
     }
+
+    /// Releases a token previously turned into a [`RawToken`] by
+    /// [`Acquired::into_raw`] back to this jobserver.
+    ///
+    /// Unlike dropping the [`Acquired`] returned by [`Acquired::from_raw`],
+    /// this reports errors which happen while releasing the token.
+    ///
+    /// # Safety
+    ///
+    /// See [`Acquired::from_raw`].
+    pub unsafe fn release_raw_token(&self, raw: RawToken) -> io::Result<()> {
+        if raw.is_soft() {
+            self.inner.soft_held.fetch_sub(1, Ordering::SeqCst);
+            return Ok(());
+        }
+        let data = imp::Acquired::from_raw(raw.data());
+        self.inner.release(Some(&data))
+    }
+
+    /// Releases all the tokens in `tokens`, reporting errors unlike dropping
+    /// them does.
+    ///
+    /// This is handy for releasing tokens collected into a container, e.g.
+    /// through [`Client::try_acquire_many_nonblocking`]. Each token is released back to the
+    /// jobserver it was acquired from, which is usually this one.
+    ///
+    /// # Errors
+    ///
+    /// If releasing a token fails, the remaining tokens are still released
+    /// and the first error is returned. The tokens for which releasing
+    /// failed are lost, so an error means that only some of the tokens may
+    /// have been released.
+    pub fn batch_release_all(&self, tokens: impl IntoIterator<Item = Acquired>) -> io::Result<()> {
+        let mut result = Ok(());
+        for token in tokens {
+            let released = token.release();
+            if result.is_ok() {
+                result = released;
+            }
+        }
+        result
+    }
+
+    /// Blocks until a token is acquired, using only async-signal-safe
+    /// operations.
+    ///
+    /// This is the same as [`Client::acquire_raw`], except that it may be
+    /// called from within a signal handler: it doesn't allocate, take locks
+    /// or panic, and only performs `read` and `poll` system calls.
+    /// Interrupted system calls are retried.
+    ///
+    /// Returns 0 if a token was acquired, which then needs to be released
+    /// through [`Client::release_signal_safe`] or [`Client::release_raw`].
+    /// Otherwise -1 is returned and `errno` is set to indicate the error, with
+    /// `EPIPE` signaling an unexpected EOF on the jobserver pipe.
+    ///
+    /// The byte read from the jobserver isn't preserved, so a `+` is written
+    /// back once the token is released.
+    #[cfg(unix)]
+    pub fn acquire_signal_safe(&self) -> i32 {
+        match &self.inner.imp {
+            Some(imp) => imp.acquire_signal_safe(),
+            None => 0,
+        }
+    }
+
+    /// Releases a token using only async-signal-safe operations.
+    ///
+    /// This is the counterpart to [`Client::acquire_signal_safe`], returning
+    /// 0 on success and otherwise -1 with `errno` set to indicate the error.
+    #[cfg(unix)]
+    pub fn release_signal_safe(&self) -> i32 {
+        match &self.inner.imp {
+            Some(imp) => imp.release_signal_safe(),
+            None => 0,
+        }
+    }
 }
 
 impl Drop for Acquired {
     fn drop(&mut self) {
-        if !self.disabled {
-            drop(self.client.release(Some(&self.data)));
+        if self.disabled {
+            return;
         }
+        if self.soft {
+            self.client.soft_held.fetch_sub(1, Ordering::SeqCst);
+        } else if self.client.release(Some(&self.data)).is_err() {
+            return;
+        }
+        self.observe_release();
     }
 }
 
@@ -541,10 +4313,20 @@ impl Drop for Acquired {
 #[derive(Debug)]
 pub struct HelperThread {
     inner: Option<imp::Helper>,
+    // Unlimited clients never block while acquiring tokens, so they're served
+    // by a plain thread rather than `imp::Helper`.
+    unlimited: Option<JoinHandle<()>>,
+    name: Option<String>,
     state: Arc<HelperState>,
 }
 
 impl HelperThread {
+    /// Returns the name of the helper thread, if it was spawned through
+    /// [`Client::into_helper_thread_named`].
+    pub fn thread_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Request that the helper thread acquires a token, eventually calling the
     /// original closure with a token when it's available.
     ///
@@ -555,6 +4337,65 @@ impl HelperThread {
         self.state.lock().requests += 1;
         self.state.cvar.notify_one();
     }
+
+    /// Same as [`HelperThread::request_token`], but only if the helper
+    /// thread can take on more requests.
+    ///
+    /// Returns `false` without requesting a token if there are already as
+    /// many pending requests as allowed by
+    /// [`HelperThread::set_max_pending`], or if the helper thread has exited.
+    pub fn try_request(&self) -> bool {
+        let mut state = self.state.lock();
+        let full = state.max_pending.map_or(false, |max| state.requests >= max);
+        if full || state.consumer_done {
+            return false;
+        }
+        state.requests += 1;
+        drop(state);
+        self.state.cvar.notify_one();
+        true
+    }
+
+    /// Requests `n` tokens at once, eventually calling the original closure
+    /// once for each of them.
+    ///
+    /// The helper thread acquires the tokens one after another, like for `n`
+    /// calls to [`HelperThread::request_token`], but the request queue is
+    /// only locked once.
+    ///
+    /// Returns `false` without requesting any token if the pending requests
+    /// would exceed the limit set by [`HelperThread::set_max_pending`] or
+    /// overflow, or if the helper thread has exited.
+    pub fn request_n(&self, n: usize) -> bool {
+        let mut state = self.state.lock();
+        let requests = match state.requests.checked_add(n) {
+            Some(requests) if state.max_pending.map_or(true, |max| requests <= max) => requests,
+            _ => return false,
+        };
+        if state.consumer_done {
+            return false;
+        }
+        state.requests = requests;
+        drop(state);
+        // The helper thread waits on the condition variable alone.
+        self.state.cvar.notify_one();
+        true
+    }
+
+    /// Sets the maximum number of pending requests accepted by
+    /// [`HelperThread::try_request`] and [`HelperThread::request_n`].
+    ///
+    /// There's no limit by default. [`HelperThread::request_token`] always
+    /// adds a request regardless of this limit.
+    pub fn set_max_pending(&self, max: usize) {
+        self.state.lock().max_pending = Some(max);
+    }
+
+    /// Returns the number of requests for tokens which the helper thread
+    /// hasn't started to serve yet.
+    pub fn pending(&self) -> usize {
+        self.state.lock().requests
+    }
 }
 
 impl Drop for HelperThread {
@@ -565,6 +4406,37 @@ impl Drop for HelperThread {
         self.state.cvar.notify_one();
 
         // ... and afterwards perform any thread cleanup logic
+        if let Some(inner) = self.inner.take() {
+            inner.join();
+        }
+        if let Some(thread) = self.unlimited.take() {
+            drop(thread.join());
+        }
+    }
+}
+
+/// Structure returned from [`Client::with_auto_release`] to manage the
+/// lifetime of the monitoring thread, see those associated docs for more info.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct AutoReleaseHandle {
+    inner: Option<imp::AutoRelease>,
+}
+
+#[cfg(unix)]
+impl AutoReleaseHandle {
+    /// Stops monitoring the file descriptors, blocking until the monitoring
+    /// thread has exited.
+    ///
+    /// Tokens already released by the monitoring thread are not re-acquired.
+    pub fn cancel(self) {
+        drop(self);
+    }
+}
+
+#[cfg(unix)]
+impl Drop for AutoReleaseHandle {
+    fn drop(&mut self) {
         self.inner.take().unwrap().join();
     }
 }
@@ -629,6 +4501,28 @@ fn find_jobserver_auth(var: &str) -> Option<&str> {
         .and_then(|s| s.split(' ').next())
 }
 
+/// Removes any `--jobserver-auth=` and `--jobserver-fds=` flags, as well as
+/// the flags for which `filter` returns `false`, from the given `MAKEFLAGS`
+/// value and appends `args` to whatever is left.
+///
+/// `filter` is called with the name and value of each flag, split at the
+/// first `=` if any.
+fn merge_jobserver_args(existing: &str, args: &str, filter: impl Fn(&str, &str) -> bool) -> String {
+    let mut value = existing
+        .split(' ')
+        .filter(|flag| !flag.is_empty())
+        .filter(|flag| {
+            !flag.starts_with("--jobserver-auth=") && !flag.starts_with("--jobserver-fds=")
+        })
+        .filter(|flag| {
+            let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+            filter(name, value)
+        })
+        .collect::<Vec<_>>();
+    value.push(args);
+    value.join(" ")
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -701,4 +4595,31 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_merge_jobserver_args() {
+        let args = "--jobserver-fds=3,4 --jobserver-auth=3,4";
+        let cases = [
+            ("", args.to_string()),
+            ("-rs", format!("-rs {args}")),
+            ("-j2 --jobserver-auth=5,6", format!("-j2 {args}")),
+            (
+                " -r  --jobserver-fds=5,6 --jobserver-auth=fifo:/myfifo --foo",
+                format!("-r --foo {args}"),
+            ),
+        ];
+        for (existing, expected) in cases {
+            assert_eq!(merge_jobserver_args(existing, args, |_, _| true), expected);
+        }
+    }
+
+    #[test]
+    fn test_merge_jobserver_args_filter() {
+        let args = "--jobserver-auth=3,4";
+        let existing = "-r --debug=b --jobserver-auth=5,6 -s";
+        let merged = merge_jobserver_args(existing, args, |name, value| {
+            name != "--debug" && value.is_empty()
+        });
+        assert_eq!(merged, format!("-r -s {args}"));
+    }
 }
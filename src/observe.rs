@@ -0,0 +1,292 @@
+use crate::{Acquired, Client};
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Hooks invoked by an [`ObservableClient`] around jobserver operations.
+///
+/// All methods default to doing nothing, so implementations only need to
+/// override the events they're interested in. Observers can be combined with
+/// [`ChainObserver`].
+pub trait Observer: Send + Sync {
+    /// Called after a token was acquired through
+    /// [`ObservableClient::acquire`], with the time spent waiting for it.
+    fn on_acquire(&self, wait_duration: Duration) {
+        let _ = wait_duration;
+    }
+
+    /// Called after a token acquired through an [`ObservableClient`] was
+    /// released.
+    fn on_release(&self) {}
+
+    /// Called after each [`ObservableClient::try_acquire`] which didn't fail,
+    /// with whether a token was acquired.
+    fn on_try_acquire(&self, success: bool) {
+        let _ = success;
+    }
+
+    /// Called whenever an operation of an [`ObservableClient`] fails.
+    fn on_error(&self, err: &io::Error) {
+        let _ = err;
+    }
+}
+
+impl<O: Observer + ?Sized> Observer for Arc<O> {
+    fn on_acquire(&self, wait_duration: Duration) {
+        (**self).on_acquire(wait_duration)
+    }
+
+    fn on_release(&self) {
+        (**self).on_release()
+    }
+
+    fn on_try_acquire(&self, success: bool) {
+        (**self).on_try_acquire(success)
+    }
+
+    fn on_error(&self, err: &io::Error) {
+        (**self).on_error(err)
+    }
+}
+
+/// A [`Client`] which reports all operations to an [`Observer`].
+///
+/// This is created through [`Client::with_observability`]. Methods not
+/// provided by [`ObservableClient`] are passed through to the underlying
+/// [`Client`] without being observed.
+#[derive(Debug)]
+pub struct ObservableClient<O> {
+    client: Client,
+    observer: Arc<O>,
+}
+
+/// An acquired token from an [`ObservableClient`].
+///
+/// This behaves like [`Acquired`], additionally reporting
+/// [`Observer::on_release`] when dropped.
+#[derive(Debug)]
+pub struct ObservedAcquired<O: Observer> {
+    token: Option<Acquired>,
+    observer: Arc<O>,
+}
+
+impl<O: Observer> ObservableClient<O> {
+    pub(crate) fn new(client: Client, observer: O) -> ObservableClient<O> {
+        ObservableClient {
+            client,
+            observer: Arc::new(observer),
+        }
+    }
+
+    /// Returns the observer of this client.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Same as [`Client::acquire`], reporting either
+    /// [`Observer::on_acquire`] or [`Observer::on_error`].
+    pub fn acquire(&self) -> io::Result<ObservedAcquired<O>> {
+        let start = Instant::now();
+        match self.client.acquire() {
+            Ok(token) => {
+                self.observer.on_acquire(start.elapsed());
+                Ok(self.wrap(token))
+            }
+            Err(e) => {
+                self.observer.on_error(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Same as [`Client::try_acquire`], reporting either
+    /// [`Observer::on_try_acquire`] or [`Observer::on_error`].
+    pub fn try_acquire(&self) -> io::Result<Option<ObservedAcquired<O>>> {
+        match self.client.try_acquire() {
+            Ok(token) => {
+                self.observer.on_try_acquire(token.is_some());
+                Ok(token.map(|token| self.wrap(token)))
+            }
+            Err(e) => {
+                self.observer.on_error(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Same as [`Client::release_raw`], reporting either
+    /// [`Observer::on_release`] or [`Observer::on_error`].
+    pub fn release_raw(&self) -> io::Result<()> {
+        match self.client.release_raw() {
+            Ok(()) => {
+                self.observer.on_release();
+                Ok(())
+            }
+            Err(e) => {
+                self.observer.on_error(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stops observing, returning the underlying [`Client`].
+    pub fn into_inner(self) -> Client {
+        self.client
+    }
+
+    fn wrap(&self, token: Acquired) -> ObservedAcquired<O> {
+        ObservedAcquired {
+            token: Some(token),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<O> Deref for ObservableClient<O> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl<O: Observer> ObservedAcquired<O> {
+    /// Stops observing this token, returning the underlying [`Acquired`].
+    ///
+    /// [`Observer::on_release`] will not be reported for it.
+    pub fn into_inner(mut self) -> Acquired {
+        self.token.take().unwrap()
+    }
+}
+
+impl<O: Observer> Drop for ObservedAcquired<O> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            drop(token);
+            self.observer.on_release();
+        }
+    }
+}
+
+/// An [`Observer`] logging all events through the `log` crate at the debug
+/// level.
+///
+/// This requires the `log` feature to be enabled.
+#[cfg(feature = "log")]
+#[derive(Debug, Default)]
+pub struct LogObserver;
+
+#[cfg(feature = "log")]
+impl Observer for LogObserver {
+    fn on_acquire(&self, wait_duration: Duration) {
+        log::debug!("acquired jobserver token after {wait_duration:?}");
+    }
+
+    fn on_release(&self) {
+        log::debug!("released jobserver token");
+    }
+
+    fn on_try_acquire(&self, success: bool) {
+        log::debug!("tried to acquire jobserver token, success: {success}");
+    }
+
+    fn on_error(&self, err: &io::Error) {
+        log::debug!("jobserver operation failed: {err}");
+    }
+}
+
+/// An [`Observer`] collecting statistics about all events.
+///
+/// Use [`MetricsObserver::stats`] to get a snapshot of the statistics.
+#[derive(Debug, Default)]
+pub struct MetricsObserver {
+    // A lock rather than atomics, as 64-bit atomics aren't available on all
+    // targets.
+    stats: Mutex<ClientStats>,
+}
+
+/// Statistics collected by a [`MetricsObserver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientStats {
+    /// Number of tokens acquired through blocking acquisitions.
+    pub acquires: u64,
+    /// Number of tokens released.
+    pub releases: u64,
+    /// Number of non-blocking acquisitions which acquired a token.
+    pub try_acquire_successes: u64,
+    /// Number of non-blocking acquisitions which found no token available.
+    pub try_acquire_failures: u64,
+    /// Number of failed operations.
+    pub errors: u64,
+    /// Total time spent waiting in blocking acquisitions.
+    pub total_wait: Duration,
+}
+
+impl MetricsObserver {
+    /// Creates a new observer with all statistics set to zero.
+    pub fn new() -> MetricsObserver {
+        MetricsObserver::default()
+    }
+
+    /// Returns a snapshot of the statistics collected so far.
+    pub fn stats(&self) -> ClientStats {
+        *self.lock()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ClientStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Observer for MetricsObserver {
+    fn on_acquire(&self, wait_duration: Duration) {
+        let mut stats = self.lock();
+        stats.acquires += 1;
+        stats.total_wait = stats.total_wait.saturating_add(wait_duration);
+    }
+
+    fn on_release(&self) {
+        self.lock().releases += 1;
+    }
+
+    fn on_try_acquire(&self, success: bool) {
+        let mut stats = self.lock();
+        if success {
+            stats.try_acquire_successes += 1;
+        } else {
+            stats.try_acquire_failures += 1;
+        }
+    }
+
+    fn on_error(&self, _err: &io::Error) {
+        self.lock().errors += 1;
+    }
+}
+
+/// An [`Observer`] forwarding all events to two other observers, in order.
+#[derive(Debug, Default)]
+pub struct ChainObserver<A, B>(pub A, pub B);
+
+impl<A: Observer, B: Observer> Observer for ChainObserver<A, B> {
+    fn on_acquire(&self, wait_duration: Duration) {
+        self.0.on_acquire(wait_duration);
+        self.1.on_acquire(wait_duration);
+    }
+
+    fn on_release(&self) {
+        self.0.on_release();
+        self.1.on_release();
+    }
+
+    fn on_try_acquire(&self, success: bool) {
+        self.0.on_try_acquire(success);
+        self.1.on_try_acquire(success);
+    }
+
+    fn on_error(&self, err: &io::Error) {
+        self.0.on_error(err);
+        self.1.on_error(err);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::Client;
+use std::io;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A [`Client`] which refuses to release more tokens than were acquired
+/// through it.
+///
+/// This is created through [`Client::with_overflow_protection`]. Tokens
+/// acquired with [`OverflowProtectedClient::acquire_raw`] are tracked, and
+/// [`OverflowProtectedClient::release_raw`] fails once all of them and up to
+/// `max_release` further tokens were released. Clones share the same count.
+/// Methods not provided by [`OverflowProtectedClient`] are passed through to
+/// the underlying [`Client`] without being tracked, which includes tokens
+/// acquired through [`Client::acquire`], as those can't be released twice.
+#[derive(Clone, Debug)]
+pub struct OverflowProtectedClient {
+    client: Client,
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug)]
+struct Counts {
+    /// Tokens acquired through this client which weren't released yet.
+    held: AtomicUsize,
+    /// Releases left beyond `held`.
+    spare: AtomicUsize,
+}
+
+impl OverflowProtectedClient {
+    pub(crate) fn new(client: Client, max_release: usize) -> OverflowProtectedClient {
+        OverflowProtectedClient {
+            client,
+            counts: Arc::new(Counts {
+                held: AtomicUsize::new(0),
+                spare: AtomicUsize::new(max_release),
+            }),
+        }
+    }
+
+    /// Same as [`Client::acquire_raw`], tracking the token.
+    pub fn acquire_raw(&self) -> io::Result<()> {
+        self.client.acquire_raw()?;
+        self.counts.held.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Same as [`Client::release_raw`], as long as this doesn't release more
+    /// tokens than allowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::InvalidInput`] without
+    /// releasing anything if all tokens acquired through this client and
+    /// the `max_release` further ones were released already, which usually
+    /// means a token was released twice. Errors from [`Client::release_raw`]
+    /// are propagated, in which case the token is still tracked as held.
+    pub fn release_raw(&self) -> io::Result<()> {
+        let counter = if take_one(&self.counts.held) {
+            &self.counts.held
+        } else if take_one(&self.counts.spare) {
+            &self.counts.spare
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "releasing more jobserver tokens than were acquired",
+            ));
+        };
+        self.client.release_raw().map_err(|e| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            e
+        })
+    }
+
+    /// Returns the number of tokens acquired through this client and its
+    /// clones which weren't released yet.
+    pub fn held(&self) -> usize {
+        self.counts.held.load(Ordering::SeqCst)
+    }
+
+    /// Stops tracking tokens, returning the underlying [`Client`].
+    pub fn into_inner(self) -> Client {
+        self.client
+    }
+}
+
+/// Decrements `counter` unless it's zero, returning whether it was
+/// decremented.
+fn take_one(counter: &AtomicUsize) -> bool {
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+}
+
+impl Deref for OverflowProtectedClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
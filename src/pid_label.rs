@@ -0,0 +1,159 @@
+use crate::{Acquired, Client};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+#[cfg(any(unix, windows))]
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// A [`Client`] which labels every token it acquires with the ID of the
+/// acquiring process, for debugging contention between processes.
+///
+/// This is created through [`Client::with_pid_labeling`]. Tokens acquired
+/// through it are recorded in a registry shared by all clones of this
+/// client until they're released, see [`PidLabeledClient::dump_holders`].
+/// Methods not provided by [`PidLabeledClient`] are passed through to the
+/// underlying [`Client`] without being recorded.
+#[derive(Clone)]
+pub struct PidLabeledClient {
+    client: Client,
+    registry: Arc<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    holders: Mutex<HashMap<usize, (u32, Instant)>>,
+    next_label: AtomicUsize,
+}
+
+/// An acquired token from a [`PidLabeledClient`].
+///
+/// This behaves like [`Acquired`], additionally removing its label from the
+/// registry when dropped.
+pub struct PidLabeledAcquired {
+    _token: Acquired,
+    label: usize,
+    pid: u32,
+    acquired_at: Instant,
+    registry: Arc<Registry>,
+}
+
+impl PidLabeledClient {
+    pub(crate) fn new(client: Client) -> PidLabeledClient {
+        PidLabeledClient {
+            client,
+            registry: Arc::default(),
+        }
+    }
+
+    /// Same as [`Client::acquire`], labeling the token.
+    pub fn acquire(&self) -> io::Result<PidLabeledAcquired> {
+        self.client.acquire().map(|token| self.label(token))
+    }
+
+    /// Same as [`Client::try_acquire`], labeling the token.
+    pub fn try_acquire(&self) -> io::Result<Option<PidLabeledAcquired>> {
+        let token = self.client.try_acquire()?;
+        Ok(token.map(|token| self.label(token)))
+    }
+
+    /// Returns the process ID and acquisition time of each token acquired
+    /// through this client and its clones which hasn't been released yet,
+    /// oldest first.
+    pub fn dump_holders(&self) -> Vec<(u32, Instant)> {
+        let mut holders = self
+            .registry
+            .holders()
+            .values()
+            .copied()
+            .collect::<Vec<_>>();
+        holders.sort_by_key(|&(_, acquired_at)| acquired_at);
+        holders
+    }
+
+    /// Stops labeling tokens, returning the underlying [`Client`].
+    ///
+    /// Tokens which were already acquired stay in the registry of the
+    /// remaining clones of this client until they're released.
+    pub fn into_inner(self) -> Client {
+        self.client
+    }
+
+    fn label(&self, token: Acquired) -> PidLabeledAcquired {
+        let label = self.registry.next_label.fetch_add(1, Ordering::Relaxed);
+        let pid = current_pid();
+        let acquired_at = Instant::now();
+        self.registry.holders().insert(label, (pid, acquired_at));
+        PidLabeledAcquired {
+            _token: token,
+            label,
+            pid,
+            acquired_at,
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+// `std::process::id` panics on platforms without processes.
+#[cfg(any(unix, windows))]
+fn current_pid() -> u32 {
+    process::id()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn current_pid() -> u32 {
+    0
+}
+
+impl Registry {
+    fn holders(&self) -> MutexGuard<'_, HashMap<usize, (u32, Instant)>> {
+        self.holders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Deref for PidLabeledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl fmt::Debug for PidLabeledClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PidLabeledClient")
+            .field("client", &self.client)
+            .field("holders", &self.registry.holders().len())
+            .finish()
+    }
+}
+
+impl PidLabeledAcquired {
+    /// Returns the ID of the process which acquired this token.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns when this token was acquired.
+    pub fn acquired_at(&self) -> Instant {
+        self.acquired_at
+    }
+}
+
+impl Drop for PidLabeledAcquired {
+    fn drop(&mut self) {
+        self.registry.holders().remove(&self.label);
+    }
+}
+
+impl fmt::Debug for PidLabeledAcquired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PidLabeledAcquired")
+            .field("pid", &self.pid)
+            .field("acquired_at", &self.acquired_at)
+            .finish_non_exhaustive()
+    }
+}
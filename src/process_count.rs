@@ -0,0 +1,96 @@
+use crate::{imp, Client};
+use std::io;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
+
+/// How often the monitoring thread checks whether the processes are alive.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A jobserver with one token per live process, created through
+/// [`Client::new_from_process_count`].
+///
+/// Each token starts out held on behalf of one of the processes, and a
+/// monitoring thread releases it to the jobserver once that process exits.
+/// Monitoring stops once all processes have exited or this is dropped.
+/// Methods of the underlying [`Client`] are available through [`Deref`].
+#[derive(Debug)]
+pub struct ProcessCountClient {
+    client: Client,
+    live: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<()>>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ProcessCountClient {
+    pub(crate) fn spawn(client: Client, mut pids: Vec<u32>) -> io::Result<ProcessCountClient> {
+        let live = Arc::new(AtomicUsize::new(pids.len()));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let (client2, live2, stop2) = (client.clone(), live.clone(), stop.clone());
+        let thread = Builder::new().spawn(move || {
+            let (lock, cvar) = &*stop2;
+            let mut stopped = lock.lock().unwrap_or_else(|e| e.into_inner());
+            while !*stopped && !pids.is_empty() {
+                pids.retain(|&pid| {
+                    if imp::process_is_alive(pid) {
+                        return true;
+                    }
+                    // Count the process as exited before its token can be
+                    // acquired.
+                    live2.fetch_sub(1, Ordering::SeqCst);
+                    // Keep the process around to retry releasing its token
+                    // on the next check.
+                    if let Err(_e) = client2.release_raw() {
+                        #[cfg(feature = "log")]
+                        log::warn!(
+                            "failed to release jobserver token of exited process {pid}: {_e}"
+                        );
+                        live2.fetch_add(1, Ordering::SeqCst);
+                        return true;
+                    }
+                    false
+                });
+                stopped = cvar
+                    .wait_timeout(stopped, POLL_INTERVAL)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+        })?;
+        Ok(ProcessCountClient {
+            client,
+            live,
+            thread: Some(thread),
+            stop,
+        })
+    }
+
+    /// Returns the number of monitored processes which haven't exited yet,
+    /// as of their last check.
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    /// Returns the underlying [`Client`].
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Deref for ProcessCountClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Drop for ProcessCountClient {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.stop;
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        cvar.notify_one();
+        drop(self.thread.take().unwrap().join());
+    }
+}
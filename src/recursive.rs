@@ -0,0 +1,54 @@
+use crate::{Acquired, Client};
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::thread::{Builder, JoinHandle};
+
+/// Tokens reserved for a child process configured through
+/// [`Client::configure_recursive_jobs_limit`].
+///
+/// The child is given a jobserver of its own, while some tokens of the
+/// parent jobserver are reserved. These are released back to the parent
+/// once this is dropped, which should only happen after the child has
+/// exited. [`RecursiveJobsLimit::release_on_exit`] takes care of that
+/// from a background thread.
+#[derive(Debug)]
+pub struct RecursiveJobsLimit {
+    client: Client,
+    reserved: Vec<Acquired>,
+}
+
+impl RecursiveJobsLimit {
+    pub(crate) fn new(client: Client, reserved: Vec<Acquired>) -> RecursiveJobsLimit {
+        RecursiveJobsLimit { client, reserved }
+    }
+
+    /// Returns the jobserver the child process was configured with.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the number of tokens reserved in the parent jobserver.
+    pub fn reserved(&self) -> usize {
+        self.reserved.len()
+    }
+
+    /// Spawns a thread waiting for `child` to exit, releasing the reserved
+    /// tokens afterwards.
+    ///
+    /// The returned [`JoinHandle`] yields the exit status of the child.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread couldn't be spawned, in which case the
+    /// tokens are released right away.
+    pub fn release_on_exit(
+        self,
+        mut child: Child,
+    ) -> io::Result<JoinHandle<io::Result<ExitStatus>>> {
+        Builder::new().spawn(move || {
+            let status = child.wait();
+            drop(self);
+            status
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use crate::Client;
+use std::io;
+use std::thread::{Builder, Scope, ScopedJoinHandle};
+
+/// A scope for spawning threads which each hold a token while running.
+///
+/// This is created through [`Client::scope`], see those docs for more info.
+#[derive(Debug)]
+pub struct ClientScope<'scope, 'env: 'scope> {
+    client: &'scope Client,
+    scope: &'scope Scope<'scope, 'env>,
+}
+
+impl<'scope, 'env> ClientScope<'scope, 'env> {
+    pub(crate) fn new(client: &'scope Client, scope: &'scope Scope<'scope, 'env>) -> Self {
+        ClientScope { client, scope }
+    }
+
+    /// Acquires a token and then spawns a scoped thread running `f`.
+    ///
+    /// The calling thread blocks until a token is available. The token is
+    /// moved into the spawned thread and released once `f` returns or
+    /// panics. Like [`Scope::spawn`], `f` may borrow anything outliving the
+    /// scope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if acquiring the token or spawning the thread fails.
+    /// In the latter case the token is released before returning.
+    pub fn spawn<F, T>(&self, f: F) -> io::Result<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let token = self.client.acquire()?;
+        Builder::new().spawn_scoped(self.scope, move || {
+            let _token = token;
+            f()
+        })
+    }
+
+    /// Returns the client tokens are acquired from.
+    pub fn client(&self) -> &'scope Client {
+        self.client
+    }
+}
@@ -0,0 +1,108 @@
+use crate::Acquired;
+use std::panic::Location;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+/// Tokens held for longer than this are logged as warnings on release.
+#[cfg(feature = "log")]
+const LONG_HOLD: Duration = Duration::from_secs(30);
+
+/// An acquired token labeled with the name of the code holding it.
+///
+/// This is created through [`Client::acquire_with_span`] and behaves like
+/// [`Acquired`]. With the `log` feature enabled, releasing the token logs
+/// for how long it was held together with the span name, at the debug level
+/// or as a warning if it was held for more than 30 seconds.
+///
+/// [`Client::acquire_with_span`]: crate::Client::acquire_with_span
+#[derive(Debug)]
+pub struct SpannedAcquired {
+    token: Option<Acquired>,
+    span_name: &'static str,
+    thread: ThreadId,
+    acquired_at: Instant,
+    /// Where the token was acquired, see [`crate::InstrumentedAcquired`].
+    location: Option<&'static Location<'static>>,
+    /// Hold time above which the release is logged as a warning.
+    #[cfg(feature = "log")]
+    long_hold: Duration,
+}
+
+impl SpannedAcquired {
+    pub(crate) fn new(token: Acquired, span_name: &'static str) -> SpannedAcquired {
+        SpannedAcquired {
+            token: Some(token),
+            span_name,
+            thread: thread::current().id(),
+            acquired_at: Instant::now(),
+            location: None,
+            #[cfg(feature = "log")]
+            long_hold: LONG_HOLD,
+        }
+    }
+
+    /// Includes `location` when logging the release of this token.
+    pub(crate) fn set_location(&mut self, location: &'static Location<'static>) {
+        self.location = Some(location);
+    }
+
+    /// Changes the hold time above which the release of this token is logged
+    /// as a warning.
+    #[cfg(feature = "log")]
+    pub(crate) fn set_long_hold(&mut self, long_hold: Duration) {
+        self.long_hold = long_hold;
+    }
+
+    /// Returns the name of the span this token was acquired for.
+    pub fn span_name(&self) -> &'static str {
+        self.span_name
+    }
+
+    /// Returns the ID of the thread which acquired this token.
+    pub fn thread_id(&self) -> ThreadId {
+        self.thread
+    }
+
+    /// Returns when this token was acquired.
+    pub fn acquired_at(&self) -> Instant {
+        self.acquired_at
+    }
+
+    /// Returns for how long this token has been held so far.
+    pub fn elapsed(&self) -> Duration {
+        self.acquired_at.elapsed()
+    }
+
+    /// Returns the underlying [`Acquired`] token.
+    ///
+    /// Its release will not be logged.
+    pub fn into_inner(mut self) -> Acquired {
+        self.token.take().unwrap()
+    }
+}
+
+impl Drop for SpannedAcquired {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            drop(token);
+            #[cfg(feature = "log")]
+            {
+                let held = self.elapsed();
+                let (span, thread) = (self.span_name, self.thread);
+                let at = match self.location {
+                    Some(location) => format!(" at {location}"),
+                    None => String::new(),
+                };
+                if held > self.long_hold {
+                    log::warn!(
+                        "span `{span}`{at} on {thread:?} held a jobserver token for {held:?}"
+                    );
+                } else {
+                    log::debug!(
+                        "span `{span}`{at} on {thread:?} held a jobserver token for {held:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{Client, ClientInner};
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{Builder, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Structure returned from [`Client::with_on_starvation`] to manage the
+/// lifetime of the monitor thread, see those associated docs for more info.
+#[derive(Debug)]
+pub struct StarvationMonitorHandle {
+    thread: Option<JoinHandle<()>>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    client: Arc<ClientInner>,
+}
+
+impl StarvationMonitorHandle {
+    pub(crate) fn spawn(
+        client: Client,
+        threshold: Duration,
+        callback: Box<dyn Fn() + Send>,
+    ) -> io::Result<StarvationMonitorHandle> {
+        let interval = (threshold / 2).max(Duration::from_millis(1));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let stop2 = stop.clone();
+        let inner = client.inner.clone();
+        // Start times of acquisitions are only tracked while a monitor runs.
+        inner.starvation_monitors.fetch_add(1, Ordering::SeqCst);
+        let spawned = Builder::new().spawn(move || {
+            let (lock, cvar) = &*stop2;
+            let mut stopped = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let mut next = Instant::now() + interval;
+            while !*stopped {
+                let now = Instant::now();
+                if now < next {
+                    stopped = cvar
+                        .wait_timeout(stopped, next - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                    continue;
+                }
+                next = now + interval;
+                let starved = client.inner.oldest_acquire().map_or(false, |start| {
+                    now.saturating_duration_since(start) > threshold
+                });
+                if starved {
+                    // Don't block `stop` while the callback runs.
+                    drop(stopped);
+                    callback();
+                    stopped = lock.lock().unwrap_or_else(|e| e.into_inner());
+                }
+            }
+        });
+        let thread = match spawned {
+            Ok(thread) => thread,
+            Err(e) => {
+                inner.starvation_monitors.fetch_sub(1, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        Ok(StarvationMonitorHandle {
+            thread: Some(thread),
+            stop,
+            client: inner,
+        })
+    }
+
+    /// Stops the monitor, blocking until the monitor thread has exited.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for StarvationMonitorHandle {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.stop;
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        cvar.notify_one();
+        drop(self.thread.take().unwrap().join());
+        self.client
+            .starvation_monitors
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+/// Snapshot of the configuration and state of a [`Client`], returned by
+/// [`Client::dump_state`].
+///
+/// With the `serde` feature enabled this implements `serde::Serialize` for
+/// machine-readable output, and [`Display`](fmt::Display) gives a
+/// human-readable summary.
+///
+/// [`Client`]: crate::Client
+/// [`Client::dump_state`]: crate::Client::dump_state
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct ClientState {
+    /// Number of tokens currently available, if it can be determined.
+    pub available: Option<usize>,
+    /// Total number of tokens, known only for jobservers created by this
+    /// process.
+    pub capacity: Option<usize>,
+    /// Kind of the jobserver: `unix-pipe`, `unix-fifo`, `windows-semaphore`,
+    /// `in-process` on platforms without cross-process support, or
+    /// `unlimited` for clients created through [`Client::new_unlimited`].
+    ///
+    /// [`Client::new_unlimited`]: crate::Client::new_unlimited
+    pub platform: String,
+    /// Platform-specific identifier of the jobserver: `fd:<read>,<write>`
+    /// for pipes, `fifo:<path>` for named fifos, the semaphore name on
+    /// Windows, and empty otherwise.
+    pub identifier: String,
+    /// Whether the jobserver was created outside of this process, for
+    /// example inherited through [`Client::from_env`].
+    ///
+    /// [`Client::from_env`]: crate::Client::from_env
+    pub is_inherited: bool,
+}
+
+impl fmt::Display for ClientState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.platform)?;
+        if !self.identifier.is_empty() {
+            write!(f, " {}", self.identifier)?;
+        }
+        if self.is_inherited {
+            write!(f, " (inherited)")?;
+        }
+        if let Some(available) = self.available {
+            write!(f, ", {available}")?;
+            match self.capacity {
+                Some(capacity) => write!(f, "/{capacity}")?,
+                None => write!(f, "/?")?,
+            }
+            write!(f, " tokens available")?;
+        }
+        Ok(())
+    }
+}
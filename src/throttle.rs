@@ -0,0 +1,97 @@
+use crate::{Acquired, Client};
+use std::io;
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A [`Client`] which limits how often tokens are acquired.
+///
+/// This is created through [`Client::throttle`], see those docs for more
+/// info. All methods other than [`ThrottledClient::acquire`] are passed
+/// through to the underlying [`Client`] without any throttling.
+#[derive(Debug)]
+pub struct ThrottledClient {
+    client: Client,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    credit: f64,
+    last_acquire: Instant,
+}
+
+impl ThrottledClient {
+    pub(crate) fn new(client: Client, max_per_second: f64) -> ThrottledClient {
+        check_rate(max_per_second);
+        ThrottledClient {
+            client,
+            bucket: Mutex::new(Bucket {
+                rate: max_per_second,
+                credit: 1.0,
+                last_acquire: Instant::now(),
+            }),
+        }
+    }
+
+    /// Acquires a token from the underlying jobserver, first sleeping if
+    /// needed to stay within the configured rate.
+    ///
+    /// Concurrent calls are throttled together, so the rate applies to this
+    /// [`ThrottledClient`] as a whole rather than to each thread.
+    ///
+    /// # Errors
+    ///
+    /// Any error from [`Client::acquire`] is propagated.
+    pub fn acquire(&self) -> io::Result<Acquired> {
+        {
+            // The lock is held while sleeping on purpose, that's what spaces
+            // out concurrent acquisitions.
+            let mut bucket = self.lock();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_acquire).as_secs_f64();
+            bucket.credit = (bucket.credit + elapsed * bucket.rate).min(1.0);
+            bucket.last_acquire = now;
+            if bucket.credit < 1.0 {
+                let wait = (1.0 - bucket.credit) / bucket.rate;
+                thread::sleep(Duration::from_secs_f64(wait));
+                bucket.credit = 0.0;
+                bucket.last_acquire = Instant::now();
+            } else {
+                bucket.credit -= 1.0;
+            }
+        }
+        self.client.acquire()
+    }
+
+    /// Changes the maximum number of acquisitions per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_per_second` isn't a positive finite number.
+    pub fn set_rate(&self, max_per_second: f64) {
+        check_rate(max_per_second);
+        self.lock().rate = max_per_second;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Deref for ThrottledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+fn check_rate(max_per_second: f64) {
+    assert!(
+        max_per_second > 0.0 && max_per_second.is_finite(),
+        "throttle rate must be a positive finite number, found {max_per_second}"
+    );
+}
@@ -35,6 +35,16 @@ pub struct Acquired {
     byte: u8,
 }
 
+impl Acquired {
+    pub fn to_raw(&self) -> u64 {
+        self.byte.into()
+    }
+
+    pub fn from_raw(raw: u64) -> Acquired {
+        Acquired { byte: raw as u8 }
+    }
+}
+
 impl Client {
     pub fn new(mut limit: usize) -> io::Result<Client> {
         let client = unsafe { Client::mk()? };
@@ -211,12 +221,20 @@ impl Client {
         }
     }
 
-    pub fn acquire(&self) -> io::Result<Acquired> {
-        // Ignore interrupts and keep trying if that happens
+    pub fn acquire(&self, max_eintr_retries: usize) -> io::Result<Acquired> {
+        // Keep trying on interrupts until we run out of retries
+        let mut retries = 0;
         loop {
             if let Some(token) = self.acquire_allow_interrupts()? {
                 return Ok(token);
             }
+            if retries == max_eintr_retries {
+                return Err(io::Error::from_raw_os_error(libc::EINTR));
+            }
+            // `usize::MAX` retries forever
+            if max_eintr_retries != usize::MAX {
+                retries += 1;
+            }
         }
     }
 
@@ -344,6 +362,25 @@ impl Client {
         }
     }
 
+    /// Waits until a token can be read without blocking or `timeout` has
+    /// elapsed, without acquiring it. Returns whether it became readable.
+    pub fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.read().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            -1 => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => Ok(false),
+                e => Err(e),
+            },
+            // Errors like `POLLHUP` are reported by reading.
+            n => Ok(n > 0),
+        }
+    }
+
     pub fn release(&self, data: Option<&Acquired>) -> io::Result<()> {
         // Note that the fd may be nonblocking but we're going to go ahead
         // and assume that the writes here are always nonblocking (we can
@@ -359,6 +396,51 @@ impl Client {
         }
     }
 
+    pub fn acquire_signal_safe(&self) -> c_int {
+        // Only raw syscalls below, as `std`'s wrappers may lock or allocate.
+        let fd = self.read().as_raw_fd();
+        let mut byte = 0u8;
+        loop {
+            match unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) } {
+                1 => return 0,
+                0 => {
+                    unsafe { set_errno(libc::EPIPE) };
+                    return -1;
+                }
+                _ => match errno() {
+                    libc::EINTR => continue,
+                    e if e == libc::EAGAIN || e == libc::EWOULDBLOCK => {}
+                    _ => return -1,
+                },
+            }
+
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, -1) } == -1 && errno() != libc::EINTR {
+                return -1;
+            }
+        }
+    }
+
+    pub fn release_signal_safe(&self) -> c_int {
+        let fd = self.write().as_raw_fd();
+        let byte = b'+';
+        loop {
+            match unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) } {
+                1 => return 0,
+                0 => {
+                    unsafe { set_errno(libc::EPIPE) };
+                    return -1;
+                }
+                _ if errno() == libc::EINTR => continue,
+                _ => return -1,
+            }
+        }
+    }
+
     pub fn string_arg(&self) -> String {
         match self {
             Client::Pipe { read, write } => format!("{},{}", read.as_raw_fd(), write.as_raw_fd()),
@@ -366,12 +448,67 @@ impl Client {
         }
     }
 
+    /// Returns the `--jobserver-auth=` value for Ninja, which only supports
+    /// named fifos.
+    pub fn ninja_arg(&self) -> io::Result<String> {
+        match self {
+            Client::Pipe { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ninja only supports jobservers backed by a named fifo",
+            )),
+            Client::Fifo { .. } => Ok(self.string_arg()),
+        }
+    }
+
+    pub fn describe(&self) -> (&'static str, String) {
+        match self {
+            Client::Pipe { read, write } => (
+                "unix-pipe",
+                format!("fd:{},{}", read.as_raw_fd(), write.as_raw_fd()),
+            ),
+            Client::Fifo { path, .. } => ("unix-fifo", format!("fifo:{}", path.display())),
+        }
+    }
+
     pub fn available(&self) -> io::Result<usize> {
         let mut len = MaybeUninit::<c_int>::uninit();
         cvt(unsafe { libc::ioctl(self.read().as_raw_fd(), libc::FIONREAD, len.as_mut_ptr()) })?;
         Ok(unsafe { len.assume_init() } as usize)
     }
 
+    pub fn max_capacity(&self) -> Option<usize> {
+        // Pipes have no maximum number of tokens other than their buffer size.
+        None
+    }
+
+    pub fn is_cross_process(&self) -> bool {
+        // Anonymous pipes can only be inherited, whereas a named fifo can be
+        // opened by anyone knowing its path.
+        matches!(self, Client::Fifo { .. })
+    }
+
+    /// Checks that the file descriptors of this client are still open and,
+    /// for fifos, that children can still open it by path.
+    pub fn verify(&self) -> io::Result<()> {
+        match self {
+            Client::Pipe { read, write } => {
+                for fd in [read.as_raw_fd(), write.as_raw_fd()] {
+                    cvt(unsafe { libc::fcntl(fd, libc::F_GETFD) })?;
+                }
+            }
+            Client::Fifo { file, path, .. } => {
+                cvt(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFD) })?;
+                if !std::fs::metadata(path)?.file_type().is_fifo() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is not a fifo", path.display()),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn configure(&self, cmd: &mut Command) {
         match self {
             // We `File::open`ed it when inheriting from environment,
@@ -393,6 +530,124 @@ impl Client {
             });
         }
     }
+
+    /// Closes the file descriptors of this client, reporting errors which
+    /// dropping it would ignore.
+    pub fn close(self) -> io::Result<()> {
+        fn close_file(file: File) -> io::Result<()> {
+            cvt(unsafe { libc::close(file.into_raw_fd()) })?;
+            Ok(())
+        }
+
+        match self {
+            Client::Pipe { read, write } => {
+                let read = close_file(read);
+                close_file(write).and(read)
+            }
+            Client::Fifo { file, .. } => close_file(file),
+        }
+    }
+
+    /// Registers a duplicate of the read end with the Tokio reactor, to
+    /// await it becoming readable.
+    ///
+    /// The file descriptor is duplicated so that several futures can wait on
+    /// the same client at once, as each registration needs its own.
+    #[cfg(feature = "tokio")]
+    pub fn async_fd(&self) -> io::Result<tokio::io::unix::AsyncFd<File>> {
+        tokio::io::unix::AsyncFd::with_interest(
+            self.read().try_clone()?,
+            tokio::io::Interest::READABLE,
+        )
+    }
+
+    pub fn set_close_on_exec(&self, close: bool) -> io::Result<()> {
+        set_cloexec(self.read().as_raw_fd(), close)?;
+        set_cloexec(self.write().as_raw_fd(), close)
+    }
+
+    /// Returns the file descriptors a child needs to inherit to use this
+    /// jobserver.
+    pub fn inherited_fds(&self) -> Vec<c_int> {
+        match self {
+            Client::Pipe { read, write } => vec![read.as_raw_fd(), write.as_raw_fd()],
+            Client::Fifo { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Blocks until a token was acquired from any of `clients`, returning the
+/// index of the client it was acquired from.
+pub(crate) fn acquire_select(clients: &[&Client]) -> io::Result<(usize, Acquired)> {
+    let mut fds = clients
+        .iter()
+        .map(|client| libc::pollfd {
+            fd: client.read().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect::<Vec<_>>();
+    loop {
+        for fd in fds.iter_mut() {
+            fd.revents = 0;
+        }
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        for (i, fd) in fds.iter().enumerate() {
+            if fd.revents == 0 {
+                continue;
+            }
+            match clients[i].try_acquire() {
+                Ok(Some(data)) => return Ok((i, data)),
+                // Someone else was faster, wait for the next token.
+                Ok(None) => {}
+                // Without non-blocking reads we'd have to block on a single
+                // pipe, which is only fine if there's no other one to wait
+                // for.
+                Err(e) if e.kind() == io::ErrorKind::Unsupported && clients.len() == 1 => {
+                    return Ok((i, clients[i].acquire(usize::MAX)?));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Makes `cmd` close all file descriptors not in `keep` before it execs,
+/// except for stdio.
+pub(crate) fn close_fds_except(cmd: &mut Command, keep: Vec<c_int>) {
+    unsafe {
+        cmd.pre_exec(move || {
+            // Same as `getdtablesize()`, which isn't available everywhere.
+            let max = match libc::sysconf(libc::_SC_OPEN_MAX) {
+                n if n > 0 => c_int::try_from(n).unwrap_or(c_int::MAX),
+                _ => 1024,
+            };
+            for fd in 3..max {
+                if keep.contains(&fd) {
+                    continue;
+                }
+                // Descriptors with `CLOEXEC` are closed by `exec` anyway, and
+                // one of them is used by `Command` to report `exec` errors.
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags != -1 && flags & libc::FD_CLOEXEC == 0 {
+                    libc::close(fd);
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+impl AsFd for Client {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.read().as_fd()
+    }
 }
 
 #[derive(Debug)]
@@ -402,6 +657,7 @@ pub struct Helper {
 }
 
 pub(crate) fn spawn_helper(
+    builder: Builder,
     client: crate::Client,
     state: Arc<super::HelperState>,
     mut f: Box<dyn FnMut(io::Result<crate::Acquired>) + Send>,
@@ -416,7 +672,7 @@ pub(crate) fn spawn_helper(
         }
         #[cfg(not(target_os = "aix"))]
         {
-            new.sa_sigaction = sigusr1_handler as usize;
+            new.sa_sigaction = sigusr1_handler as *const () as usize;
         }
         new.sa_flags = libc::SA_SIGINFO as _;
         if libc::sigaction(libc::SIGUSR1, &new, ptr::null_mut()) != 0 {
@@ -429,14 +685,18 @@ pub(crate) fn spawn_helper(
     }
 
     let state2 = state.clone();
-    let thread = Builder::new().spawn(move || {
+    let thread = builder.spawn(move || {
+        // Unlimited clients are served by `Client::into_helper_thread` itself.
+        let imp = client.inner.imp.as_ref().unwrap();
         state2.for_each_request(|helper| loop {
-            match client.inner.acquire_allow_interrupts() {
+            match imp.acquire_allow_interrupts() {
                 Ok(Some(data)) => {
                     break f(Ok(crate::Acquired {
                         client: client.inner.clone(),
                         data,
                         disabled: false,
+                        soft: false,
+                        acquired_at: client.inner.observe_start(),
                     }));
                 }
                 Err(e) => break f(Err(e)),
@@ -496,6 +756,92 @@ impl Helper {
     }
 }
 
+#[derive(Debug)]
+pub struct AutoRelease {
+    thread: JoinHandle<()>,
+    wake: File,
+    /// Read end of `wake`, shared with the thread so that it stays open for
+    /// as long as either may use it.
+    wake_read: Arc<File>,
+}
+
+pub(crate) fn spawn_auto_release(
+    client: crate::Client,
+    monitor_fds: &[RawFd],
+) -> io::Result<AutoRelease> {
+    // A private pipe whose only purpose is to wake up the monitoring thread
+    // when we're cancelled, the thread is otherwise blocked in `poll`
+    // indefinitely.
+    let (wake_read, wake) = match unsafe { Client::mk()? } {
+        Client::Pipe { read, write } => (read, write),
+        Client::Fifo { .. } => unreachable!(),
+    };
+    let wake_read = Arc::new(wake_read);
+    let wake_fd = wake_read.as_raw_fd();
+
+    let mut fds = vec![libc::pollfd {
+        fd: wake_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    // `POLLHUP`, `POLLERR` and `POLLNVAL` are always reported, so we don't
+    // request any events for the monitored file descriptors.
+    fds.extend(monitor_fds.iter().map(|&fd| libc::pollfd {
+        fd,
+        events: 0,
+        revents: 0,
+    }));
+
+    let thread_wake_read = wake_read.clone();
+    let thread = Builder::new().spawn(move || {
+        let _wake_read = thread_wake_read;
+        while fds.len() > 1 {
+            for fd in fds.iter_mut() {
+                fd.revents = 0;
+            }
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } == -1 {
+                match io::Error::last_os_error().kind() {
+                    io::ErrorKind::Interrupted => continue,
+                    _ => break,
+                }
+            }
+            if fds[0].revents != 0 {
+                break;
+            }
+            // Each file descriptor that went away accounts for exactly one
+            // token, and is no longer watched afterwards.
+            fds.retain(|fd| {
+                if fd.fd == wake_fd {
+                    return true;
+                }
+                if fd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) == 0 {
+                    return true;
+                }
+                drop(client.release_raw());
+                false
+            });
+        }
+    })?;
+
+    Ok(AutoRelease {
+        thread,
+        wake,
+        wake_read,
+    })
+}
+
+impl AutoRelease {
+    pub fn join(self) {
+        // This wakes up the `poll` above. If the thread has already exited
+        // because all the file descriptors were closed then the byte just
+        // stays in the pipe, whose read end is still open through
+        // `wake_read` so that this can't raise `SIGPIPE`.
+        drop((&self.wake).write(&[0]));
+        drop(self.thread.join());
+        drop(self.wake_read);
+    }
+}
+
 unsafe fn fcntl_check(fd: c_int) -> Result<(), FromEnvErrorInner> {
     match libc::fcntl(fd, libc::F_GETFD) {
         -1 => Err(FromEnvErrorInner::CannotOpenFd(
@@ -531,6 +877,423 @@ unsafe fn fd_check(fd: c_int, check_pipe: bool) -> Result<(), FromEnvErrorInner>
     }
 }
 
+/// Raises the scheduling priority of the calling thread to `SCHED_FIFO`
+/// with a given priority, restoring the previous policy once dropped.
+#[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+pub(crate) struct RaisedPriority {
+    policy: c_int,
+    param: libc::sched_param,
+}
+
+#[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+impl RaisedPriority {
+    pub(crate) fn new(priority: u8) -> io::Result<RaisedPriority> {
+        let priority = c_int::from(priority);
+        let (min, max) = unsafe {
+            (
+                libc::sched_get_priority_min(libc::SCHED_FIFO),
+                libc::sched_get_priority_max(libc::SCHED_FIFO),
+            )
+        };
+        if priority < min || priority > max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("SCHED_FIFO priority must be within {min}..={max}, got {priority}"),
+            ));
+        }
+        let thread = unsafe { libc::pthread_self() };
+        let mut policy = 0;
+        let mut param = libc::sched_param { sched_priority: 0 };
+        cvt_pthread(unsafe { libc::pthread_getschedparam(thread, &mut policy, &mut param) })?;
+        let raised = libc::sched_param {
+            sched_priority: priority,
+        };
+        cvt_pthread(unsafe { libc::pthread_setschedparam(thread, libc::SCHED_FIFO, &raised) })?;
+        Ok(RaisedPriority { policy, param })
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+impl Drop for RaisedPriority {
+    fn drop(&mut self) {
+        unsafe {
+            libc::pthread_setschedparam(libc::pthread_self(), self.policy, &self.param);
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+fn cvt_pthread(r: c_int) -> io::Result<()> {
+    if r == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(r))
+    }
+}
+
+/// Returns whether the process `pid` exists and hasn't exited yet.
+pub(crate) fn process_is_alive(pid: u32) -> bool {
+    // A pid of zero would target our own process group in `kill`.
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+
+    // On Linux a zombie still accepts signals, so consult procfs for the
+    // process state first, falling back to `kill` if procfs is unavailable.
+    #[cfg(target_os = "linux")]
+    match std::fs::read_to_string(format!("/proc/{pid}/status")) {
+        Ok(status) => {
+            return !status
+                .lines()
+                .any(|line| line.starts_with("State:") && line[6..].trim_start().starts_with('Z'))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return false,
+        Err(_) => {}
+    }
+
+    // Signal 0 only checks whether the process could be signaled, and
+    // `EPERM` means it exists but belongs to someone else.
+    unsafe {
+        libc::kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+/// Sends `client` over the Unix domain socket `socket_fd`, passing its file
+/// descriptors as `SCM_RIGHTS`. `None` stands for an unlimited client.
+///
+/// The message consists of a tag byte, `p` for pipes, `f` for fifos followed
+/// by their path and `n` for unlimited clients.
+pub(crate) fn send_client(socket_fd: c_int, client: Option<&Client>) -> io::Result<()> {
+    let (payload, fds) = match client {
+        Some(Client::Pipe { read, write }) => {
+            (b"p".to_vec(), vec![read.as_raw_fd(), write.as_raw_fd()])
+        }
+        Some(Client::Fifo { file, path, .. }) => {
+            let mut payload = b"f".to_vec();
+            payload.extend_from_slice(path.as_os_str().as_bytes());
+            (payload, vec![file.as_raw_fd()])
+        }
+        None => (b"n".to_vec(), Vec::new()),
+    };
+
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    // Large enough for two file descriptors on all platforms, and aligned
+    // for `cmsghdr`.
+    let mut cmsg_buf = [0u64; 8];
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            let fds_len = (fds.len() * mem::size_of::<c_int>()) as u32;
+            msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+            msg.msg_controllen = libc::CMSG_SPACE(fds_len) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = libc::MSG_NOSIGNAL;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = 0;
+        cvt_ssize(libc::sendmsg(socket_fd, &msg, flags))?;
+    }
+    Ok(())
+}
+
+/// Receives a client sent with [`send_client`] from the Unix domain socket
+/// `socket_fd`.
+pub(crate) fn recv_client(socket_fd: c_int) -> io::Result<Option<Client>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut payload = [0u8; 4096];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr().cast(),
+        iov_len: payload.len(),
+    };
+    let mut cmsg_buf = [0u64; 8];
+    let mut fds = Vec::new();
+    let n = unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&cmsg_buf) as _;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = libc::MSG_CMSG_CLOEXEC;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = 0;
+        let n = cvt_ssize(libc::recvmsg(socket_fd, &mut msg, flags))? as usize;
+
+        // Take ownership of everything we received first, so that nothing
+        // leaks if the message turns out to be invalid.
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let len = (*cmsg).cmsg_len as usize - (data as usize - cmsg as usize);
+                for i in 0..len / mem::size_of::<c_int>() {
+                    let fd = ptr::read_unaligned(data.cast::<c_int>().add(i));
+                    fds.push(File::from_raw_fd(fd));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        if msg.msg_flags & (libc::MSG_CTRUNC | libc::MSG_TRUNC) != 0 {
+            return Err(invalid("jobserver message was truncated"));
+        }
+        n
+    };
+    for fd in &fds {
+        set_cloexec(fd.as_raw_fd(), true)?;
+    }
+
+    let mut fds = fds.into_iter();
+    match (&payload[..n], fds.len()) {
+        ([b'p'], 2) => Ok(Some(Client::Pipe {
+            read: fds.next().unwrap(),
+            write: fds.next().unwrap(),
+        })),
+        ([b'f', path @ ..], 1) => Ok(Some(Client::Fifo {
+            file: fds.next().unwrap(),
+            path: PathBuf::from(std::ffi::OsStr::from_bytes(path)),
+            is_non_blocking: AtomicBool::new(false),
+        })),
+        ([b'n'], 0) => Ok(None),
+        ([], _) => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "socket closed before receiving a jobserver",
+        )),
+        _ => Err(invalid("invalid jobserver message")),
+    }
+}
+
+/// Returns the system load average over the last minute.
+pub(crate) fn load_average() -> io::Result<f64> {
+    #[cfg(target_os = "linux")]
+    {
+        let loadavg = std::fs::read_to_string("/proc/loadavg")?;
+        loadavg
+            .split_whitespace()
+            .next()
+            .and_then(|load| load.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid /proc/loadavg contents: {loadavg:?}"),
+                )
+            })
+    }
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    {
+        let mut load = 0.0;
+        if unsafe { libc::getloadavg(&mut load, 1) } != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to get the load average",
+            ));
+        }
+        Ok(load)
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "load average is not supported on this platform",
+        ))
+    }
+}
+
+/// Returns the number of CPUs which are online in the system.
+pub(crate) fn online_cpus() -> io::Result<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        let online = match std::fs::read_to_string("/sys/devices/system/cpu/online") {
+            Ok(online) => parse_cpu_list(&online),
+            Err(_) => None,
+        };
+        if let Some(cpus) = online {
+            return Ok(cpus);
+        }
+        let cpuinfo = std::fs::read_to_string("/proc/cpuinfo")?;
+        let cpus = cpuinfo
+            .lines()
+            .filter(|line| line.split(':').next().map(str::trim) == Some("processor"))
+            .count();
+        if cpus == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no processors found in /proc/cpuinfo",
+            ));
+        }
+        Ok(cpus)
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        let mut cpus: libc::c_int = 0;
+        let mut len = mem::size_of_val(&cpus);
+        let r = unsafe {
+            libc::sysctlbyname(
+                b"hw.logicalcpu\0".as_ptr().cast(),
+                (&mut cpus as *mut libc::c_int).cast(),
+                &mut len,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if r != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(cpus.max(1) as usize)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+    {
+        let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+        if cpus < 1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(cpus as usize)
+    }
+}
+
+/// Returns the maximum number of processes the current user may create, or
+/// `None` if that's unlimited or unknown.
+pub(crate) fn process_limit() -> Option<usize> {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    {
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_NPROC, &mut rlimit) } != 0
+            || rlimit.rlim_cur == libc::RLIM_INFINITY
+        {
+            return None;
+        }
+        Some(usize::try_from(rlimit.rlim_cur).unwrap_or(usize::MAX))
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )))]
+    {
+        None
+    }
+}
+
+/// Counts the CPUs in a list like `0-3,8,10-11`, as found in
+/// `/sys/devices/system/cpu/online`.
+#[cfg(target_os = "linux")]
+fn parse_cpu_list(list: &str) -> Option<usize> {
+    let mut cpus = 0;
+    for range in list.trim().split(',') {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+            None => {
+                let cpu = range.parse::<usize>().ok()?;
+                (cpu, cpu)
+            }
+        };
+        cpus += end.checked_sub(start)? + 1;
+    }
+    Some(cpus)
+}
+
+/// Tries to lock the byte at `offset` of `file` without blocking, returning
+/// whether it was locked.
+///
+/// Note that these locks are held by the process, so they don't exclude
+/// other threads of this process, and all of them are released once any
+/// file descriptor of the file is closed by this process.
+pub(crate) fn lock_byte(file: &File, offset: u64) -> io::Result<bool> {
+    match set_byte_lock(file, offset, libc::F_WRLCK as _) {
+        Ok(()) => Ok(true),
+        Err(e)
+            if e.raw_os_error() == Some(libc::EACCES) || e.kind() == io::ErrorKind::WouldBlock =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Unlocks the byte at `offset` of `file` locked by [`lock_byte`].
+pub(crate) fn unlock_byte(file: &File, offset: u64) -> io::Result<()> {
+    set_byte_lock(file, offset, libc::F_UNLCK as _)
+}
+
+fn set_byte_lock(file: &File, offset: u64, l_type: libc::c_short) -> io::Result<()> {
+    let mut lock: libc::flock = unsafe { mem::zeroed() };
+    lock.l_type = l_type;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = offset as libc::off_t;
+    lock.l_len = 1;
+    cvt(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) })?;
+    Ok(())
+}
+
+fn errno() -> c_int {
+    io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Sets `errno`, which is a no-op on platforms we don't know how to do so.
+unsafe fn set_errno(value: c_int) {
+    #[cfg(target_os = "linux")]
+    {
+        *libc::__errno_location() = value;
+    }
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))]
+    {
+        *libc::__error() = value;
+    }
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    {
+        *libc::__errno() = value;
+    }
+    let _ = value;
+}
+
 fn clone_fd_and_set_cloexec(fd: c_int) -> Result<File, FromEnvErrorInner> {
     // Safety: fd is a valid fd dand it remains open until returns
     unsafe { BorrowedFd::borrow_raw(fd) }
@@ -628,13 +1391,13 @@ fn non_blocking_read(fd: c_int, buf: &mut [u8]) -> io::Result<usize> {
 mod test {
     use super::Client as ClientImp;
 
-    use crate::{test::run_named_fifo_try_acquire_tests, Client};
+    use crate::{test::run_named_fifo_try_acquire_tests, Client, ClientInner};
 
     use std::sync::Arc;
 
     fn from_imp_client(imp: ClientImp) -> Client {
         Client {
-            inner: Arc::new(imp),
+            inner: Arc::new(ClientInner::inherited(imp)),
         }
     }
 
@@ -651,9 +1414,23 @@ mod test {
             .map(from_imp_client)
             .unwrap();
 
+        assert!(client.is_cross_process());
         run_named_fifo_try_acquire_tests(&client);
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_cpu_list() {
+        use super::parse_cpu_list;
+
+        assert_eq!(parse_cpu_list("0\n"), Some(1));
+        assert_eq!(parse_cpu_list("0-3\n"), Some(4));
+        assert_eq!(parse_cpu_list("0-3,8,10-11"), Some(7));
+        assert_eq!(parse_cpu_list(""), None);
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
     #[cfg(not(target_os = "linux"))]
     #[test]
     fn test_try_acquire_annoymous_pipe_linux_specific_optimization() {
@@ -1,8 +1,10 @@
 use crate::FromEnvErrorInner;
+use std::fs::File;
 use std::io;
 use std::process::Command;
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Client {
@@ -18,6 +20,16 @@ struct Inner {
 #[derive(Debug)]
 pub struct Acquired(());
 
+impl Acquired {
+    pub fn to_raw(&self) -> u64 {
+        0
+    }
+
+    pub fn from_raw(_raw: u64) -> Acquired {
+        Acquired(())
+    }
+}
+
 impl Client {
     pub fn new(limit: usize) -> io::Result<Client> {
         Ok(Client {
@@ -55,6 +67,24 @@ impl Client {
         }
     }
 
+    /// Waits until a token is available or `timeout` has elapsed, without
+    /// acquiring it. Returns whether one became available.
+    pub fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+        let lock = self.inner.count.lock().unwrap_or_else(|e| e.into_inner());
+        let (lock, _) = self
+            .inner
+            .cvar
+            .wait_timeout_while(lock, timeout, |count| *count == 0)
+            .unwrap_or_else(|e| e.into_inner());
+        let available = *lock > 0;
+        drop(lock);
+        // The notification may have been meant for a blocked acquisition.
+        if available {
+            self.inner.cvar.notify_one();
+        }
+        Ok(available)
+    }
+
     pub fn release(&self, _data: Option<&Acquired>) -> io::Result<()> {
         let mut lock = self.inner.count.lock().unwrap_or_else(|e| e.into_inner());
         *lock += 1;
@@ -63,6 +93,13 @@ impl Client {
         Ok(())
     }
 
+    pub fn verify(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "jobservers can't be passed to child processes on this platform",
+        ))
+    }
+
     pub fn string_arg(&self) -> String {
         panic!(
             "On this platform there is no cross process jobserver support,
@@ -70,11 +107,32 @@ impl Client {
         );
     }
 
+    pub fn ninja_arg(&self) -> io::Result<String> {
+        Ok(self.string_arg())
+    }
+
+    pub fn describe(&self) -> (&'static str, String) {
+        ("in-process", String::new())
+    }
+
     pub fn available(&self) -> io::Result<usize> {
         let lock = self.inner.count.lock().unwrap_or_else(|e| e.into_inner());
         Ok(*lock)
     }
 
+    /// There are no OS resources to close on this platform.
+    pub fn close(self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn max_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    pub fn is_cross_process(&self) -> bool {
+        false
+    }
+
     pub fn configure(&self, _cmd: &mut Command) {
         unreachable!();
     }
@@ -85,16 +143,75 @@ pub struct Helper {
     thread: JoinHandle<()>,
 }
 
+/// There are no other processes here, so the jobserver can only be owned by
+/// ourselves.
+pub(crate) fn process_is_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Blocks until a token was acquired from any of `clients`, returning the
+/// index of the client it was acquired from.
+///
+/// There's no way to wait on several condition variables at once, so this
+/// polls all clients, sleeping in between.
+pub(crate) fn acquire_select(clients: &[&Client]) -> io::Result<(usize, Acquired)> {
+    loop {
+        for (i, client) in clients.iter().enumerate() {
+            if let Some(data) = client.try_acquire()? {
+                return Ok((i, data));
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+/// There's no load average to read on this platform.
+pub(crate) fn load_average() -> io::Result<f64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "load average is not supported on this platform",
+    ))
+}
+
+/// There's no way to count CPUs on this platform.
+pub(crate) fn online_cpus() -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "counting CPUs is not supported on this platform",
+    ))
+}
+
+/// There are no processes on this platform.
+pub(crate) fn process_limit() -> Option<usize> {
+    None
+}
+
+/// There are no file locks on this platform.
+pub(crate) fn lock_byte(_file: &File, _offset: u64) -> io::Result<bool> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file locks are not supported on this platform",
+    ))
+}
+
+pub(crate) fn unlock_byte(_file: &File, _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file locks are not supported on this platform",
+    ))
+}
+
 pub(crate) fn spawn_helper(
+    builder: Builder,
     client: crate::Client,
     state: Arc<super::HelperState>,
     mut f: Box<dyn FnMut(io::Result<crate::Acquired>) + Send>,
 ) -> io::Result<Helper> {
-    let thread = Builder::new().spawn(move || {
+    let thread = builder.spawn(move || {
         state.for_each_request(|_| f(client.acquire()));
     })?;
 
-    Ok(Helper { thread: thread })
+    Ok(Helper { thread })
 }
 
 impl Helper {
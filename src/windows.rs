@@ -1,20 +1,39 @@
 use crate::FromEnvErrorInner;
-use std::ffi::CString;
+use std::ffi::{c_void, CString};
+use std::fs::File;
 use std::io;
+use std::mem;
+use std::os::windows::io::AsRawHandle;
 use std::process::Command;
 use std::ptr;
 use std::sync::Arc;
 use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Client {
     sem: Handle,
     name: String,
+    /// Maximum count of the semaphore, known only if this process created it.
+    max_capacity: Option<usize>,
+    /// Whether the handle is left open when this is dropped, see
+    /// [`Client::new_detached`].
+    detached: bool,
 }
 
 #[derive(Debug)]
 pub struct Acquired;
 
+impl Acquired {
+    pub fn to_raw(&self) -> u64 {
+        0
+    }
+
+    pub fn from_raw(_raw: u64) -> Acquired {
+        Acquired
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 type BOOL = i32;
 #[allow(clippy::upper_case_acronyms)]
@@ -24,9 +43,13 @@ type HANDLE = *mut u8;
 #[allow(clippy::upper_case_acronyms)]
 type LONG = i32;
 
+const ERROR_ACCESS_DENIED: DWORD = 5;
 const ERROR_ALREADY_EXISTS: DWORD = 183;
+const ERROR_LOCK_VIOLATION: DWORD = 33;
 const FALSE: BOOL = 0;
+const HANDLE_FLAG_INHERIT: DWORD = 0x1;
 const INFINITE: DWORD = 0xffffffff;
+const SEMAPHORE_ALL_ACCESS: DWORD = 0x1f0003;
 const SEMAPHORE_MODIFY_STATE: DWORD = 0x2;
 const SYNCHRONIZE: DWORD = 0x00100000;
 const TRUE: BOOL = 1;
@@ -36,6 +59,23 @@ const WAIT_FAILED: DWORD = 4294967295u32;
 const WAIT_OBJECT_0: DWORD = 0u32;
 const WAIT_TIMEOUT: DWORD = 258u32;
 
+/// `SYSTEM_INFO`, of which only the number of processors is used.
+#[allow(dead_code)]
+#[repr(C)]
+struct SystemInfo {
+    processor_architecture: u16,
+    reserved: u16,
+    page_size: DWORD,
+    minimum_application_address: *mut c_void,
+    maximum_application_address: *mut c_void,
+    active_processor_mask: usize,
+    number_of_processors: DWORD,
+    processor_type: DWORD,
+    allocation_granularity: DWORD,
+    processor_level: u16,
+    processor_revision: u16,
+}
+
 extern "system" {
     fn CloseHandle(handle: HANDLE) -> BOOL;
     fn SetEvent(hEvent: HANDLE) -> BOOL;
@@ -62,12 +102,89 @@ extern "system" {
         lMaximumCount: LONG,
         lpName: *const i8,
     ) -> HANDLE;
+    fn GetHandleInformation(hObject: HANDLE, lpdwFlags: *mut DWORD) -> BOOL;
+    fn GetModuleHandleA(lpModuleName: *const i8) -> HANDLE;
+    fn GetProcAddress(hModule: HANDLE, lpProcName: *const i8) -> *const c_void;
+    fn GetSystemInfo(lpSystemInfo: *mut SystemInfo);
+    fn LockFile(
+        hFile: HANDLE,
+        dwFileOffsetLow: DWORD,
+        dwFileOffsetHigh: DWORD,
+        nNumberOfBytesToLockLow: DWORD,
+        nNumberOfBytesToLockHigh: DWORD,
+    ) -> BOOL;
+    fn UnlockFile(
+        hFile: HANDLE,
+        dwFileOffsetLow: DWORD,
+        dwFileOffsetHigh: DWORD,
+        nNumberOfBytesToUnlockLow: DWORD,
+        nNumberOfBytesToUnlockHigh: DWORD,
+    ) -> BOOL;
+    fn SetHandleInformation(hObject: HANDLE, dwMask: DWORD, dwFlags: DWORD) -> BOOL;
+    fn OpenProcess(dwDesiredAccess: DWORD, bInheritHandle: BOOL, dwProcessId: DWORD) -> HANDLE;
     fn OpenSemaphoreA(dwDesiredAccess: DWORD, bInheritHandle: BOOL, lpName: *const i8) -> HANDLE;
     fn WaitForSingleObject(hHandle: HANDLE, dwMilliseconds: DWORD) -> DWORD;
     #[link_name = "SystemFunction036"]
     fn RtlGenRandom(RandomBuffer: *mut u8, RandomBufferLength: u32) -> u8;
 }
 
+#[allow(non_snake_case)]
+type CreateSemaphoreExW = unsafe extern "system" fn(
+    lpSemaphoreAttributes: *mut u8,
+    lInitialCount: LONG,
+    lMaximumCount: LONG,
+    lpName: *const u16,
+    dwFlags: DWORD,
+    dwDesiredAccess: DWORD,
+) -> HANDLE;
+
+/// Looks up `CreateSemaphoreExW`, which is only available on Windows Vista
+/// and later.
+fn create_semaphore_ex_w() -> Option<CreateSemaphoreExW> {
+    unsafe {
+        let kernel32 = GetModuleHandleA(b"kernel32.dll\0".as_ptr() as *const _);
+        if kernel32.is_null() {
+            return None;
+        }
+        let f = GetProcAddress(kernel32, b"CreateSemaphoreExW\0".as_ptr() as *const _);
+        if f.is_null() {
+            None
+        } else {
+            Some(std::mem::transmute::<*const c_void, CreateSemaphoreExW>(f))
+        }
+    }
+}
+
+/// Creates the semaphore `name` with `limit` slots out of `max`, using
+/// `CreateSemaphoreExW` if available and `CreateSemaphoreA` otherwise.
+///
+/// `security_attributes` is either null or points to a `SECURITY_ATTRIBUTES`.
+unsafe fn create_semaphore(
+    security_attributes: *mut c_void,
+    limit: LONG,
+    max: LONG,
+    name: &CString,
+) -> HANDLE {
+    match create_semaphore_ex_w() {
+        Some(create) => {
+            let wide = name
+                .to_string_lossy()
+                .encode_utf16()
+                .chain(Some(0))
+                .collect::<Vec<_>>();
+            create(
+                security_attributes.cast(),
+                limit,
+                max,
+                wide.as_ptr(),
+                0,
+                SEMAPHORE_ALL_ACCESS,
+            )
+        }
+        None => CreateSemaphoreA(security_attributes.cast(), limit, max, name.as_ptr()),
+    }
+}
+
 // Note that we ideally would use the `getrandom` crate, but unfortunately
 // that causes build issues when this crate is used in rust-lang/rust (see
 // rust-lang/rust#65014 for more information). As a result we just inline
@@ -89,6 +206,28 @@ fn getrandom(dest: &mut [u8]) -> io::Result<()> {
 
 impl Client {
     pub fn new(limit: usize) -> io::Result<Client> {
+        Client::new_validated(limit, None, None, ptr::null_mut())
+    }
+
+    /// `max_capacity` defaults to `limit`, and `security_attributes` is
+    /// either null or points to a `SECURITY_ATTRIBUTES` which is valid for
+    /// the duration of the call.
+    pub fn new_validated(
+        limit: usize,
+        max_capacity: Option<usize>,
+        name_validator: Option<fn(&str) -> bool>,
+        security_attributes: *mut c_void,
+    ) -> io::Result<Client> {
+        let max_capacity = max_capacity.unwrap_or(limit);
+        // Leave room for the slot acquired below if `limit == 0`.
+        let max_count = max_capacity + usize::from(limit == 0);
+        if max_capacity < limit || max_count > LONG::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "semaphore maximum must be between the limit and i32::MAX",
+            ));
+        }
+
         // Try a bunch of random semaphore names until we get a unique one,
         // but don't try for too long.
         //
@@ -100,14 +239,20 @@ impl Client {
         for _ in 0..100 {
             let mut bytes = [0; 4];
             getrandom(&mut bytes)?;
-            let mut name = format!("__rust_jobserver_semaphore_{}\0", u32::from_ne_bytes(bytes));
+            let name = format!("__rust_jobserver_semaphore_{}", u32::from_ne_bytes(bytes));
+            if let Some(validator) = name_validator {
+                if !validator(&name) {
+                    continue;
+                }
+            }
+            let c_name = CString::new(name.clone()).unwrap();
             unsafe {
                 let create_limit = if limit == 0 { 1 } else { limit };
-                let r = CreateSemaphoreA(
-                    ptr::null_mut(),
-                    create_limit as LONG,
+                let r = create_semaphore(
+                    security_attributes,
                     create_limit as LONG,
-                    name.as_ptr() as *const _,
+                    max_count as LONG,
+                    &c_name,
                 );
                 if r.is_null() {
                     return Err(io::Error::last_os_error());
@@ -118,8 +263,12 @@ impl Client {
                 if err.raw_os_error() == Some(ERROR_ALREADY_EXISTS as i32) {
                     continue;
                 }
-                name.pop(); // chop off the trailing nul
-                let client = Client { sem: handle, name };
+                let client = Client {
+                    sem: handle,
+                    name,
+                    max_capacity: Some(max_capacity),
+                    detached: false,
+                };
                 if create_limit != limit {
                     client.acquire()?;
                 }
@@ -129,10 +278,46 @@ impl Client {
 
         Err(io::Error::new(
             io::ErrorKind::Other,
-            "failed to find a unique name for a semaphore",
+            "failed to find a unique and valid name for a semaphore",
         ))
     }
 
+    /// Opens the semaphore `name`, creating it with `limit` slots if it
+    /// doesn't exist yet. Returns whether it was created.
+    pub fn open_or_create(name: &str, limit: usize) -> io::Result<(Client, bool)> {
+        let c_name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // See `new_validated` for why `limit == 0` is special.
+        let create_limit = if limit == 0 { 1 } else { limit };
+        unsafe {
+            let r = create_semaphore(
+                ptr::null_mut(),
+                create_limit as LONG,
+                create_limit as LONG,
+                &c_name,
+            );
+            if r.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let handle = Handle(r);
+
+            // When the semaphore already exists we get a handle to it, with
+            // its original count and maximum left untouched.
+            let err = io::Error::last_os_error();
+            let created = err.raw_os_error() != Some(ERROR_ALREADY_EXISTS as i32);
+            let client = Client {
+                sem: handle,
+                name: name.to_string(),
+                max_capacity: if created { Some(limit) } else { None },
+                detached: false,
+            };
+            if created && create_limit != limit {
+                client.acquire()?;
+            }
+            Ok((client, created))
+        }
+    }
+
     pub(crate) unsafe fn open(s: &str, _check_pipe: bool) -> Result<Client, FromEnvErrorInner> {
         let name = match CString::new(s) {
             Ok(s) => s,
@@ -149,10 +334,61 @@ impl Client {
             Ok(Client {
                 sem: Handle(sem),
                 name: s.to_string(),
+                max_capacity: None,
+                detached: false,
             })
         }
     }
 
+    /// Creates the semaphore `name` like [`Client::open_or_create`], failing
+    /// if it exists already. Its handle is never closed once this is
+    /// dropped.
+    pub fn new_detached(limit: usize, name: &str) -> io::Result<Client> {
+        let (mut client, created) = Client::open_or_create(name, limit)?;
+        if !created {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("semaphore {name:?} already exists"),
+            ));
+        }
+        client.detached = true;
+        Ok(client)
+    }
+
+    /// Closes the semaphore handle of this client, reporting errors which
+    /// dropping it would ignore.
+    pub fn close(mut self) -> io::Result<()> {
+        let handle = mem::replace(&mut self.sem.0, ptr::null_mut());
+        if unsafe { CloseHandle(handle) } == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn max_capacity(&self) -> Option<usize> {
+        self.max_capacity
+    }
+
+    pub fn set_inherit(&self, inherit: bool) -> io::Result<()> {
+        let flags = if inherit { HANDLE_FLAG_INHERIT } else { 0 };
+        if unsafe { SetHandleInformation(self.sem.0, HANDLE_FLAG_INHERIT, flags) } == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn is_inheritable(&self) -> io::Result<bool> {
+        let mut flags = 0;
+        if unsafe { GetHandleInformation(self.sem.0, &mut flags) } == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(flags & HANDLE_FLAG_INHERIT != 0)
+    }
+
+    pub fn verify(&self) -> io::Result<()> {
+        self.is_inheritable().map(drop)
+    }
+
     pub fn acquire(&self) -> io::Result<Acquired> {
         unsafe {
             let r = WaitForSingleObject(self.sem.0, INFINITE);
@@ -179,6 +415,19 @@ impl Client {
         }
     }
 
+    /// Waits until a token is available or `timeout` has elapsed, without
+    /// keeping it. Returns whether one became available.
+    pub fn wait_readable(&self, timeout: Duration) -> io::Result<bool> {
+        let timeout = DWORD::try_from(timeout.as_millis()).unwrap_or(INFINITE - 1);
+        match unsafe { WaitForSingleObject(self.sem.0, timeout) } {
+            // Semaphores can't be waited on without decrementing them, so
+            // hand the slot right back.
+            WAIT_OBJECT_0 => self.release(None).map(|()| true),
+            WAIT_TIMEOUT => Ok(false),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
     pub fn release(&self, _data: Option<&Acquired>) -> io::Result<()> {
         unsafe {
             let r = ReleaseSemaphore(self.sem.0, 1, ptr::null_mut());
@@ -194,6 +443,14 @@ impl Client {
         self.name.clone()
     }
 
+    pub fn ninja_arg(&self) -> io::Result<String> {
+        Ok(self.string_arg())
+    }
+
+    pub fn describe(&self) -> (&'static str, String) {
+        ("windows-semaphore", self.name.clone())
+    }
+
     pub fn available(&self) -> io::Result<usize> {
         // Can't read value of a semaphore on Windows, so
         // try to acquire without sleeping, since we can find out the
@@ -214,6 +471,10 @@ impl Client {
         }
     }
 
+    pub fn is_cross_process(&self) -> bool {
+        true
+    }
+
     pub fn configure(&self, _cmd: &mut Command) {
         // nothing to do here, we gave the name of our semaphore to the
         // child above
@@ -228,8 +489,19 @@ unsafe impl Send for Handle {}
 
 impl Drop for Handle {
     fn drop(&mut self) {
-        unsafe {
-            CloseHandle(self.0);
+        if !self.0.is_null() {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if self.detached {
+            // Leave the handle open to keep the semaphore alive.
+            self.sem.0 = ptr::null_mut();
         }
     }
 }
@@ -240,7 +512,99 @@ pub struct Helper {
     thread: JoinHandle<()>,
 }
 
+/// Returns whether the process `pid` exists and hasn't exited yet.
+pub(crate) fn process_is_alive(pid: u32) -> bool {
+    unsafe {
+        let handle = OpenProcess(SYNCHRONIZE, FALSE, pid);
+        if handle.is_null() {
+            // The process exists but we're not allowed to wait on it.
+            return io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED as i32);
+        }
+        let handle = Handle(handle);
+        WaitForSingleObject(handle.0, 0) == WAIT_TIMEOUT
+    }
+}
+
+/// Blocks until a token was acquired from any of `clients`, returning the
+/// index of the client it was acquired from.
+pub(crate) fn acquire_select(clients: &[&Client]) -> io::Result<(usize, Acquired)> {
+    const MAXIMUM_WAIT_OBJECTS: usize = 64;
+    if clients.len() > MAXIMUM_WAIT_OBJECTS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot wait on more than 64 jobservers at once",
+        ));
+    }
+    let handles = clients.iter().map(|c| c.sem.0).collect::<Vec<_>>();
+    let r = unsafe {
+        WaitForMultipleObjects(handles.len() as DWORD, handles.as_ptr(), FALSE, INFINITE)
+    };
+    if r == WAIT_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    match r.checked_sub(WAIT_OBJECT_0) {
+        Some(i) if (i as usize) < handles.len() => Ok((i as usize, Acquired)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Wait on jobserver semaphores returned WAIT_ABANDONED",
+        )),
+    }
+}
+
+/// There's no load average to read on this platform.
+pub(crate) fn load_average() -> io::Result<f64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "load average is not supported on this platform",
+    ))
+}
+
+/// Returns the number of logical processors in the system.
+///
+/// Like `GetSystemInfo`, this only counts the processors of the processor
+/// group of this process on systems with more than 64 of them.
+pub(crate) fn online_cpus() -> io::Result<usize> {
+    let mut info = mem::MaybeUninit::<SystemInfo>::uninit();
+    let info = unsafe {
+        GetSystemInfo(info.as_mut_ptr());
+        info.assume_init()
+    };
+    Ok((info.number_of_processors as usize).max(1))
+}
+
+/// There's no limit on processes here, so the number of processors is used
+/// as the recommended upper bound instead.
+pub(crate) fn process_limit() -> Option<usize> {
+    online_cpus().ok()
+}
+
+/// Tries to lock the byte at `offset` of `file` without blocking, returning
+/// whether it was locked.
+pub(crate) fn lock_byte(file: &File, offset: u64) -> io::Result<bool> {
+    let handle = file.as_raw_handle() as HANDLE;
+    let r = unsafe { LockFile(handle, offset as DWORD, (offset >> 32) as DWORD, 1, 0) };
+    if r != FALSE {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// Unlocks the byte at `offset` of `file` locked by [`lock_byte`].
+pub(crate) fn unlock_byte(file: &File, offset: u64) -> io::Result<()> {
+    let handle = file.as_raw_handle() as HANDLE;
+    if unsafe { UnlockFile(handle, offset as DWORD, (offset >> 32) as DWORD, 1, 0) } == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 pub(crate) fn spawn_helper(
+    builder: Builder,
     client: crate::Client,
     state: Arc<super::HelperState>,
     mut f: Box<dyn FnMut(io::Result<crate::Acquired>) + Send>,
@@ -255,8 +619,9 @@ pub(crate) fn spawn_helper(
     };
     let event = Arc::new(event);
     let event2 = event.clone();
-    let thread = Builder::new().spawn(move || {
-        let objects = [event2.0, client.inner.sem.0];
+    let thread = builder.spawn(move || {
+        // Unlimited clients are served by `Client::into_helper_thread` itself.
+        let objects = [event2.0, client.inner.imp.as_ref().unwrap().sem.0];
         state.for_each_request(|_| {
             const WAIT_OBJECT_1: u32 = WAIT_OBJECT_0 + 1;
             match unsafe { WaitForMultipleObjects(2, objects.as_ptr(), FALSE, INFINITE) } {
@@ -265,6 +630,8 @@ pub(crate) fn spawn_helper(
                     client: client.inner.clone(),
                     data: Acquired,
                     disabled: false,
+                    soft: false,
+                    acquired_at: client.inner.observe_start(),
                 })),
                 _ => f(Err(io::Error::last_os_error())),
             }
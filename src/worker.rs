@@ -0,0 +1,81 @@
+use crate::Acquired;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Builder, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A worker thread running on behalf of a jobserver token, created through
+/// [`Client::spawn_worker`](crate::Client::spawn_worker).
+///
+/// The token is held by this handle rather than the thread, and released
+/// once the thread was joined through [`WorkerHandle::join`] or
+/// [`WorkerHandle::abort`]. Dropping the handle detaches the thread and
+/// releases the token right away.
+#[derive(Debug)]
+pub struct WorkerHandle<R> {
+    thread: JoinHandle<R>,
+    token: Acquired,
+    stop: Arc<AtomicBool>,
+}
+
+impl<R: Send + 'static> WorkerHandle<R> {
+    pub(crate) fn spawn<F>(token: Acquired, f: F) -> io::Result<WorkerHandle<R>>
+    where
+        F: FnOnce(&AtomicBool) -> R + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop2 = stop.clone();
+        let thread = Builder::new().spawn(move || f(&stop2))?;
+        Ok(WorkerHandle {
+            thread,
+            token,
+            stop,
+        })
+    }
+
+    /// Waits for the worker to finish and then releases its token.
+    ///
+    /// # Errors
+    ///
+    /// Returns the panic payload if the worker panicked, as with
+    /// [`JoinHandle::join`].
+    pub fn join(self) -> thread::Result<R> {
+        let ret = self.thread.join();
+        drop(self.token);
+        ret
+    }
+
+    /// Asks the worker to stop and waits up to `timeout` for it to finish.
+    ///
+    /// This sets the flag passed to the worker, which is expected to check it
+    /// regularly and return early once it's set. If the worker finishes in
+    /// time it's joined and its token released, as with
+    /// [`WorkerHandle::join`]. Otherwise `None` is returned, and the token is
+    /// only released once the worker eventually finishes, from a background
+    /// thread, so that the worker never runs without a token.
+    pub fn abort(self, timeout: Duration) -> Option<thread::Result<R>> {
+        self.stop.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        while !self.thread.is_finished() {
+            let now = Instant::now();
+            if now >= deadline {
+                let WorkerHandle { thread, token, .. } = self;
+                // If the background thread can't be spawned the token is
+                // released right away instead.
+                drop(Builder::new().spawn(move || {
+                    drop(thread.join());
+                    drop(token);
+                }));
+                return None;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(1)));
+        }
+        Some(self.join())
+    }
+
+    /// Returns whether the worker has finished running.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
@@ -1,13 +1,14 @@
 use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::process::Command;
+use std::num::NonZeroUsize;
+use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 
-use jobserver::Client;
+use jobserver::{Client, FromEnvErrorKind};
 
 macro_rules! t {
     ($e:expr) => {
@@ -103,6 +104,123 @@ const TESTS: &[Test] = &[
             c.release_raw().unwrap();
         },
     },
+    Test {
+        name: "from_env_lossy without jobserver",
+        make_args: &[],
+        rule: &|me| me.to_string(),
+        f: &|| {
+            let c = unsafe { Client::from_env_lossy() };
+            assert!(c.is_unlimited());
+            drop(c.acquire().unwrap());
+        },
+    },
+    Test {
+        name: "from_env_lossy with jobserver",
+        make_args: &["-j2"],
+        rule: &|me| format!("+{}", me),
+        f: &|| {
+            let c = unsafe { Client::from_env_lossy() };
+            assert!(!c.is_unlimited());
+        },
+    },
+    Test {
+        name: "from_env noop sentinel",
+        make_args: &["-j2"],
+        rule: &|me| format!("CARGO_MAKEFLAGS=--jobserver-auth=none {}", me),
+        f: &|| {
+            let c = unsafe { Client::from_env().unwrap() };
+            assert!(c.is_unlimited());
+            let tokens = (0..10).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+            drop(tokens);
+        },
+    },
+    Test {
+        name: "from_env_with_fallback without jobserver",
+        make_args: &[],
+        rule: &|me| me.to_string(),
+        f: &|| {
+            let c = unsafe { Client::from_env_with_fallback(|| Client::new(3)) }.unwrap();
+            assert_eq!(c.available().unwrap(), 3);
+        },
+    },
+    Test {
+        name: "from_env_with_fallback with jobserver",
+        make_args: &["-j2"],
+        rule: &|me| format!("+{}", me),
+        f: &|| {
+            let c = unsafe { Client::from_env_with_fallback(|| panic!("shouldn't fall back")) };
+            drop(c.unwrap().acquire().unwrap());
+        },
+    },
+    Test {
+        name: "from_env_with_fallback with broken jobserver",
+        make_args: &[],
+        rule: &|me| format!("MAKEFLAGS=--jobserver-auth=bogus {}", me),
+        f: &|| {
+            let c = unsafe { Client::from_env_with_fallback(|| panic!("shouldn't fall back")) };
+            assert!(c.is_err());
+        },
+    },
+    Test {
+        name: "from_env_or_default without jobserver",
+        make_args: &[],
+        rule: &|me| me.to_string(),
+        f: &|| {
+            let c = unsafe { Client::from_env_or_default(NonZeroUsize::new(3).unwrap()) };
+            assert_eq!(c.available().unwrap(), 3);
+        },
+    },
+    Test {
+        name: "from_env_or_default with jobserver",
+        make_args: &["-j2"],
+        rule: &|me| format!("+{}", me),
+        f: &|| {
+            let c = unsafe { Client::from_env_or_default(NonZeroUsize::new(3).unwrap()) };
+            assert_eq!(c.available().unwrap(), 1);
+        },
+    },
+    Test {
+        name: "from_env_vars",
+        make_args: &["-j2"],
+        rule: &|me| format!("+BOGUS_FLAGS=--jobserver-auth=bogus {}", me),
+        f: &|| {
+            let vars = ["JOBSERVER_UNSET", "BOGUS_FLAGS", "MAKEFLAGS"];
+            let c = unsafe { Client::from_env_vars(&vars) }.unwrap();
+            drop(c.acquire().unwrap());
+            let err = unsafe { Client::from_env_vars(&vars[..2]) }.unwrap_err();
+            assert!(!matches!(err.kind(), FromEnvErrorKind::NoEnvVar));
+            let err = unsafe { Client::from_env_vars(&vars[..1]) }.unwrap_err();
+            assert!(matches!(err.kind(), FromEnvErrorKind::NoEnvVar));
+        },
+    },
+    Test {
+        name: "from_env_checking_pid alive",
+        make_args: &["-j2"],
+        rule: &|me| format!("+{}", me),
+        f: &|| {
+            let from_env = unsafe { Client::from_env_checking_pid(std::process::id()) };
+            drop(from_env.client.unwrap().acquire().unwrap());
+        },
+    },
+    Test {
+        name: "from_env_checking_pid dead",
+        make_args: &["-j2"],
+        rule: &|me| format!("+{}", me),
+        f: &|| {
+            let mut child = Command::new("make")
+                .arg("--version")
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap();
+            let pid = child.id();
+            child.wait().unwrap();
+            let from_env = unsafe { Client::from_env_checking_pid(pid) };
+            assert!(matches!(
+                from_env.client.unwrap_err().kind(),
+                FromEnvErrorKind::OwnerProcessDead
+            ));
+        },
+    },
 ];
 
 fn main() {
@@ -74,3 +74,61 @@ fn prompt_shutdown() {
         assert!(d.as_secs_f64() < 0.5);
     }
 }
+
+#[test]
+fn named() {
+    let (tx, rx) = mpsc::channel();
+    let client = t!(Client::new(1));
+    let helper = client
+        .into_helper_thread_named("jobserver-helper", move |_| {
+            drop(tx.send(std::thread::current().name().map(|s| s.to_string())))
+        })
+        .unwrap();
+    assert_eq!(helper.thread_name(), Some("jobserver-helper"));
+    helper.request_token();
+    assert_eq!(rx.recv().unwrap().as_deref(), Some("jobserver-helper"));
+
+    let helper = t!(Client::new(1)).into_helper_thread(|_| ()).unwrap();
+    assert_eq!(helper.thread_name(), None);
+}
+
+#[test]
+fn try_request() {
+    let client = t!(Client::new(0));
+    let helper = client.into_helper_thread(|_| ()).unwrap();
+    helper.set_max_pending(2);
+
+    // The first request is picked up by the helper thread, which then blocks.
+    assert!(helper.try_request());
+    while helper.pending() != 0 {
+        std::thread::yield_now();
+    }
+    assert!(helper.try_request());
+    assert!(helper.try_request());
+    assert!(!helper.try_request());
+    assert_eq!(helper.pending(), 2);
+
+    helper.request_token();
+    assert_eq!(helper.pending(), 3);
+}
+
+#[test]
+fn request_n() {
+    let client = t!(Client::new(3));
+    let (tx, rx) = mpsc::channel();
+    let helper = client
+        .into_helper_thread(move |a| drop(tx.send(a)))
+        .unwrap();
+    assert!(helper.request_n(3));
+    for _ in 0..3 {
+        rx.recv().unwrap().unwrap();
+    }
+
+    helper.set_max_pending(2);
+    while helper.pending() != 0 {
+        std::thread::yield_now();
+    }
+    assert!(!helper.request_n(3));
+    assert_eq!(helper.pending(), 0);
+    assert!(!helper.request_n(usize::MAX));
+}
@@ -5,10 +5,14 @@ use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread;
 
 use jobserver::Client;
 
+/// Serializes tests which set `MAKEFLAGS` for this process.
+static MAKEFLAGS_LOCK: Mutex<()> = Mutex::new(());
+
 macro_rules! t {
     ($e:expr) => {
         match $e {
@@ -179,3 +183,2086 @@ fn zero_client() {
         assert!(rx.try_recv().is_err());
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn auto_release_on_close() {
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    let c = t!(Client::new(1));
+    c.acquire().unwrap().drop_without_releasing();
+    assert_eq!(c.available().unwrap(), 0);
+
+    let (read, write) = t!(nix::unistd::pipe());
+    let handle = t!(c.with_auto_release(&[read.as_raw_fd()]));
+    drop(write);
+
+    let start = Instant::now();
+    while c.available().unwrap() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::yield_now();
+    }
+    assert_eq!(c.available().unwrap(), 1);
+    handle.cancel();
+}
+
+#[cfg(unix)]
+#[test]
+fn auto_release_cancel() {
+    use std::os::unix::io::AsRawFd;
+
+    let c = t!(Client::new(0));
+    let (read, _write) = t!(nix::unistd::pipe());
+    t!(c.with_auto_release(&[read.as_raw_fd()])).cancel();
+    assert_eq!(c.available().unwrap(), 0);
+}
+
+#[test]
+fn configure_without_extra_env_merges_makeflags() {
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("make");
+    cmd.env("MAKEFLAGS", "-r --jobserver-auth=stale");
+    c.configure_without_extra_env(&mut cmd);
+
+    let envs = cmd.get_envs().collect::<Vec<_>>();
+    assert_eq!(envs.len(), 1);
+    let (key, value) = envs[0];
+    assert_eq!(key, "MAKEFLAGS");
+    let value = value.unwrap().to_str().unwrap();
+    assert!(value.starts_with("-r --jobserver-fds="));
+    assert!(!value.contains("stale"));
+}
+
+#[test]
+fn configure_env_inherit_filter() {
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("make");
+    cmd.env("MAKEFLAGS", "-r --debug=b -s");
+    c.configure_env_inherit_filter(|name, _| name != "--debug", &mut cmd);
+
+    let (_, value) = cmd.get_envs().next().unwrap();
+    let value = value.unwrap().to_str().unwrap();
+    assert!(value.starts_with("-r -s --jobserver-fds="));
+    assert!(!value.contains("--debug"));
+}
+
+#[test]
+fn configure_preserve_makeflags() {
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("make");
+    cmd.env("MAKEFLAGS", "--debug=b");
+    let _lock = MAKEFLAGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("MAKEFLAGS", "-r --no-print-directory");
+    c.configure_preserve_makeflags(&mut cmd);
+    env::remove_var("MAKEFLAGS");
+
+    let value = |name: &str| {
+        let (_, value) = cmd.get_envs().find(|(key, _)| *key == name).unwrap();
+        value.unwrap().to_str().unwrap().to_string()
+    };
+    let makeflags = value("MAKEFLAGS");
+    assert!(makeflags.starts_with("-r --no-print-directory --jobserver-fds="));
+    assert!(!makeflags.contains("--debug"));
+    assert!(value("MFLAGS").starts_with("-j --jobserver-fds="));
+    assert!(value("CARGO_MAKEFLAGS").starts_with("-j --jobserver-fds="));
+}
+
+#[cfg(unix)]
+#[test]
+fn ninja() {
+    use jobserver::FromEnvErrorKind;
+
+    let mut cmd = Command::new("ninja");
+    let err = t!(Client::new(1))
+        .configure_for_ninja(&mut cmd)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert_eq!(cmd.get_envs().count(), 0);
+
+    let td = t!(tempfile::tempdir());
+    let path = td.path().join("fifo");
+    t!(nix::unistd::mkfifo(&path, nix::sys::stat::Mode::S_IRWXU));
+    let auth = format!("fifo:{}", path.display());
+
+    let _lock = MAKEFLAGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("MAKEFLAGS", "-j --jobserver-auth=3,4");
+    let err = Client::from_ninja_env().unwrap_err();
+    assert!(matches!(err.kind(), FromEnvErrorKind::CannotParse));
+    env::set_var("MAKEFLAGS", format!("-j4 --jobserver-auth={auth}"));
+    let c = Client::from_ninja_env();
+    env::remove_var("MAKEFLAGS");
+    let c = t!(c);
+
+    t!(c.release_raw());
+    drop(t!(c.acquire()));
+    t!(c.configure_for_ninja(&mut cmd));
+    let (key, value) = cmd.get_envs().next().unwrap();
+    assert_eq!(key, "MAKEFLAGS");
+    assert_eq!(value.unwrap(), &*format!("-j --jobserver-auth={auth}"));
+}
+
+#[test]
+fn configure_strict() {
+    let mut cmd = Command::new("make");
+    let err = Client::new_unlimited()
+        .configure_strict(&mut cmd)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert_eq!(cmd.get_envs().count(), 0);
+
+    let c = t!(Client::new(1));
+    t!(c.verify());
+    t!(c.configure_strict(&mut cmd));
+    assert!(cmd.get_envs().any(|(key, _)| key == "CARGO_MAKEFLAGS"));
+}
+
+#[cfg(unix)]
+#[test]
+fn verify_removed_fifo() {
+    let td = t!(tempfile::tempdir());
+    let path = td.path().join("fifo");
+    t!(nix::unistd::mkfifo(&path, nix::sys::stat::Mode::S_IRWXU));
+    let var = "JOBSERVER_TEST_VERIFY_MAKEFLAGS";
+    env::set_var(var, format!("--jobserver-auth=fifo:{}", path.display()));
+    let c = t!(unsafe { Client::from_env_vars(&[var]) });
+    t!(c.verify());
+
+    t!(std::fs::remove_file(&path));
+    assert!(c.verify().is_err());
+    let mut cmd = Command::new("make");
+    assert!(c.configure_strict(&mut cmd).is_err());
+    assert_eq!(cmd.get_envs().count(), 0);
+}
+
+#[test]
+fn split_n() {
+    let c = t!(Client::new(10));
+    let _a = c.acquire().unwrap();
+    let parts = t!(c.split_n(4));
+    let limits = parts
+        .iter()
+        .map(|p| p.available().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(limits, [3, 2, 2, 2]);
+
+    let _b = parts[0].acquire().unwrap();
+    assert_eq!(parts[0].available().unwrap(), 2);
+    assert_eq!(parts[1].available().unwrap(), 2);
+    assert_eq!(c.available().unwrap(), 9);
+
+    assert!(c.split_n(0).is_err());
+}
+
+#[test]
+fn is_cross_process() {
+    let c = t!(Client::new(1));
+    assert_eq!(c.is_cross_process(), cfg!(windows));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn acquire_or_spawn_blocking() {
+    let c = t!(Client::new(1));
+    let rt = t!(tokio::runtime::Builder::new_current_thread().build());
+    let available = rt
+        .block_on(c.acquire_or_spawn_blocking({
+            let c = c.clone();
+            move |_token| c.available().unwrap()
+        }))
+        .unwrap();
+    assert_eq!(available, 0);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn acquire_async_tokio() {
+    let c = t!(Client::new(1));
+    let rt = t!(tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build());
+    let token = t!(rt.block_on(c.acquire_async_tokio()));
+    assert_eq!(c.available().unwrap(), 0);
+
+    let token = rt.block_on(async {
+        let waiter = tokio::spawn(c.acquire_async_tokio());
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!waiter.is_finished());
+        drop(token);
+        waiter.await.unwrap()
+    });
+    drop(t!(token));
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_in_thread() {
+    let c = t!(Client::new(1));
+    let a = c.acquire().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let t = t!(c.acquire_in_thread(move |_token| {
+        tx.send(()).unwrap();
+        1
+    }));
+    assert!(rx.try_recv().is_err());
+    drop(a);
+    assert_eq!(t.join().unwrap().unwrap(), 1);
+    rx.recv().unwrap();
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn throttle() {
+    use std::time::{Duration, Instant};
+
+    let c = t!(Client::new(3)).throttle(20.0);
+    let start = Instant::now();
+    let tokens = (0..3).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    assert_eq!(c.available().unwrap(), 0);
+    drop(tokens);
+
+    c.set_rate(1000.0);
+    let start = Instant::now();
+    drop(c.acquire().unwrap());
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn acquire_park() {
+    let c = t!(Client::new(1));
+    let token = c.acquire().unwrap();
+    let token = c.unpark_one(token).unwrap_err();
+
+    let c2 = c.clone();
+    let t = thread::spawn(move || {
+        let token = c2.acquire_park();
+        assert_eq!(c2.available().unwrap(), 0);
+        drop(token);
+    });
+    let mut token = Some(token);
+    while let Some(t) = token.take() {
+        if let Err(t) = c.unpark_one(t) {
+            token = Some(t);
+            thread::yield_now();
+        }
+    }
+    t.join().unwrap();
+    assert_eq!(c.available().unwrap(), 1);
+
+    let other = t!(Client::new(1));
+    assert!(c.unpark_one(other.acquire().unwrap()).is_err());
+}
+
+#[test]
+fn raw_token() {
+    use jobserver::{Acquired, RawToken};
+
+    let c = t!(Client::new(2));
+    let raw = c.acquire().unwrap().into_raw();
+    let raw2 = u64::from(c.acquire().unwrap().into_raw());
+    assert_eq!(c.available().unwrap(), 0);
+
+    drop(unsafe { Acquired::from_raw(&c, raw) });
+    assert_eq!(c.available().unwrap(), 1);
+    t!(unsafe { c.release_raw_token(RawToken::from(raw2)) });
+    assert_eq!(c.available().unwrap(), 2);
+}
+
+#[test]
+fn raw_soft_token() {
+    use jobserver::Acquired;
+
+    let c = t!(Client::new(1));
+    c.soft_limit(2);
+    let raw = t!(c.soft_acquire()).into_raw();
+    let raw2 = t!(c.soft_acquire()).into_raw();
+    assert_eq!(c.soft_held(), 2);
+    drop(unsafe { Acquired::from_raw(&c, raw) });
+    t!(unsafe { c.release_raw_token(raw2) });
+    assert_eq!(c.soft_held(), 0);
+    assert_eq!(c.available().unwrap(), 1);
+
+    t!(c.soft_acquire()).drop_without_releasing();
+    assert_eq!(c.soft_held(), 0);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn batch_release_all() {
+    let c = t!(Client::new(3));
+    let tokens = (0..3).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+    assert_eq!(c.available().unwrap(), 0);
+    t!(c.batch_release_all(tokens));
+    assert_eq!(c.available().unwrap(), 3);
+
+    let tokens = (0..2)
+        .map(|_| c.acquire().unwrap())
+        .collect::<std::collections::VecDeque<_>>();
+    t!(c.batch_release_all(tokens));
+    assert_eq!(c.available().unwrap(), 3);
+}
+
+#[test]
+fn configure_make_j_flag() {
+    let c = t!(Client::new(3));
+    let mut cmd = Command::new("make");
+    c.configure_make_j_flag(&mut cmd);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["-j3"]);
+    assert!(cmd.get_envs().any(|(key, _)| key == "MAKEFLAGS"));
+
+    let mut cmd = Command::new("make");
+    cmd.arg("-j8");
+    c.configure_make_j_flag(&mut cmd);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["-j8"]);
+
+    let mut cmd = Command::new("make");
+    c.configure_make_j_flag_with_count(&mut cmd, 5);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["-j5"]);
+
+    let mut cmd = Command::new("make");
+    Client::new_unlimited().configure_make_j_flag(&mut cmd);
+    assert_eq!(cmd.get_args().count(), 0);
+}
+
+#[test]
+fn configure_job_count() {
+    let c = t!(Client::new(3));
+    let _a = c.acquire().unwrap();
+
+    let mut cmd = Command::new("cmake");
+    t!(c.configure_job_count(&mut cmd));
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--jobs=2"]);
+    assert!(cmd.get_envs().any(|(key, _)| key == "CARGO_MAKEFLAGS"));
+
+    let mut cmd = Command::new("cmake");
+    c.configure_job_count_exact(&mut cmd, 8);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--jobs=8"]);
+
+    let unlimited = Client::new_unlimited();
+    let mut cmd = Command::new("cmake");
+    t!(unlimited.configure_job_count(&mut cmd));
+    assert_eq!(cmd.get_args().count(), 0);
+    unlimited.configure_job_count_exact(&mut cmd, 4);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--jobs=4"]);
+    assert_eq!(cmd.get_envs().count(), 0);
+}
+
+#[test]
+fn unlimited() {
+    let c = Client::new_unlimited();
+    assert!(c.is_unlimited());
+    assert!(!t!(Client::new(1)).is_unlimited());
+
+    let tokens = (0..10).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+    assert!(c.try_acquire().unwrap().is_some());
+    assert_eq!(c.available().unwrap(), usize::MAX);
+    drop(tokens);
+
+    let mut cmd = Command::new("make");
+    c.configure_make(&mut cmd);
+    assert_eq!(cmd.get_envs().count(), 0);
+
+    let (tx, rx) = mpsc::channel();
+    let helper = c.into_helper_thread(move |a| drop(tx.send(a))).unwrap();
+    helper.request_token();
+    helper.request_token();
+    rx.recv().unwrap().unwrap();
+    rx.recv().unwrap().unwrap();
+}
+
+#[test]
+fn acquire_then_spawn() {
+    let c = t!(Client::new(1));
+    let builder = thread::Builder::new().name("worker".to_string());
+    let t = t!(c.acquire_then_spawn(builder, |_token| {
+        thread::current().name().unwrap().to_string()
+    }));
+    assert_eq!(t.join().unwrap(), "worker");
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_or_create_thread() {
+    let c = t!(Client::new(2));
+    let (tx, rx) = mpsc::channel::<()>();
+    let rx = Arc::new(std::sync::Mutex::new(rx));
+    let threads = (0..2)
+        .map(|_| {
+            let rx = rx.clone();
+            t!(c.acquire_or_create_thread(move |_token| {
+                rx.lock().unwrap().recv().unwrap_err();
+            }))
+        })
+        .collect::<Vec<_>>();
+    // Both tokens are held by the spawned threads.
+    assert!(c.try_acquire().map_or(true, |t| t.is_none()));
+    drop(tx);
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(c.available().unwrap(), 2);
+}
+
+#[cfg(any(unix, windows))]
+#[test]
+fn file_lock_client() {
+    use jobserver::FileLockClient;
+
+    let td = t!(tempfile::tempdir());
+    let path = td.path().join("jobserver.lock");
+    let c = t!(FileLockClient::open(&path, 2));
+    assert_eq!(c.limit(), 2);
+    assert!(path.exists());
+
+    let a = t!(c.acquire());
+    let b = t!(c.acquire());
+    assert_ne!(a.slot(), b.slot());
+    assert!(t!(c.try_acquire()).is_none());
+    let slot = a.slot();
+    drop(a);
+    let a = t!(c.try_acquire()).unwrap();
+    assert_eq!(a.slot(), slot);
+    drop((a, b));
+}
+
+#[test]
+fn spawn_worker() {
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let worker = t!(c.spawn_worker(|_stop| 1 + 1));
+    assert!(c.try_acquire().map_or(true, |t| t.is_none()));
+    assert_eq!(worker.join().unwrap(), 2);
+    assert_eq!(c.available().unwrap(), 1);
+
+    let worker = t!(c.spawn_worker(|stop| {
+        while !stop.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        "stopped"
+    }));
+    let ret = worker.abort(Duration::from_secs(10));
+    assert_eq!(ret.unwrap().unwrap(), "stopped");
+    assert_eq!(c.available().unwrap(), 1);
+
+    // A worker ignoring the flag keeps its token until it finishes.
+    let (tx, rx) = mpsc::channel::<()>();
+    let worker = t!(c.spawn_worker(move |_stop| rx.recv().unwrap_err()));
+    assert!(worker.abort(Duration::from_millis(10)).is_none());
+    assert_eq!(c.available().unwrap(), 0);
+    drop(tx);
+    drop(t!(c.acquire()));
+}
+
+#[test]
+fn configure_spawn_on_thread() {
+    let c = t!(Client::new(1));
+    let me = t!(env::current_exe());
+    let mut cmd = Command::new(me);
+    cmd.arg("--list");
+    let t = t!(c.configure_spawn_on_thread("child-monitor", cmd));
+    assert!(t.join().unwrap().success());
+    assert_eq!(c.available().unwrap(), 1);
+
+    let err = c.configure_spawn_on_thread("child-monitor", Command::new("/nonexistent/command"));
+    assert!(err.is_err());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn builder() {
+    use jobserver::ClientBuilder;
+
+    let c = t!(ClientBuilder::new(2)
+        .with_name_validator(|name| name.starts_with("__rust_jobserver"))
+        .build());
+    assert_eq!(c.available().unwrap(), 2);
+
+    let res = ClientBuilder::new(2).with_name_validator(|_| false).build();
+    assert_eq!(res.is_err(), cfg!(windows));
+}
+
+#[test]
+fn builder_semaphore_max() {
+    use jobserver::ClientBuilder;
+
+    let c = t!(ClientBuilder::new(2).with_semaphore_max(8).build());
+    assert_eq!(c.available().unwrap(), 2);
+    let max = c.max_capacity();
+    assert_eq!(max, if cfg!(windows) { Some(8) } else { None });
+    if cfg!(windows) {
+        assert_eq!(t!(Client::new(3)).max_capacity(), Some(3));
+        // The extra room allows releasing more tokens than were acquired.
+        t!(c.release_raw());
+        assert_eq!(c.available().unwrap(), 3);
+    }
+
+    let err = ClientBuilder::new(2)
+        .with_semaphore_max(1)
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(unix)]
+#[test]
+fn as_fd() {
+    use std::os::unix::io::AsRawFd;
+
+    let c = t!(Client::new(1));
+    let fd = c.as_fd().unwrap().try_clone_to_owned().unwrap();
+    assert_ne!(Some(fd.as_raw_fd()), c.as_raw_fd());
+    assert_eq!(c.clone().as_raw_fd(), c.as_raw_fd());
+    assert!(Client::new_unlimited().as_fd().is_none());
+
+    let mut fds = [nix::poll::PollFd::new(
+        c.as_fd().unwrap(),
+        nix::poll::PollFlags::POLLIN,
+    )];
+    assert_eq!(
+        t!(nix::poll::poll(&mut fds, nix::poll::PollTimeout::ZERO)),
+        1
+    );
+    let _a = c.acquire().unwrap();
+    assert_eq!(
+        t!(nix::poll::poll(&mut fds, nix::poll::PollTimeout::ZERO)),
+        0
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn set_close_on_exec() {
+    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+
+    let cloexec = |c: &Client| {
+        let flags = t!(fcntl(c.as_raw_fd().unwrap(), FcntlArg::F_GETFD));
+        FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC)
+    };
+    let c = t!(Client::new(1));
+    assert!(cloexec(&c));
+    t!(c.set_close_on_exec(false));
+    assert!(!cloexec(&c));
+    t!(c.set_close_on_exec(true));
+    assert!(cloexec(&c));
+
+    let err = Client::new_unlimited()
+        .set_close_on_exec(false)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[cfg(unix)]
+#[test]
+fn configure_on_unix_exec() {
+    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+
+    let c = t!(Client::new(1));
+    let cloexec = || {
+        let flags = t!(fcntl(c.as_raw_fd().unwrap(), FcntlArg::F_GETFD));
+        FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC)
+    };
+    t!(c.configure_on_unix_exec());
+    assert!(!cloexec());
+    t!(c.unconfigure_on_unix_exec());
+    assert!(cloexec());
+    t!(Client::new_unlimited().configure_on_unix_exec());
+}
+
+#[test]
+fn soft_limit() {
+    let c = t!(Client::new(1));
+    c.soft_limit(2);
+    let a = c.soft_acquire().unwrap();
+    let b = c.soft_acquire().unwrap();
+    assert_eq!(c.soft_held(), 2);
+    assert_eq!(c.available().unwrap(), 1);
+
+    // The soft budget is exhausted, so this one comes from the jobserver.
+    let d = c.soft_acquire().unwrap();
+    assert_eq!(c.soft_held(), 2);
+    assert_eq!(c.available().unwrap(), 0);
+
+    drop((a, b, d));
+    assert_eq!(c.soft_held(), 0);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_yielding() {
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let a = c.acquire_yielding().unwrap();
+    assert_eq!(c.available().unwrap(), 0);
+
+    let c2 = c.clone();
+    let t = thread::spawn(move || drop(c2.acquire_yielding_with(1, Duration::from_millis(1))));
+    thread::sleep(Duration::from_millis(10));
+    drop(a);
+    t.join().unwrap();
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn configure_make_recursive() {
+    let c = t!(Client::new(3));
+    let mut cmd = Command::new("make");
+    let reserved = t!(c.configure_make_recursive(&mut cmd, 2));
+    assert_eq!(reserved.len(), 2);
+    assert_eq!(c.available().unwrap(), 1);
+    assert!(cmd.get_envs().any(|(key, _)| key == "MAKEFLAGS"));
+    drop(reserved);
+    assert_eq!(c.available().unwrap(), 3);
+
+    // No more tokens are reserved than the jobserver has.
+    let reserved = t!(c.configure_make_recursive(&mut cmd, 5));
+    assert_eq!(reserved.len(), 3);
+    drop(reserved);
+
+    // Nor more than it has left besides the tokens already held.
+    let a = c.acquire().unwrap();
+    let reserved = t!(c.configure_make_recursive(&mut cmd, 3));
+    assert_eq!(reserved.len(), 2);
+    assert_eq!(c.available().unwrap(), 0);
+    drop((a, reserved));
+    assert_eq!(c.available().unwrap(), 3);
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn observability() {
+    use jobserver::{ChainObserver, MetricsObserver};
+
+    let metrics = Arc::new(MetricsObserver::new());
+    let c = t!(Client::new(1))
+        .with_observability(ChainObserver(metrics.clone(), MetricsObserver::new()));
+    let a = c.acquire().unwrap();
+    assert!(c.try_acquire().unwrap().is_none());
+    drop(a);
+    drop(c.try_acquire().unwrap().unwrap());
+    c.acquire().unwrap().into_inner().drop_without_releasing();
+    t!(c.release_raw());
+
+    let stats = metrics.stats();
+    assert_eq!(stats.acquires, 2);
+    assert_eq!(stats.releases, 3);
+    assert_eq!(stats.try_acquire_successes, 1);
+    assert_eq!(stats.try_acquire_failures, 1);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(c.observer().1.stats(), stats);
+    assert_eq!(c.into_inner().available().unwrap(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn acquire_signal_safe() {
+    let c = t!(Client::new(1));
+    assert_eq!(c.acquire_signal_safe(), 0);
+    assert_eq!(c.available().unwrap(), 0);
+    assert_eq!(c.release_signal_safe(), 0);
+    assert_eq!(c.available().unwrap(), 1);
+
+    let c = Client::new_unlimited();
+    assert_eq!(c.acquire_signal_safe(), 0);
+    assert_eq!(c.release_signal_safe(), 0);
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn cgroup() {
+    let dir = t!(tempfile::tempdir());
+    let cpu_max = dir.path().join("cpu.max");
+    t!(std::fs::write(&cpu_max, "250000 100000\n"));
+    let c = t!(Client::configure_cgroup(dir.path()));
+    assert_eq!(c.available().unwrap(), 3);
+
+    t!(std::fs::write(&cpu_max, "500000 100000\n"));
+    t!(c.adjust_to_cgroup(dir.path()));
+    assert_eq!(c.available().unwrap(), 5);
+
+    // Shrinking leaves acquired tokens alone until they're released.
+    let a = c.acquire().unwrap();
+    let b = c.acquire().unwrap();
+    t!(std::fs::write(&cpu_max, "100000 100000\n"));
+    t!(c.adjust_to_cgroup(dir.path()));
+    assert_eq!(c.available().unwrap(), 0);
+    drop((a, b));
+    t!(c.adjust_to_cgroup(dir.path()));
+    assert_eq!(c.available().unwrap(), 1);
+
+    t!(std::fs::write(&cpu_max, "garbage\n"));
+    assert!(c.adjust_to_cgroup(dir.path()).is_err());
+    assert!(Client::configure_cgroup(&dir.path().join("missing")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn eintr_retries() {
+    use nix::sys::pthread::{pthread_kill, pthread_self};
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+    extern "C" fn noop(_: nix::libc::c_int) {}
+
+    // No `SA_RESTART`, so that blocking reads fail with `EINTR`.
+    let action = SigAction::new(SigHandler::Handler(noop), SaFlags::empty(), SigSet::empty());
+    t!(unsafe { sigaction(Signal::SIGUSR1, &action) });
+
+    let c = t!(Client::new(0)).with_eintr_retries(0);
+    let (tx, rx) = mpsc::channel();
+    let done = Arc::new(AtomicBool::new(false));
+    let done2 = done.clone();
+    let thread = thread::spawn(move || {
+        tx.send(pthread_self()).unwrap();
+        let err = c.acquire().unwrap_err();
+        done2.store(true, Ordering::SeqCst);
+        err
+    });
+
+    // The signal may arrive before the thread blocks, so keep sending it.
+    let tid = rx.recv().unwrap();
+    while !done.load(Ordering::SeqCst) {
+        t!(pthread_kill(tid, Signal::SIGUSR1));
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(
+        thread.join().unwrap().kind(),
+        std::io::ErrorKind::Interrupted
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn open_or_create() {
+    let name = format!("__rust_jobserver_open_or_create_{}", std::process::id());
+    let (a, created) = t!(Client::open_or_create(&name, 2));
+    assert!(created);
+    let (b, created) = t!(Client::open_or_create(&name, 5));
+    assert!(!created);
+
+    // Both refer to the same semaphore with the initial limit of 2.
+    let token1 = a.acquire().unwrap();
+    let token2 = b.try_acquire().unwrap().unwrap();
+    assert!(b.try_acquire().unwrap().is_none());
+    drop(token1);
+    assert!(b.try_acquire().unwrap().is_some());
+    drop(token2);
+
+    assert!(Client::open_or_create("nul\0name", 1).is_err());
+}
+
+#[cfg(windows)]
+#[test]
+fn new_detached() {
+    let name = format!("__rust_jobserver_new_detached_{}", std::process::id());
+    let c = t!(Client::new_detached(2, &name));
+    t!(c.acquire_raw());
+    drop(c);
+
+    // The semaphore is still alive, with one token held.
+    let (c, created) = t!(Client::open_or_create(&name, 5));
+    assert!(!created);
+    assert_eq!(c.available().unwrap(), 1);
+    t!(c.release_raw());
+    assert_eq!(c.available().unwrap(), 2);
+
+    let err = Client::new_detached(2, &name).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[cfg(windows)]
+#[test]
+fn builder_security_attributes() {
+    use jobserver::ClientBuilder;
+
+    let c =
+        t!(unsafe { ClientBuilder::new(2).with_security_attributes(std::ptr::null_mut()) }.build());
+    let _a = c.acquire().unwrap();
+    let _b = c.acquire().unwrap();
+    assert!(c.try_acquire().unwrap().is_none());
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn acquire_with_context() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Acquire<'a>(&'a Client);
+
+    impl Future for Acquire<'_> {
+        type Output = std::io::Result<jobserver::Acquired>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.0.acquire_with_context(cx)
+        }
+    }
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let c = t!(Client::new(1));
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    let a = match Pin::new(&mut Acquire(&c)).poll(&mut cx) {
+        Poll::Ready(token) => t!(token),
+        Poll::Pending => panic!("token should be available"),
+    };
+    let mut future = Acquire(&c);
+    assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+
+    drop(a);
+    let b = loop {
+        match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(token) => break t!(token),
+            Poll::Pending => thread::park(),
+        }
+    };
+    assert_eq!(c.available().unwrap(), 0);
+    drop(b);
+    assert_eq!(c.available().unwrap(), 1);
+
+    // Giving up on a pending acquisition doesn't leave a token behind.
+    let a = t!(c.acquire());
+    assert!(Pin::new(&mut Acquire(&c)).poll(&mut cx).is_pending());
+    drop(a);
+    thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(c.available().unwrap(), 1);
+    let b = t!(c.try_acquire()).unwrap();
+    drop((b, c));
+}
+
+#[cfg(all(
+    feature = "futures",
+    any(windows, all(target_os = "linux", target_env = "gnu"))
+))]
+#[test]
+fn acquire_batch_async() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let c = t!(Client::new(2));
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut stream = c.acquire_batch_async(3);
+    assert_eq!(stream.size_hint(), (3, Some(3)));
+
+    let mut tokens = Vec::new();
+    for _ in 0..2 {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(token)) => tokens.push(t!(token)),
+            _ => panic!("token should be available"),
+        }
+    }
+    assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+
+    drop(tokens.pop());
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(token)) => break tokens.push(t!(token)),
+            Poll::Ready(None) => panic!("stream ended early"),
+            Poll::Pending => thread::park(),
+        }
+    }
+    assert!(matches!(
+        Pin::new(&mut stream).poll_next(&mut cx),
+        Poll::Ready(None)
+    ));
+    assert_eq!(c.available().unwrap(), 0);
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn try_acquire_many_nonblocking() {
+    let c = t!(Client::new(3));
+    let tokens = t!(c.try_acquire_many_nonblocking(2));
+    assert_eq!(tokens.len(), 2);
+    assert!(t!(c.try_acquire_many_nonblocking(2)).is_empty());
+    // The failed attempt didn't keep the last token.
+    assert_eq!(t!(c.try_acquire_many_nonblocking(1)).len(), 1);
+    drop(tokens);
+    assert_eq!(t!(c.try_acquire_many_nonblocking(3)).len(), 3);
+    assert!(t!(c.try_acquire_many_nonblocking(4)).is_empty());
+    assert_eq!(c.available().unwrap(), 3);
+}
+
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn take_snapshot_and_drain() {
+    let c = t!(Client::new(3));
+    let token = t!(c.acquire());
+    let drained = t!(c.take_snapshot_and_drain());
+    assert_eq!(drained.count(), 2);
+    assert_eq!(c.available().unwrap(), 0);
+    t!(drained.release_all());
+    assert_eq!(c.available().unwrap(), 2);
+
+    drop(t!(c.take_snapshot_and_drain()));
+    drop(token);
+    assert_eq!(c.available().unwrap(), 3);
+}
+
+#[test]
+fn try_downgrade_to_token_count() {
+    let c = t!(Client::new(4));
+    assert!(t!(c.try_downgrade_to_token_count(2)));
+    assert_eq!(c.available().unwrap(), 2);
+    assert!(t!(c.try_downgrade_to_token_count(3)));
+    assert_eq!(c.available().unwrap(), 2);
+    assert_eq!(c.dump_state().unwrap().capacity, Some(2));
+
+    let err = Client::new_unlimited()
+        .try_downgrade_to_token_count(1)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn close() {
+    let c = t!(Client::new(1));
+    t!(c.close());
+    t!(Client::new_unlimited().close());
+
+    let c = t!(Client::new(1));
+    let token = t!(c.acquire());
+    let err = c.clone().close().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    drop(token);
+    t!(c.close());
+}
+
+#[test]
+fn configure_noop() {
+    let mut cmd = Command::new("foo");
+    Client::configure_noop(&mut cmd);
+    let envs = cmd.get_envs().collect::<Vec<_>>();
+    for var in ["CARGO_MAKEFLAGS", "MAKEFLAGS"] {
+        assert!(envs.contains(&(var.as_ref(), Some("--jobserver-auth=none".as_ref()))));
+    }
+}
+
+#[cfg(all(unix, feature = "mio"))]
+#[test]
+fn mio() {
+    use mio::{Events, Poll, Token};
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let a = c.acquire().unwrap();
+    let mut poll = t!(Poll::new());
+    let mut events = Events::with_capacity(4);
+    t!(c.register_with_mio(poll.registry(), Token(7)));
+
+    t!(poll.poll(&mut events, Some(Duration::from_millis(10))));
+    assert!(events.is_empty());
+
+    drop(a);
+    t!(poll.poll(&mut events, Some(Duration::from_secs(10))));
+    let event = events.iter().next().unwrap();
+    assert_eq!(event.token(), Token(7));
+    let token = t!(c.complete_acquire(event)).unwrap();
+    assert!(t!(c.complete_acquire(event)).is_none());
+    drop(token);
+
+    let err = Client::new_unlimited()
+        .register_with_mio(poll.registry(), Token(8))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn new_from_cpuinfo() {
+    let c = t!(Client::new_from_cpuinfo());
+    let cpus = c.available().unwrap();
+    assert!(cpus >= 1);
+    let c = t!(Client::new_from_cpuinfo_capped(1));
+    assert_eq!(c.available().unwrap(), 1);
+    let c = t!(Client::new_from_cpuinfo_capped(usize::MAX));
+    assert_eq!(c.available().unwrap(), cpus);
+}
+
+#[cfg(all(target_os = "linux", feature = "priority-inheritance"))]
+#[test]
+fn acquire_with_priority_inheritance() {
+    use nix::libc;
+
+    fn policy() -> (i32, i32) {
+        let mut policy = 0;
+        let mut param = libc::sched_param { sched_priority: 0 };
+        let r =
+            unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+        assert_eq!(r, 0);
+        (policy, param.sched_priority)
+    }
+
+    let c = t!(Client::new(1));
+    let err = c.acquire_with_priority_inheritance(0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let before = policy();
+    match c.acquire_with_priority_inheritance(1) {
+        Ok(token) => drop(token),
+        // Unprivileged processes may not raise their priority.
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied),
+    }
+    assert_eq!(policy(), before);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn new_within_system_limits() {
+    let limit = Client::global_limit();
+    if let Some(limit) = limit {
+        assert!(limit >= 1);
+    }
+    let c = t!(Client::new_within_system_limits(2));
+    assert_eq!(
+        c.available().unwrap(),
+        limit.map_or(2, |limit| limit.min(2))
+    );
+}
+
+#[test]
+fn effective_parallelism() {
+    let cpus = thread::available_parallelism().unwrap().get();
+    assert_eq!(
+        Client::new_unlimited().effective_parallelism().unwrap(),
+        cpus
+    );
+
+    let c = t!(Client::new(cpus + 1));
+    assert_eq!(c.effective_parallelism().unwrap(), cpus);
+    let tokens = (0..cpus).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+    assert_eq!(c.effective_parallelism().unwrap(), 1);
+    drop(tokens);
+}
+
+#[test]
+fn wait_for_all_released() {
+    use std::time::Duration;
+
+    let err = Client::new_unlimited()
+        .wait_for_all_released(Duration::from_millis(10))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+
+    let c = t!(Client::new(2));
+    assert!(t!(c.wait_for_all_released(Duration::from_millis(10))));
+    let a = t!(c.acquire());
+    let b = t!(c.acquire());
+    assert!(!t!(c.wait_for_all_released(Duration::from_millis(50))));
+
+    drop(a);
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        drop(b);
+    });
+    t!(c.wait_for_all_released_blocking());
+    t.join().unwrap();
+}
+
+#[test]
+fn scope() {
+    let c = t!(Client::new(2));
+    let running = std::sync::atomic::AtomicUsize::new(0);
+    let max_running = std::sync::atomic::AtomicUsize::new(0);
+    let results = c.scope(|s| {
+        let handles = (0..6)
+            .map(|i| {
+                let (running, max_running) = (&running, &max_running);
+                t!(s.spawn(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(std::time::Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i * 2
+                }))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(results, [0, 2, 4, 6, 8, 10]);
+    assert!(max_running.into_inner() <= 2);
+    assert_eq!(c.available().unwrap(), 2);
+}
+
+#[cfg(windows)]
+#[test]
+fn set_inherit() {
+    let c = t!(Client::new(1));
+    t!(c.set_inherit(true));
+    assert!(t!(c.is_inheritable()));
+    t!(c.set_inherit(false));
+    assert!(!t!(c.is_inheritable()));
+    assert!(Client::new_unlimited().set_inherit(true).is_err());
+}
+
+#[test]
+fn acquire_counted() {
+    let c = t!(Client::new(2));
+    assert_eq!(c.acquisition_count(), 0);
+    let (a, n) = t!(c.acquire_counted());
+    assert_eq!(n, 1);
+    let b = c.clone().acquire().unwrap();
+    assert_eq!(c.available().unwrap(), 0);
+    assert_eq!(c.acquisition_count(), 2);
+    drop((a, b));
+    let (_a, n) = t!(c.acquire_counted());
+    assert_eq!(n, 3);
+    assert_eq!(c.acquisition_count(), 3);
+}
+
+#[test]
+fn acquire_nonblocking_count() {
+    assert_eq!(
+        Client::new_unlimited().acquire_nonblocking_count(),
+        Some(usize::MAX)
+    );
+
+    let c = t!(Client::new(3));
+    assert_eq!(c.acquire_nonblocking_count(), Some(3));
+    let a = t!(c.acquire());
+    let b = t!(c.clone().acquire());
+    assert_eq!(c.acquire_nonblocking_count(), Some(1));
+    drop(a);
+    assert_eq!(c.acquire_nonblocking_count(), Some(2));
+    t!(c.acquire_raw());
+    assert_eq!(c.acquire_nonblocking_count(), Some(1));
+    t!(c.release_raw());
+    drop(b);
+    assert_eq!(c.acquire_nonblocking_count(), Some(3));
+
+    // Moving tokens out shrinks the jobserver instead.
+    let other = t!(Client::new(0));
+    t!(c.transfer(&other, 2));
+    assert_eq!(c.acquire_nonblocking_count(), Some(1));
+    assert_eq!(other.acquire_nonblocking_count(), Some(2));
+}
+
+#[test]
+fn pending_acquires() {
+    use std::time::{Duration, Instant};
+
+    let c = t!(Client::new(1));
+    assert_eq!(c.pending_acquires(), 0);
+    let token = t!(c.acquire());
+    assert_eq!(c.max_pending_acquires(), 1);
+
+    let threads = (0..2)
+        .map(|_| {
+            let c = c.clone();
+            thread::spawn(move || drop(c.acquire().unwrap()))
+        })
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    while c.pending_acquires() < 2 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::yield_now();
+    }
+    drop(token);
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(c.pending_acquires(), 0);
+    assert_eq!(c.max_pending_acquires(), 2);
+}
+
+#[test]
+fn env_prefix() {
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("foo");
+    c.configure_env_prefix("JOBSERVER_TEST_LINK", &mut cmd);
+    let (name, value) = cmd.get_envs().next().unwrap();
+    assert_eq!(name, "JOBSERVER_TEST_LINK_JOBSERVER_AUTH");
+    assert_eq!(cmd.get_envs().count(), 1);
+
+    env::set_var(name, value.unwrap());
+    let c2 = unsafe { Client::from_env_prefix("JOBSERVER_TEST_LINK") }.unwrap();
+    assert!(!t!(c.dump_state()).is_inherited);
+    assert!(t!(c2.dump_state()).is_inherited);
+    let a = c2.acquire().unwrap();
+    assert_eq!(c.available().unwrap(), 0);
+    drop(a);
+
+    let err = unsafe { Client::from_env_prefix("JOBSERVER_TEST_MISSING") }.unwrap_err();
+    assert!(matches!(err.kind(), jobserver::FromEnvErrorKind::NoEnvVar));
+}
+
+#[test]
+fn heartbeat() {
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let beats = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let beats2 = beats.clone();
+    let handle = t!(c.heartbeat(
+        Duration::from_millis(10),
+        Box::new(move || {
+            beats2.fetch_add(1, Ordering::SeqCst);
+        }),
+    ));
+
+    // Nothing's waiting for a token yet.
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(beats.load(Ordering::SeqCst), 0);
+
+    let a = c.acquire().unwrap();
+    let c2 = c.clone();
+    let waiter = thread::spawn(move || drop(c2.acquire().unwrap()));
+    while beats.load(Ordering::SeqCst) < 2 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(a);
+    waiter.join().unwrap();
+
+    handle.stop();
+    let after_stop = beats.load(Ordering::SeqCst);
+    let _a = c.acquire().unwrap();
+    let c2 = c.clone();
+    let _waiter = thread::spawn(move || drop(c2.acquire().unwrap()));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(beats.load(Ordering::SeqCst), after_stop);
+}
+
+#[test]
+fn with_on_starvation() {
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let alerts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let alerts2 = alerts.clone();
+    let handle = t!(c.with_on_starvation(
+        Duration::from_millis(20),
+        Box::new(move || {
+            alerts2.fetch_add(1, Ordering::SeqCst);
+        }),
+    ));
+
+    // Acquisitions which don't wait for long aren't starved.
+    for _ in 0..5 {
+        drop(c.acquire().unwrap());
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(alerts.load(Ordering::SeqCst), 0);
+
+    let a = c.acquire().unwrap();
+    let c2 = c.clone();
+    let waiter = thread::spawn(move || drop(c2.acquire().unwrap()));
+    while alerts.load(Ordering::SeqCst) == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(a);
+    waiter.join().unwrap();
+    handle.stop();
+}
+
+#[test]
+fn dump_state() {
+    let c = t!(Client::new(3));
+    let _a = c.acquire().unwrap();
+    let state = t!(c.dump_state());
+    assert_eq!(state.available, Some(2));
+    assert_eq!(state.capacity, Some(3));
+    assert!(!state.is_inherited);
+    #[cfg(unix)]
+    {
+        assert_eq!(state.platform, "unix-pipe");
+        assert!(state.identifier.starts_with("fd:"));
+        assert_eq!(
+            state.to_string(),
+            format!("unix-pipe {}, 2/3 tokens available", state.identifier)
+        );
+    }
+
+    let state = t!(Client::new_unlimited().dump_state());
+    assert_eq!(state.platform, "unlimited");
+    assert_eq!(state.to_string(), "unlimited");
+}
+
+#[test]
+fn conditional_acquire() {
+    let c = t!(Client::new(1));
+    assert!(t!(c.conditional_acquire(|| false)).is_none());
+    assert_eq!(c.available().unwrap(), 1);
+    let a = t!(c.conditional_acquire(|| true)).unwrap();
+
+    // Elsewhere anonymous pipes can't be read without blocking, so waiting
+    // for a token can't be cancelled.
+    #[cfg(any(all(target_os = "linux", target_env = "gnu"), windows))]
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled2 = cancelled.clone();
+        let c2 = c.clone();
+        let waiter = thread::spawn(move || {
+            t!(c2.conditional_acquire(|| !cancelled2.load(Ordering::SeqCst))).is_none()
+        });
+        thread::sleep(std::time::Duration::from_millis(20));
+        cancelled.store(true, Ordering::SeqCst);
+        assert!(waiter.join().unwrap());
+    }
+    drop(a);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn transfer() {
+    let a = t!(Client::new(3));
+    let b = t!(Client::new(1));
+    t!(a.transfer(&b, 2));
+    assert_eq!(a.available().unwrap(), 1);
+    assert_eq!(b.available().unwrap(), 3);
+    assert_eq!(t!(b.dump_state()).capacity, Some(3));
+
+    // `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+    #[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+    {
+        use std::time::Duration;
+
+        let token = a.acquire().unwrap();
+        assert!(!t!(a.transfer_timeout(&b, 1, Duration::from_millis(20))));
+        drop(token);
+        assert!(t!(a.transfer_timeout(&b, 1, Duration::from_secs(10))));
+        assert_eq!(a.available().unwrap(), 0);
+        assert_eq!(b.available().unwrap(), 4);
+        assert_eq!(t!(a.dump_state()).capacity, Some(0));
+    }
+}
+
+#[test]
+fn with_pid_labeling() {
+    let c = t!(Client::new(2)).with_pid_labeling();
+    assert!(c.dump_holders().is_empty());
+
+    let a = t!(c.acquire());
+    let b = t!(c.clone().acquire());
+    assert_eq!(a.pid(), std::process::id());
+    assert_eq!(
+        c.dump_holders(),
+        [(a.pid(), a.acquired_at()), (b.pid(), b.acquired_at())]
+    );
+    assert_eq!(c.available().unwrap(), 0);
+
+    drop(a);
+    assert_eq!(c.dump_holders(), [(b.pid(), b.acquired_at())]);
+    drop(b);
+    assert!(c.dump_holders().is_empty());
+    assert_eq!(c.into_inner().available().unwrap(), 2);
+}
+
+#[test]
+fn with_overflow_protection() {
+    let c = t!(Client::new(2)).with_overflow_protection(1);
+    t!(c.acquire_raw());
+    t!(c.clone().acquire_raw());
+    assert_eq!(c.held(), 2);
+    assert_eq!(c.available().unwrap(), 0);
+    t!(c.release_raw());
+    t!(c.release_raw());
+    assert_eq!(c.held(), 0);
+    assert_eq!(c.available().unwrap(), 2);
+
+    // The implicit token may be released once.
+    t!(c.release_raw());
+    let err = c.release_raw().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(c.into_inner().available().unwrap(), 3);
+}
+
+#[test]
+fn audit_log() {
+    use std::sync::Mutex;
+
+    struct SharedSink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let c = t!(Client::new(1)).audit_log(Box::new(SharedSink(log.clone())));
+    let token = t!(c.acquire());
+    drop(token);
+    t!(c.acquire()).into_inner().drop_without_releasing();
+    t!(c.release_raw());
+    let c = c.into_inner();
+    drop(c.acquire().unwrap());
+
+    let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("{\"timestamp_ms\":"));
+    assert!(lines[0].contains("\"op\":\"acquire\""));
+    assert!(lines[1].contains("\"op\":\"release\""));
+    assert!(lines[2].contains("\"op\":\"acquire\""));
+    assert!(lines[3].contains("\"op\":\"release\""));
+    assert!(lines.iter().all(|l| l.ends_with("\"success\":true}")));
+}
+
+#[test]
+fn new_limited_by_cpu() {
+    let cpus = std::thread::available_parallelism().unwrap().get();
+    let c = t!(Client::new_limited_by_cpu());
+    assert_eq!(t!(c.dump_state()).capacity, Some(cpus));
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let c = t!(Client::new_limited_by_load(-1.0));
+        assert_eq!(t!(c.dump_state()).capacity, Some(1));
+        let c = t!(Client::new_limited_by_load(f64::MAX));
+        assert_eq!(t!(c.dump_state()).capacity, Some(cpus));
+    }
+    #[cfg(windows)]
+    assert_eq!(
+        Client::new_limited_by_load(1.0).unwrap_err().kind(),
+        std::io::ErrorKind::Unsupported
+    );
+}
+
+#[test]
+fn configure_with_flags() {
+    use jobserver::MakeFlag;
+
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("foo");
+    c.configure_mflags(&mut cmd);
+    let envs = cmd
+        .get_envs()
+        .map(|(k, v)| (k.to_str().unwrap().to_string(), v.unwrap().to_os_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(envs.len(), 2);
+    assert_eq!(envs[0].0, "MAKEFLAGS");
+    assert_eq!(envs[1].0, "MFLAGS");
+    assert_eq!(envs[0].1, envs[1].1);
+
+    let mut cmd = Command::new("foo");
+    c.configure_with_flags(
+        &mut cmd,
+        &[
+            MakeFlag::CargoMakeFlags,
+            MakeFlag::Custom("MY_FLAGS".to_string()),
+        ],
+    );
+    let keys = cmd.get_envs().map(|(k, _)| k).collect::<Vec<_>>();
+    assert_eq!(keys, ["CARGO_MAKEFLAGS", "MY_FLAGS"]);
+}
+
+#[test]
+fn configure_cargo_build() {
+    use std::ffi::OsStr;
+
+    let c = t!(Client::new(2));
+    let get = |cmd: &Command, key: &str| {
+        cmd.get_envs()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v.map(|v| v.to_os_string()))
+    };
+    let mut cmd = Command::new("cargo");
+    c.configure_cargo_build(&mut cmd);
+    assert!(get(&cmd, "MAKEFLAGS").is_some());
+    assert_eq!(get(&cmd, "MAKEFLAGS"), get(&cmd, "CARGO_MAKEFLAGS"));
+    if env::var_os("CARGO_BUILD_JOBS").is_none() {
+        assert_eq!(get(&cmd, "CARGO_BUILD_JOBS").unwrap(), OsStr::new("2"));
+    }
+
+    let mut cmd = Command::new("cargo");
+    cmd.env("CARGO_BUILD_JOBS", "7");
+    c.configure_cargo_build(&mut cmd);
+    assert_eq!(get(&cmd, "CARGO_BUILD_JOBS").unwrap(), OsStr::new("7"));
+}
+
+#[test]
+fn batch_acquire_release() {
+    let c = t!(Client::new(1));
+    let mut seen = Vec::new();
+    t!(c.batch_acquire_release(0..3, |i| {
+        assert_eq!(c.available().unwrap(), 0);
+        seen.push(i);
+    }));
+    assert_eq!(seen, [0, 1, 2]);
+    assert_eq!(c.available().unwrap(), 1);
+
+    let c2 = c.clone();
+    let r = std::panic::catch_unwind(move || {
+        c2.batch_acquire_release(Some(()), |()| panic!("worker failed"))
+    });
+    assert!(r.is_err());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn configure_as_env_var() {
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("foo");
+    c.configure_as_env_var("MY_JOBSERVER", &mut cmd);
+    let keys = cmd.get_envs().map(|(k, _)| k).collect::<Vec<_>>();
+    assert_eq!(keys, ["MY_JOBSERVER"]);
+}
+
+#[test]
+fn into_channel() {
+    let c = t!(Client::new(1));
+    let (requests, tokens) = t!(c.clone().into_channel(2));
+    requests.send(()).unwrap();
+    requests.send(()).unwrap();
+    let a = tokens.recv().unwrap();
+    assert!(tokens
+        .recv_timeout(std::time::Duration::from_millis(50))
+        .is_err());
+    drop(a);
+    let b = tokens.recv().unwrap();
+    drop(b);
+
+    drop(requests);
+    assert!(tokens.recv().is_err());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn with_guard_type() {
+    use jobserver::{Acquired, GuardTrait, SimpleGuard, TimedGuard};
+
+    struct LeakingGuard;
+
+    impl GuardTrait for LeakingGuard {
+        fn new(client: &Client, token: Acquired) -> LeakingGuard {
+            assert_eq!(client.available().unwrap(), 0);
+            token.drop_without_releasing();
+            LeakingGuard
+        }
+    }
+
+    let c = t!(Client::new(1));
+    let guard = t!(c.with_guard_type::<SimpleGuard>().acquire());
+    assert_eq!(c.available().unwrap(), 0);
+    drop(guard);
+    assert_eq!(c.available().unwrap(), 1);
+
+    let guard = t!(c.with_guard_type::<TimedGuard>().acquire());
+    assert!(guard.acquired_at() <= std::time::Instant::now());
+    drop(guard.into_inner());
+
+    let LeakingGuard = t!(c.with_guard_type::<LeakingGuard>().acquire());
+    assert_eq!(c.available().unwrap(), 0);
+    t!(c.release_raw());
+}
+
+#[test]
+fn wait_all() {
+    let c = t!(Client::new(2));
+    let jobs = (0..2)
+        .map(|_| {
+            let token = t!(c.acquire());
+            (thread::spawn(|| {}), token)
+        })
+        .collect();
+    t!(c.wait_all(jobs));
+    assert_eq!(c.available().unwrap(), 2);
+
+    let jobs = vec![
+        (thread::spawn(|| panic!("job failed")), t!(c.acquire())),
+        (thread::spawn(|| {}), t!(c.acquire())),
+    ];
+    let c2 = c.clone();
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || c2.wait_all(jobs)));
+    assert!(r.is_err());
+    assert_eq!(c.available().unwrap(), 2);
+}
+
+#[test]
+fn configure_recursive_jobs_limit() {
+    let c = t!(Client::new(4));
+    let mut cmd = Command::new(env::current_exe().unwrap());
+    cmd.arg("--list").stdout(std::process::Stdio::null());
+    let limit = t!(c.configure_recursive_jobs_limit(&mut cmd, 2));
+    assert_eq!(limit.reserved(), 2);
+    assert_eq!(limit.client().available().unwrap(), 2);
+    assert_eq!(c.available().unwrap(), 2);
+    assert!(cmd.get_envs().any(|(k, _)| k == "MAKEFLAGS"));
+
+    let child = t!(cmd.spawn());
+    let status = t!(limit.release_on_exit(child)).join().unwrap();
+    assert!(t!(status).success());
+    assert_eq!(c.available().unwrap(), 4);
+
+    let mut cmd = Command::new("foo");
+    let limit = t!(c.configure_recursive_jobs_limit(&mut cmd, 1));
+    assert_eq!(limit.reserved(), 3);
+    assert_eq!(limit.client().available().unwrap(), 1);
+    drop(limit);
+    let limit = t!(c.configure_recursive_jobs_limit(&mut cmd, 10));
+    assert_eq!(limit.reserved(), 0);
+    drop(limit);
+
+    // Tokens already held count towards the reserve.
+    let held = (0..3).map(|_| c.acquire().unwrap()).collect::<Vec<_>>();
+    let limit = t!(c.configure_recursive_jobs_limit(&mut cmd, 2));
+    assert_eq!(limit.reserved(), 0);
+    drop((held, limit));
+    assert_eq!(c.available().unwrap(), 4);
+}
+
+#[test]
+fn hooks() {
+    use std::sync::atomic::AtomicUsize;
+
+    let c = t!(Client::new(1));
+    let pre = Arc::new(AtomicUsize::new(0));
+    let post = Arc::new(AtomicUsize::new(0));
+    let released = Arc::new(AtomicUsize::new(0));
+    let (pre2, post2, released2) = (pre.clone(), post.clone(), released.clone());
+    c.hook_pre_acquire(Box::new(move || {
+        pre2.fetch_add(1, Ordering::SeqCst);
+    }));
+    c.hook_post_acquire(Box::new(move |_wait| {
+        post2.fetch_add(1, Ordering::SeqCst);
+    }));
+    c.hook_post_release(Box::new(move || {
+        released2.fetch_add(1, Ordering::SeqCst);
+    }));
+
+    drop(t!(c.acquire()));
+    t!(c.acquire_raw());
+    t!(c.release_raw());
+    assert_eq!(pre.load(Ordering::SeqCst), 2);
+    assert_eq!(post.load(Ordering::SeqCst), 2);
+    assert_eq!(released.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn observe_releases() {
+    use std::time::Duration;
+
+    let c = t!(Client::new(1));
+    let (tx, rx) = mpsc::channel();
+    let untracked = t!(c.acquire());
+    c.observe_releases(Box::new(move |held| tx.send(held).unwrap()));
+    drop(untracked);
+    assert!(rx.try_recv().is_err());
+
+    let token = t!(c.acquire());
+    thread::sleep(Duration::from_millis(10));
+    drop(token);
+    assert!(rx.try_recv().unwrap() >= Duration::from_millis(10));
+
+    let token = t!(c.acquire());
+    c.clear_observe();
+    drop(token);
+    drop(t!(c.acquire()));
+    assert!(rx.try_recv().is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn acquire_in_rayon_thread_pool() {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let c = t!(Client::new(1));
+    let token = t!(c.acquire_in_rayon_thread_pool(&pool));
+
+    // The pool's only worker keeps running other jobs while waiting for a
+    // token, including the one releasing it.
+    let token = pool.install(|| {
+        pool.spawn(move || drop(token));
+        c.acquire_in_rayon_thread_pool(&pool)
+    });
+    drop(t!(token));
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_notifying() {
+    use std::sync::{Condvar, Mutex};
+
+    let c = t!(Client::new(1));
+    let token = t!(c.acquire());
+    let cv = Arc::new((Mutex::new(false), Condvar::new()));
+    let cv2 = cv.clone();
+    let c2 = c.clone();
+    let t = thread::spawn(move || c2.acquire_notifying(cv2).unwrap());
+
+    let (lock, cvar) = &*cv;
+    assert!(!*lock.lock().unwrap());
+    drop(token);
+    let done = cvar
+        .wait_while(lock.lock().unwrap(), |done| !*done)
+        .unwrap();
+    assert!(*done);
+    drop(done);
+    drop(t.join().unwrap());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn fd_passing() {
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    let (a, b) = t!(UnixStream::pair());
+    let c = t!(Client::new(1));
+    t!(c.configure_fd_passing(a.as_raw_fd()));
+    let received = t!(Client::recv_fd_passing(b.as_raw_fd()));
+    let token = t!(received.acquire());
+    assert_eq!(c.available().unwrap(), 0);
+    drop(token);
+    assert_eq!(c.available().unwrap(), 1);
+
+    t!(Client::new_unlimited().configure_fd_passing(a.as_raw_fd()));
+    assert!(t!(Client::recv_fd_passing(b.as_raw_fd())).is_unlimited());
+
+    drop(a);
+    let err = Client::recv_fd_passing(b.as_raw_fd()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn for_each_parallel() {
+    use std::sync::atomic::AtomicUsize;
+
+    let c = t!(Client::new(4));
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    let sum = AtomicUsize::new(0);
+    t!(c.for_each_parallel(1..=20, 2, |i| {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        thread::sleep(std::time::Duration::from_millis(1));
+        sum.fetch_add(i, Ordering::SeqCst);
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }));
+    assert_eq!(sum.into_inner(), 210);
+    assert!(max_running.into_inner() <= 2);
+    assert_eq!(c.available().unwrap(), 4);
+
+    let err = c
+        .for_each_parallel(0..10, 3, |i| {
+            if i == 5 {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "five"))
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap_err();
+    assert_eq!(err.to_string(), "five");
+    let err = c.for_each_parallel(0..1, 0, |_| Ok(())).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn acquire_with_span() {
+    let c = t!(Client::new(1));
+    let token = t!(c.acquire_with_span("compile"));
+    assert_eq!(token.span_name(), "compile");
+    assert_eq!(token.thread_id(), thread::current().id());
+    assert!(token.elapsed() <= token.acquired_at().elapsed());
+    assert_eq!(c.available().unwrap(), 0);
+    drop(token);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_instrumented() {
+    let c = t!(Client::new(1));
+    let c2 = c.clone();
+    let (token, line) = thread::Builder::new()
+        .name("linker".to_string())
+        .spawn(move || (c2.acquire_instrumented("link"), line!()))
+        .unwrap()
+        .join()
+        .unwrap();
+    let token = t!(token);
+    assert_eq!(token.name(), "link");
+    assert_eq!(token.location().file(), file!());
+    assert_eq!(token.location().line(), line);
+    assert_eq!(token.thread_name(), Some("linker"));
+
+    let debug = format!("{token:?}");
+    let prefix = format!(
+        "Token[link] acquired at {}:{line} by thread 'linker', held for ",
+        file!()
+    );
+    assert!(debug.starts_with(&prefix), "{debug}");
+    assert!(debug.ends_with('s'), "{debug}");
+    let span = token.into_span();
+    assert_eq!(span.span_name(), "link");
+    assert_eq!(c.available().unwrap(), 0);
+    drop(span);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[cfg(feature = "debug-backtraces")]
+#[test]
+fn debug_acquire() {
+    use std::time::Duration;
+
+    env::set_var("JOBSERVER_DEBUG_TIMEOUT", "0.01");
+    let c = t!(Client::new(1));
+    drop(t!(c.debug_acquire()));
+
+    let a = t!(c.acquire());
+    let c2 = c.clone();
+    let waiter = thread::spawn(move || c2.debug_acquire().map(drop));
+    thread::sleep(Duration::from_millis(50));
+    drop(a);
+    t!(waiter.join().unwrap());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn into_arc() {
+    let c = t!(Client::new_arc(1));
+    let c2 = c.clone();
+    let token = t!(thread::spawn(move || c2.acquire()).join().unwrap());
+    assert!(c.try_acquire().map_or(true, |t| t.is_none()));
+    drop(token);
+    t!(c.acquire_raw());
+    t!(c.release_raw());
+
+    let c = t!(Client::new(2)).into_arc();
+    assert_eq!(c.available().unwrap(), 2);
+}
+
+#[test]
+fn acquire_and_run() {
+    let c = t!(Client::new(1));
+    let available = t!(c.acquire_and_run(|| c.available().unwrap()));
+    assert_eq!(available, 0);
+    assert_eq!(c.available().unwrap(), 1);
+
+    let res = std::panic::catch_unwind(|| c.acquire_and_run(|| panic!("oops")));
+    assert!(res.is_err());
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn try_acquire_and_run() {
+    let c = t!(Client::new(1));
+    assert_eq!(t!(c.try_acquire_and_run(|| 2).unwrap()), 2);
+    let token = t!(c.acquire());
+    assert!(c.try_acquire_and_run(|| panic!("no token")).is_none());
+    drop(token);
+    assert_eq!(c.available().unwrap(), 1);
+}
+
+#[test]
+fn acquire_with_hook() {
+    let c = t!(Client::new(1));
+    let events = std::cell::RefCell::new(Vec::new());
+    let token = t!(c.acquire_with_hook(
+        || events.borrow_mut().push("pre"),
+        |_wait| events.borrow_mut().push("post"),
+    ));
+    drop(token);
+    assert_eq!(events.into_inner(), ["pre", "post"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn new_from_process_count() {
+    let mut child = t!(Command::new("sleep").arg("60").spawn());
+    let mut exited = t!(Command::new("true").spawn());
+    t!(exited.wait());
+
+    let c = t!(Client::new_from_process_count(&[
+        std::process::id(),
+        child.id(),
+        exited.id()
+    ]));
+    assert_eq!(t!(c.dump_state()).capacity, Some(2));
+    assert_eq!(c.available().unwrap(), 0);
+    t!(child.kill());
+    t!(child.wait());
+    let token = t!(c.acquire());
+    assert_eq!(c.live(), 1);
+    drop(token);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn configure_inherit_exact() {
+    use std::os::unix::io::AsRawFd;
+
+    // Neither has `CLOEXEC` set, so both would be inherited by default.
+    let (keep, _keep_write) = nix::unistd::pipe().unwrap();
+    let (close, _close_write) = nix::unistd::pipe().unwrap();
+    let c = t!(Client::new(1));
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(format!(
+        "[ -e /dev/fd/{} ] && [ ! -e /dev/fd/{} ]",
+        keep.as_raw_fd(),
+        close.as_raw_fd()
+    ));
+    c.configure_inherit_exact(&mut cmd, &[keep.as_raw_fd()]);
+    assert!(t!(cmd.status()).success());
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn acquire_select() {
+    let a = t!(Client::new(0));
+    let b = t!(Client::new(1));
+    let (i, token) = t!(Client::acquire_select(&[&a, &b]));
+    assert_eq!(i, 1);
+    assert_eq!(b.available().unwrap(), 0);
+
+    let a2 = a.clone();
+    let t = thread::spawn(move || a2.release_raw().unwrap());
+    let (i, token2) = t!(Client::acquire_select(&[&a, &b]));
+    assert_eq!(i, 0);
+    t.join().unwrap();
+    drop((token, token2));
+    assert_eq!(a.available().unwrap(), 1);
+    assert_eq!(b.available().unwrap(), 1);
+
+    let unlimited = Client::new_unlimited();
+    assert_eq!(t!(Client::acquire_select(&[&a, &unlimited])).0, 1);
+    assert!(Client::acquire_select(&[]).is_err());
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn acquire_select_hooks() {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    let a = t!(Client::new(0));
+    let b = t!(Client::new(0));
+    let counters = [&a, &b].map(|c| {
+        let pre = Arc::new(AtomicUsize::new(0));
+        let post = Arc::new(AtomicUsize::new(0));
+        let (pre2, post2) = (pre.clone(), post.clone());
+        c.hook_pre_acquire(Box::new(move || {
+            pre2.fetch_add(1, Ordering::SeqCst);
+        }));
+        c.hook_post_acquire(Box::new(move |_wait| {
+            post2.fetch_add(1, Ordering::SeqCst);
+        }));
+        (pre, post)
+    });
+
+    let (a2, b2) = (a.clone(), b.clone());
+    let waiter = thread::spawn(move || t!(Client::acquire_select(&[&a2, &b2])).0);
+    while a.pending_acquires() == 0 || b.pending_acquires() == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    t!(b.release_raw());
+    assert_eq!(waiter.join().unwrap(), 1);
+    assert_eq!(a.pending_acquires(), 0);
+    assert_eq!(b.pending_acquires(), 0);
+    assert_eq!(counters[0].0.load(Ordering::SeqCst), 1);
+    assert_eq!(counters[0].1.load(Ordering::SeqCst), 0);
+    assert_eq!(counters[1].0.load(Ordering::SeqCst), 1);
+    assert_eq!(counters[1].1.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn duplicate() {
+    assert!(t!(Client::new_unlimited().duplicate()).is_unlimited());
+
+    let c = t!(Client::new(3));
+    let token = t!(c.acquire());
+    let dup = t!(c.duplicate());
+    assert_eq!(dup.available().unwrap(), 2);
+
+    let dup_token = t!(dup.acquire());
+    assert_eq!(dup.available().unwrap(), 1);
+    assert_eq!(c.available().unwrap(), 2);
+    drop(token);
+    assert_eq!(c.available().unwrap(), 3);
+    assert_eq!(dup.available().unwrap(), 1);
+    drop(dup_token);
+}
+
+#[test]
+fn checkpoint() {
+    let td = t!(tempfile::tempdir());
+    let path = td.path().join("jobserver.checkpoint");
+    let c = t!(Client::restore_checkpoint(&path, 4));
+    assert_eq!(c.available().unwrap(), 4);
+
+    let token = t!(c.acquire());
+    t!(c.checkpoint(&path));
+    assert_eq!(t!(std::fs::read_to_string(&path)), "3\n");
+    drop(token);
+
+    assert_eq!(
+        t!(Client::restore_checkpoint(&path, 4))
+            .available()
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        t!(Client::restore_checkpoint(&path, 2))
+            .available()
+            .unwrap(),
+        2
+    );
+
+    t!(std::fs::write(&path, "garbage"));
+    let err = Client::restore_checkpoint(&path, 4).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+// `try_acquire` on anonymous pipes is only supported on Linux with glibc.
+#[cfg(any(windows, all(target_os = "linux", target_env = "gnu")))]
+#[test]
+fn acquire_with_timeout_and_backoff() {
+    use jobserver::BackoffStrategy;
+    use std::time::{Duration, Instant};
+
+    let c = t!(Client::new(1));
+    let backoff = BackoffStrategy::Fixed(Duration::from_millis(5));
+    let token = t!(c.acquire_with_timeout_and_backoff(Duration::from_secs(1), backoff)).unwrap();
+
+    let start = Instant::now();
+    let timeout = Duration::from_millis(50);
+    assert!(t!(c.acquire_with_timeout_and_backoff(timeout, BackoffStrategy::default())).is_none());
+    assert!(start.elapsed() >= timeout);
+
+    let c2 = c.clone();
+    let t = thread::spawn(move || {
+        c2.acquire_with_timeout_and_backoff(Duration::from_secs(10), backoff)
+            .unwrap()
+            .is_some()
+    });
+    drop(token);
+    assert!(t.join().unwrap());
+}